@@ -0,0 +1,147 @@
+use std::path::{Path, PathBuf};
+
+/// Output containers that can carry chapters.
+pub const CHAPTER_CONTAINERS: &[&str] = &["m4a", "m4b", "mka", "mkv", "mp3", "mp4"];
+
+/// Whether an output with extension `ext` can carry chapters.
+pub fn holds_chapters(ext: &str) -> bool {
+    CHAPTER_CONTAINERS.iter().any(|x| x.eq_ignore_ascii_case(ext))
+}
+
+/// One chapter of the output, timed in seconds from its start.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    pub start_secs: f64,
+    pub end_secs: f64,
+}
+
+/// A chapter per input, titled by its file name (without the extension). `offset_secs` of leading
+/// silence pushes every chapter back, and each input starts `spacing_secs` after the one before it ends:
+/// a gap of silence, or a negative overlap for a crossfade, where the next chapter starts with the fade.
+pub fn from_inputs(files: &[PathBuf], durations: &[f64], offset_secs: f64, spacing_secs: f64) -> Vec<Chapter> {
+    let count = files.len().min(durations.len());
+    let mut start_secs = offset_secs;
+    files
+        .iter()
+        .zip(durations)
+        .enumerate()
+        .map(|(i, (file, duration))| {
+            let next_start_secs = start_secs + duration + spacing_secs;
+            let end_secs = match i + 1 < count {
+                true => (start_secs + duration).min(next_start_secs),
+                false => start_secs + duration,
+            };
+            let chapter = Chapter {
+                title: title(file),
+                start_secs,
+                end_secs,
+            };
+            start_secs = next_start_secs;
+            chapter
+        })
+        .collect()
+}
+
+fn title(file: &Path) -> String {
+    file.file_stem()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Render `chapters` as a CUE sheet for `audio_file`, a track per chapter, titled by it. the album's `title`
+/// (or `album`) and `artist` tags, if there are any in `metadata`, go at the top. the sheet refers to the
+/// audio by file name, so it belongs next to it.
+pub fn cue_sheet(chapters: &[Chapter], audio_file: &Path, metadata: &[(String, String)]) -> String {
+    let tag = |key: &str| metadata.iter().find(|(k, _)| k == key).map(|(_, value)| value.as_str());
+
+    let mut out = String::new();
+    if let Some(artist) = tag("artist") {
+        out.push_str(&format!("PERFORMER {}\n", cue_quote(artist)));
+    }
+    if let Some(title) = tag("album").or_else(|| tag("title")) {
+        out.push_str(&format!("TITLE {}\n", cue_quote(title)));
+    }
+
+    let file_name = audio_file.file_name().map(|x| x.to_string_lossy().into_owned()).unwrap_or_default();
+    out.push_str(&format!("FILE {} {}\n", cue_quote(&file_name), cue_file_type(audio_file)));
+    for (i, chapter) in chapters.iter().enumerate() {
+        out.push_str(&format!("  TRACK {:02} AUDIO\n", i + 1));
+        out.push_str(&format!("    TITLE {}\n", cue_quote(&chapter.title)));
+        out.push_str(&format!("    INDEX 01 {}\n", cue_timestamp(chapter.start_secs)));
+    }
+    out
+}
+
+/// `MM:SS:FF`, with 75 frames a second. the minutes go past 99 for long outputs, which most players accept.
+fn cue_timestamp(secs: f64) -> String {
+    let frames = (secs.max(0.0) * 75.0).round() as u64;
+    format!("{:02}:{:02}:{:02}", frames / (75 * 60), frames / 75 % 60, frames % 75)
+}
+
+/// CUE strings can't escape a double quote, so any in `value` become single quotes.
+fn cue_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "'"))
+}
+
+fn cue_file_type(audio_file: &Path) -> &'static str {
+    match crate::options::output_extension(audio_file) {
+        Some(ext) if ext.eq_ignore_ascii_case("mp3") => "MP3",
+        Some(ext) if ext.eq_ignore_ascii_case("aif") || ext.eq_ignore_ascii_case("aiff") => "AIFF",
+        _ => "WAVE",
+    }
+}
+
+/// Render `chapters` as an ffmpeg FFMETADATA file, for use with `-map_chapters`.
+pub fn ffmetadata(chapters: &[Chapter]) -> String {
+    let mut out = String::from(";FFMETADATA1\n");
+    for chapter in chapters {
+        out.push_str("[CHAPTER]\nTIMEBASE=1/1000\n");
+        out.push_str(&format!("START={}\n", (chapter.start_secs * 1000.0).round() as u64));
+        out.push_str(&format!("END={}\n", (chapter.end_secs * 1000.0).round() as u64));
+        out.push_str(&format!("title={}\n", escape(&chapter.title)));
+    }
+    out
+}
+
+/// FFMETADATA treats `=`, `;`, `#`, `\` and newlines as syntax, so they're escaped with a backslash.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_chapters_from_inputs() {
+        let files = ["01 intro.wav", "02 a=b; #1.wav"].map(PathBuf::from);
+        let chapters = from_inputs(&files, &[1.5, 2.25], 1.0, 0.0);
+        assert_eq!(
+            ffmetadata(&chapters),
+            ";FFMETADATA1\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=1000\nEND=2500\ntitle=01 intro\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=2500\nEND=4750\ntitle=02 a\\=b\\; \\#1\n"
+        );
+
+        let metadata = [(String::from("artist"), String::from("The \"Band\""))];
+        assert_eq!(
+            cue_sheet(&from_inputs(&files, &[61.5, 2.0], 0.0, 0.0), Path::new("/out/live set.mp3"), &metadata),
+            "PERFORMER \"The 'Band'\"\n\
+             FILE \"live set.mp3\" MP3\n\
+             \x20 TRACK 01 AUDIO\n    TITLE \"01 intro\"\n    INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n    TITLE \"02 a=b; #1\"\n    INDEX 01 01:01:38\n"
+        );
+
+        let bounds = |chapters: Vec<Chapter>| chapters.iter().map(|x| (x.start_secs, x.end_secs)).collect::<Vec<_>>();
+        assert_eq!(bounds(from_inputs(&files, &[1.5, 2.25], 0.0, -0.5)), [(0.0, 1.0), (1.0, 3.25)]);
+        assert_eq!(bounds(from_inputs(&files, &[1.5, 2.25], 0.0, 2.0)), [(0.0, 1.5), (3.5, 5.75)]);
+    }
+}
@@ -0,0 +1,590 @@
+use clap::{ArgAction, ArgGroup, Args, Parser, Subcommand};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use stitcher::{codec, fetch, normalize, options, order_report, playlist, probe, sort, tags, waveform};
+
+#[derive(Parser, Debug)]
+#[command(version, about = "a little ffmpeg utility: stitch a directory of audio files together")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+
+    /// (optional) Print more: -v adds the files found, the concat list and each ffmpeg command, -vv everything.
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// (optional) Print nothing but errors and the result: no progress messages, warnings or ffmpeg output.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Stitch the files in a directory together.
+    Stitch(StitchArgs),
+    /// Print the duration and audio format of each file that would be stitched.
+    Probe(ProbeArgs),
+    /// Print the files that would be stitched, in order, and the ffmpeg command that would run. runs nothing.
+    Plan(StitchArgs),
+    /// Download a static ffmpeg build (with ffprobe), check its checksum, and put it where stitcher looks.
+    FetchFfmpeg(FetchArgs),
+    /// Split one file into numbered parts: every so often, at each pause, or at each chapter of a cue sheet.
+    Split(SplitArgs),
+    /// Print a completion script for a shell, e.g. `stitcher completions bash > ~/.local/share/bash-completion/completions/stitcher`.
+    Completions(CompletionsArgs),
+    /// Print the man page, in roff, e.g. `stitcher man > ~/.local/share/man/man1/stitcher.1`.
+    Man,
+}
+
+/// Arguments for `stitcher completions`.
+#[derive(Args, Debug)]
+pub struct CompletionsArgs {
+    /// The shell to complete in.
+    #[arg(value_enum)]
+    pub shell: clap_complete::Shell,
+}
+
+/// Arguments for `stitcher split`.
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("split_by").required(true).args(["every", "silence", "chapters"])))]
+pub struct SplitArgs {
+    /// The file to split.
+    pub input: PathBuf,
+
+    /// Cut every this long, e.g. 10min, 600s or 600 (seconds).
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub every: Option<f64>,
+
+    /// Cut in the middle of each pause, as found by ffmpeg's silencedetect.
+    #[arg(long)]
+    pub silence: bool,
+
+    /// (optional) With --silence, anything quieter than this many dBFS counts as silence.
+    #[arg(long, value_name = "DB", default_value_t = -35.0, allow_negative_numbers = true)]
+    pub silence_db: f64,
+
+    /// (optional) With --silence, how long a pause has to last to be cut at, in seconds.
+    #[arg(long, value_name = "SECS", default_value_t = 1.0)]
+    pub silence_min: f64,
+
+    /// Cut where each chapter of this CUE sheet or FFMETADATA file starts.
+    #[arg(long, value_name = "FILE")]
+    pub chapters: Option<PathBuf>,
+
+    /// (optional) Directory to write the parts to, as `<name>_001.<ext>`, `<name>_002.<ext>`...
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    pub out_dir: PathBuf,
+
+    /// (optional) Replace the parts of an earlier split of the same file. without it, finding any is an error.
+    #[arg(long, visible_alias = "force")]
+    pub overwrite: bool,
+
+    /// (optional) Use the ffmpeg binary at this path, instead of looking for one.
+    #[arg(long, value_name = "PATH")]
+    pub ffmpeg_path: Option<PathBuf>,
+}
+
+/// Arguments for `stitcher fetch-ffmpeg`.
+#[derive(Args, Debug)]
+pub struct FetchArgs {
+    /// (optional) Directory to put ffmpeg and ffprobe in.
+    #[arg(long, value_name = "DIR", default_value = fetch::VENDOR_DIR)]
+    pub dest: PathBuf,
+}
+
+/// Arguments for `stitcher probe`.
+#[derive(Args, Debug)]
+pub struct ProbeArgs {
+    /// Directory to look for files in, or an .m3u/.m3u8 playlist listing them in order.
+    #[arg(short, long, env = "STITCHER_INPUT_PATH")]
+    pub input_path: PathBuf,
+
+    /// (optional) Look for files in subdirectories of the input directory too.
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// (optional) With --recursive, only look this many levels deep. 1 is just the input directory itself.
+    #[arg(long, value_name = "N", requires = "recursive", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_depth: Option<u64>,
+
+    /// (optional) Use the ffmpeg binary at this path, instead of looking for one. ffprobe is looked for next to it.
+    #[arg(long, value_name = "PATH")]
+    pub ffmpeg_path: Option<PathBuf>,
+
+    /// (optional) Print where each file starts in the stitched output and how long it is, then the total
+    /// length, instead of each file's format.
+    #[arg(long)]
+    pub durations: bool,
+}
+
+/// Arguments for `stitcher stitch` and `stitcher plan`.
+#[derive(Args, Debug, Clone)]
+pub struct StitchArgs {
+    /// Directory to look for files in, an .m3u/.m3u8 playlist listing them in order, or a file to stitch.
+    /// `-` reads a list of paths from stdin, like --files-from -. can be repeated. defaults to
+    /// $STITCHER_INPUT_PATH when no inputs are given at all.
+    #[arg(short, long)]
+    pub input_path: Vec<PathBuf>,
+
+    /// More inputs, like -i: directories, playlists or files, stitched after any -i ones, in the order given.
+    #[arg(value_name = "INPUT")]
+    pub inputs: Vec<PathBuf>,
+
+    /// (optional) Stitch the paths listed in this file, one per line, after any other inputs. `-` reads
+    /// them from stdin, e.g. `find takes -name '*.wav' | sort | stitcher stitch --files-from -`.
+    #[arg(long, value_name = "PATH")]
+    pub files_from: Option<PathBuf>,
+
+    /// (optional) Name of the output file. file type should match the input file types, unless --format is given.
+    /// `-` writes to stdout instead, in the container --format names, e.g. `-o - --format mp3 | mpv -`.
+    #[arg(short, long)]
+    pub out: Option<PathBuf>,
+
+    /// (optional) File name, without the extension, for an output that wasn't named with --out. `{date}` is the
+    /// date and time, `{dirname}` the name of the (first) input directory, `{count}` how many files are
+    /// stitched, and `{ext}` the output's extension. defaults to `STITCH_OUTPUT_{date}`.
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "out")]
+    pub name_template: Option<String>,
+
+    /// (optional) Directory to write an output that wasn't named with --out into, created if it isn't there.
+    /// defaults to the config file's output_dir, or the current directory.
+    #[arg(long, value_name = "DIR", conflicts_with = "out")]
+    pub output_dir: Option<PathBuf>,
+
+    /// (optional) Output container, by extension (e.g. `flac`, `opus`, `m4a`). inputs in another format are
+    /// transcoded into it with its default codec (or --codec's), instead of copied. an --out without an
+    /// extension gets this one.
+    #[arg(long, value_name = "EXT", value_parser = parse_format, conflicts_with = "audiobook")]
+    pub format: Option<String>,
+
+    /// (optional) Use the ffmpeg binary at this path, instead of looking for one. ffprobe is looked for next to it.
+    #[arg(long, value_name = "PATH")]
+    pub ffmpeg_path: Option<PathBuf>,
+
+    /// (optional) What does the stitching: `cli` runs an ffmpeg binary, `libav` joins the files in-process with
+    /// the libav libraries, and `gstreamer` with a GStreamer pipeline (each only in builds with the feature of
+    /// the same name, and only when nothing but joining is asked for).
+    #[arg(long, value_enum, default_value_t)]
+    pub backend: options::Backend,
+
+    /// (optional) Replace the output file if it already exists. without it, stitcher asks first when run in a
+    /// terminal, and refuses otherwise.
+    #[arg(long, visible_alias = "force")]
+    pub overwrite: bool,
+
+    /// (optional) Don't stitch: print the files in order, the concat list and the ffmpeg command, like `plan`.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// (optional) More arguments for ffmpeg, split like a shell would, e.g. `--ffmpeg-args "-ar 44100 -ac 1"`.
+    /// they go just before the output path, after everything stitcher adds, so they can override it. can be repeated.
+    #[arg(long, value_name = "ARGS", value_parser = parse_ffmpeg_args, allow_hyphen_values = true)]
+    pub ffmpeg_args: Vec<Vec<String>>,
+
+    /// (optional) Leave the temp files (the concat list, and any chapters or filter graph script) behind after
+    /// the run, for debugging. where each one went is logged.
+    #[arg(long)]
+    pub keep_temp: bool,
+
+    /// (optional) Print the exact ffmpeg command on stderr, shell-quoted, before it runs, so a failure can be
+    /// reproduced by hand. the temp files it reads (the concat list, chapters, a filter graph script) are kept for
+    /// it, like --keep-temp.
+    #[arg(long)]
+    pub show_command: bool,
+
+    /// (optional) Stitch each subdirectory of the inputs on its own, into an output named after it
+    /// (e.g. `2024-05-01/` → `2024-05-01.wav`), in --output-dir, the config file's output_dir or the current directory.
+    #[arg(long, conflicts_with_all = ["out", "watch", "dry_run"])]
+    pub batch: bool,
+
+    /// (optional) Split the output into parts of at most this long (e.g. `74min`, `1h`, `600s` or `1:14:00`), named
+    /// like `out_part1.wav`, `out_part2.wav`. parts start and end where the inputs do, so no input is cut in two.
+    /// needs ffprobe.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, conflicts_with_all = ["order_report", "chapters_out", "waveform"])]
+    pub max_part_duration: Option<f64>,
+
+    /// (optional) Before stitching, show the files in a list to reorder and prune, then stitch them as confirmed.
+    #[arg(long, conflicts_with_all = ["watch", "dry_run"])]
+    pub interactive: bool,
+
+    /// (optional) Hold an exclusive lock on this file for the whole run. if another run holds it, exit straight away.
+    #[arg(long)]
+    pub lockfile: Option<PathBuf>,
+
+    /// (optional) While ffmpeg runs, print progress to stderr as newline-delimited JSON objects.
+    #[arg(long)]
+    pub progress_json: bool,
+
+    /// (optional) While ffmpeg runs, draw a progress bar on stderr with percent complete and the current position.
+    #[arg(long, conflicts_with = "progress_json")]
+    pub progress: bool,
+
+    /// (optional) When writing a .flac output, re-encode losslessly at this compression level (0-12, default 5)
+    /// instead of stream copying, so the output is one clean FLAC stream.
+    #[arg(long, num_args = 0..=1, default_missing_value = "5", value_parser = clap::value_parser!(u8).range(0..=12))]
+    pub flac_recompress: Option<u8>,
+
+    /// (optional) Stitch video files (.mp4, .mov, .mkv) instead of audio, copying their video and audio streams.
+    /// every clip needs the same codecs and parameters, like the clips off one camera.
+    #[arg(long)]
+    pub video: bool,
+
+    /// (optional) Keep embedded cover art (attached-picture streams) instead of stripping all video.
+    #[arg(long)]
+    pub keep_cover: bool,
+
+    /// (optional) Decode and re-encode the inputs instead of copying the streams. needed to change format,
+    /// e.g. to write .m4a or .ogg output from wav inputs.
+    #[arg(long)]
+    pub reencode: bool,
+
+    /// (optional) Audio codec to re-encode with (implies --reencode). defaults to a sensible codec for the output extension.
+    #[arg(long)]
+    pub codec: Option<String>,
+
+    /// (optional) Loudness-normalize each input on its own before joining, so no single clip dominates. re-encodes.
+    #[arg(long)]
+    pub normalize_each: bool,
+
+    /// (optional) How --normalize-each evens the inputs out (implies --normalize-each). `peak` measures each input
+    /// in a first pass; dynaudnorm suits sparse material that loudnorm makes pump.
+    #[arg(long, value_name = "METHOD")]
+    pub normalize_method: Option<normalize::NormalizeMethod>,
+
+    /// (optional) Bring the whole output to this integrated loudness in LUFS (default -16), measuring it in a first
+    /// pass and normalizing linearly in a second. re-encodes.
+    #[arg(long, value_name = "LUFS", num_args = 0..=1, default_missing_value = "-16", allow_negative_numbers = true)]
+    pub normalize: Option<f64>,
+
+    /// (optional) Make the output file read-only once it's written, so a master can't be overwritten by accident.
+    #[arg(long)]
+    pub readonly_output: bool,
+
+    /// (optional) Embed a chapter per input, titled by file name. needs a container that holds chapters, like .mka.
+    #[arg(long)]
+    pub chapters: bool,
+
+    /// (optional) Write a CUE sheet next to the output (e.g. `album.cue` for `album.flac`), with a track per input,
+    /// titled by file name, so the track boundaries aren't lost. needs ffprobe.
+    #[arg(long)]
+    pub cue: bool,
+
+    /// (optional) Write an FFMETADATA file of chapters here, one per input, titled by file name, to attach later
+    /// (`ffmpeg -i out.wav -i chapters.txt -map_chapters 1 ...`) or to see where each input starts. needs ffprobe.
+    #[arg(long, value_name = "PATH")]
+    pub chapters_out: Option<PathBuf>,
+
+    /// (optional) Set the output's title tag.
+    #[arg(long)]
+    pub title: Option<String>,
+
+    /// (optional) Set the output's artist tag.
+    #[arg(long)]
+    pub artist: Option<String>,
+
+    /// (optional) Set the output's album tag.
+    #[arg(long)]
+    pub album: Option<String>,
+
+    /// (optional) Set the output's comment tag.
+    #[arg(long)]
+    pub comment: Option<String>,
+
+    /// (optional) Carry the inputs' tags into the output: `first` takes the first input's, `merge` every tag all
+    /// the inputs share (so each track's title is left out). --title and the like still win. needs ffprobe.
+    #[arg(long, value_enum, value_name = "FROM")]
+    pub copy_metadata: Option<tags::CopyMetadata>,
+
+    /// (optional) Make an audiobook: re-encode to AAC with a chapter per input, into an .m4b unless --out names an .m4a.
+    #[arg(long)]
+    pub audiobook: bool,
+
+    /// (optional) After stitching, render a PNG of the output's waveform here.
+    #[arg(long, value_name = "PATH")]
+    pub waveform: Option<PathBuf>,
+
+    /// (optional) Size of the --waveform image, as WIDTHxHEIGHT.
+    #[arg(long, value_name = "WxH", default_value_t = waveform::WaveformSize::default())]
+    pub waveform_size: waveform::WaveformSize,
+
+    /// (optional) After stitching, write an audit report here listing each input in final order,
+    /// with its position, SHA-256 and size.
+    #[arg(long, value_name = "PATH")]
+    pub order_report: Option<PathBuf>,
+
+    /// (optional) After stitching, write a checksum manifest next to the output (e.g. `out.wav.sha256`),
+    /// for checking it later with `sha256sum -c`.
+    #[arg(long, value_name = "ALGORITHM")]
+    pub checksum: Option<order_report::ChecksumAlgorithm>,
+
+    /// (optional) With --checksum, list the hash of every input in the manifest too.
+    #[arg(long, requires = "checksum")]
+    pub checksum_inputs: bool,
+
+    /// (optional) Retry reading the input directory this many times if it fails, e.g. on a flaky network mount.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub discovery_retries: u32,
+
+    /// (optional) Print nothing but one tab-separated summary line on stdout once done:
+    /// output path, input count, duration in seconds ("-" if unknown) and size in bytes.
+    #[arg(long)]
+    pub summary_only: bool,
+
+    /// (optional) Re-encode at this bitrate, e.g. `192k`. without it (or --vbr-quality), lossy codecs get a sensible
+    /// default: VBR quality 2 for MP3, 5 for Vorbis, 192k for AAC and 128k for Opus.
+    #[arg(long, value_name = "RATE", value_parser = codec::parse_bitrate, conflicts_with_all = ["vbr_quality", "target_size"])]
+    pub bitrate: Option<u32>,
+
+    /// (optional) Re-encode at this VBR quality, on the codec's own scale: 0-9 for MP3 (lower is better),
+    /// -1-10 for Vorbis, 0.1-2 for AAC, 1-5 for libfdk_aac.
+    #[arg(long, value_name = "Q", allow_negative_numbers = true, conflicts_with = "target_size")]
+    pub vbr_quality: Option<f64>,
+
+    /// (optional) Re-encode at this compression level: 0-12 for FLAC, 0-9 for MP3, 0-10 for Opus. it trades
+    /// encoding time for size (or, for MP3, quality).
+    #[arg(long, value_name = "N")]
+    pub compression_level: Option<u8>,
+
+    /// (optional) Re-encode at whatever bitrate makes the output land near this size, in megabytes.
+    /// the bitrate is worked out from the total input duration, so ffprobe is needed.
+    #[arg(long, value_name = "MB")]
+    pub target_size: Option<f64>,
+
+    /// (optional) Keep running, and stitch again whenever files are added to, removed from or rewritten in the
+    /// inputs. a change waits until the inputs have looked the same for a whole --watch-interval. Ctrl-C to stop.
+    #[arg(long)]
+    pub watch: bool,
+
+    /// (optional) With --watch, how often to look at the inputs, in seconds.
+    #[arg(long, value_name = "SECS", default_value_t = 2, value_parser = clap::value_parser!(u64).range(1..))]
+    pub watch_interval: u64,
+
+    /// (optional) Order to stitch the discovered files in (default: the config file's, or none).
+    /// `--only` overrides this with its own order.
+    #[arg(long, value_enum)]
+    pub sort: Option<sort::SortOrder>,
+
+    /// (optional) Seconds of silence to put before the first file. re-encodes.
+    #[arg(long, value_name = "SECS", default_value_t = 0.0)]
+    pub pre_gap: f64,
+
+    /// (optional) Seconds of silence to put after the last file. re-encodes.
+    #[arg(long, value_name = "SECS", default_value_t = 0.0)]
+    pub post_gap: f64,
+
+    /// (optional) Seconds of silence to put between each file and the next, matched to the inputs' format. re-encodes.
+    #[arg(long, value_name = "SECS", default_value_t = 0.0)]
+    pub gap: f64,
+
+    /// (optional) Cut the silence (or room noise) off the start and end of every input before joining them.
+    /// re-encodes.
+    #[arg(long)]
+    pub trim_silence: bool,
+
+    /// (optional) With --trim-silence, anything quieter than this many dBFS counts as silence.
+    #[arg(long, value_name = "DB", default_value_t = -50.0, allow_negative_numbers = true, requires = "trim_silence")]
+    pub trim_silence_db: f64,
+
+    /// (optional) With --trim-silence, sound has to last at least this many seconds to end the trimming, so
+    /// shorter bumps in the silence are cut along with it.
+    #[arg(long, value_name = "SECS", default_value_t = 0.2, requires = "trim_silence")]
+    pub trim_silence_min: f64,
+
+    /// (optional) Join MP3s (or AAC files) without a gap or click at each join, by decoding each one, which
+    /// trims the silence its encoder padded it with, then re-encoding the joined audio.
+    #[arg(long)]
+    pub gapless: bool,
+
+    /// (optional) Fade each file into the next over this many seconds, instead of a hard cut. re-encodes,
+    /// and makes the output shorter by that much per join.
+    #[arg(long, value_name = "SECS", default_value_t = 0.0)]
+    pub crossfade: f64,
+
+    /// (optional) Fade the output in from silence over this many seconds at its start. re-encodes.
+    #[arg(long, value_name = "SECS", default_value_t = 0.0, value_parser = parse_secs)]
+    pub fade_in: f64,
+
+    /// (optional) Fade the output out to silence over this many seconds at its end. re-encodes.
+    #[arg(long, value_name = "SECS", default_value_t = 0.0, value_parser = parse_secs)]
+    pub fade_out: f64,
+
+    /// (optional) Convert every input to this sample rate: `max` for the highest one among them, or a rate in Hz
+    /// (e.g. `48000` or `44.1k`). re-encodes, unless every input is at that rate already.
+    #[arg(long, value_name = "RATE|max")]
+    pub resample: Option<probe::SampleRate>,
+
+    /// (optional) Force ffmpeg to read every input with this demuxer (e.g. `s16le` for headerless PCM),
+    /// instead of guessing from the contents. re-encodes.
+    #[arg(long, value_name = "FMT")]
+    pub input_format: Option<String>,
+
+    /// (optional) Read settings from this TOML file, instead of ~/.config/stitcher/config.toml. its `[defaults]`
+    /// section sets defaults for the command line, and its `[extensions]` section adds input extensions
+    /// (with their re-encode codec, and whether they stream copy cleanly) without recompiling.
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// (optional) Look for files in subdirectories of the input directory too.
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// (optional) With --recursive, only look this many levels deep. 1 is just the input directory itself.
+    #[arg(long, value_name = "N", requires = "recursive", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_depth: Option<u64>,
+
+    /// (optional) With --recursive, don't descend into subdirectories matching this glob (e.g. `**/drafts/**`). can be repeated.
+    #[arg(long, value_name = "GLOB")]
+    pub prune_dir: Vec<glob::Pattern>,
+
+    /// (optional) Only stitch files whose name matches this glob (e.g. `take_*.wav`). can be repeated, to match any of them.
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<glob::Pattern>,
+
+    /// (optional) Leave out files whose name matches this glob (e.g. `*-draft.wav`), even if --include matched. can be repeated.
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<glob::Pattern>,
+
+    /// (optional) Only stitch files modified at or after this time: a length of time ago (e.g. `24h`, `2d`) or a
+    /// local date and time (e.g. `2026-10-15`, `2026-10-15 09:30`).
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub since: Option<SystemTime>,
+
+    /// (optional) Only stitch files modified before this time, given like --since.
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub until: Option<SystemTime>,
+
+    /// (optional) Leave out files shorter than this (e.g. `5s`, `2min`), like accidental clips. needs ffprobe, except
+    /// for WAVs.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub min_duration: Option<f64>,
+
+    /// (optional) Leave out files longer than this (e.g. `2h`), given like --min-duration.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub max_duration: Option<f64>,
+
+    /// (optional) Stitch at most this many files. counted after --exclude and --sort.
+    #[arg(long, value_name = "N")]
+    pub limit: Option<usize>,
+
+    /// (optional) Apply --limit right after sorting, before --exclude, so excluded files count towards it.
+    #[arg(long, requires = "limit")]
+    pub limit_before_filter: bool,
+
+    /// (optional) Only stitch files with exactly this name. can be repeated; files are stitched in the order given.
+    #[arg(long = "only", value_name = "NAME")]
+    pub only: Vec<String>,
+
+    /// (optional) Skip files whose contents are exactly the same as an earlier file's (by SHA-256),
+    /// keeping the first copy. each skipped file is reported.
+    #[arg(long)]
+    pub dedupe: bool,
+
+    /// (optional) Fail (non-zero exit) when ffmpeg warns about something that usually means a glitch
+    /// in the output, like non-monotonous timestamps. by default these are just passed on as warnings.
+    #[arg(long)]
+    pub strict_ffmpeg: bool,
+
+    /// (optional) Give up on any ffprobe call that takes longer than this many seconds, e.g. on a hung mount.
+    /// that file is skipped with a warning (an error with --strict).
+    #[arg(long, value_name = "SECONDS", value_parser = parse_timeout)]
+    pub probe_timeout: Option<Duration>,
+
+    /// (optional) Skip the stitch (successfully) if the output already exists and is newer than every input.
+    #[arg(long)]
+    pub if_newer: bool,
+
+    /// (optional) Print the result on stdout as a JSON object, instead of a bare path or summary line: the output,
+    /// the inputs in order, duration, size and ffmpeg's exit status, or the error if the stitch failed.
+    #[arg(long)]
+    pub json: bool,
+
+    /// (optional) Turn every warning about the inputs into an error, before ffmpeg runs: a file that would be
+    /// skipped (unreadable, missing from a playlist, an `--only` name that matched nothing), inputs in mismatched
+    /// formats, a duration or tags that can't be read, a sidecar that doesn't parse. needs ffprobe, and the cli
+    /// backend. implies --strict-ffmpeg, for what only shows up while ffmpeg runs, like non-monotonic timestamps at
+    /// a join.
+    #[arg(long)]
+    pub strict: bool,
+}
+
+/// An output extension, lowercased, with or without its dot.
+fn parse_format(s: &str) -> Result<String, String> {
+    let format = s.trim().trim_start_matches('.').to_lowercase();
+    match format.is_empty() || format.contains(['/', '\\']) {
+        true => Err(format!("expected an extension like `flac`, got `{}`", s)),
+        false => Ok(format),
+    }
+}
+
+/// Seconds from a length like `74min`, `1h`, `90s` or `1:14:00`, above zero.
+fn parse_duration(s: &str) -> Result<f64, String> {
+    let s = s.trim();
+    match secs_with_unit(s).or_else(|| playlist::parse_timestamp(s)) {
+        Some(secs) if secs > 0.0 && secs.is_finite() => Ok(secs),
+        _ => Err(format!("expected a length like 74min, 1h, 600s or 1:14:00, got `{}`", s)),
+    }
+}
+
+/// A number of seconds that's 0 or more, like 2 or 0.5.
+fn parse_secs(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(secs) if secs >= 0.0 && secs.is_finite() => Ok(secs),
+        _ => Err(format!("expected a number of seconds, 0 or more, like 2 or 0.5, got `{}`", s)),
+    }
+}
+
+/// A timeout in seconds, which has to be more than none, and short enough to be a `Duration`.
+fn parse_timeout(s: &str) -> Result<Duration, String> {
+    match s.trim().parse::<f64>().ok().filter(|x| *x > 0.0).map(Duration::try_from_secs_f64) {
+        Some(Ok(timeout)) => Ok(timeout),
+        _ => Err(format!("expected a number of seconds more than 0, like 10 or 2.5, got `{}`", s)),
+    }
+}
+
+/// `s` in seconds, if it's a number with a unit, like `74min`, `1.5h` or `2d`.
+fn secs_with_unit(s: &str) -> Option<f64> {
+    let units = [("d", 86400.0), ("h", 3600.0), ("min", 60.0), ("m", 60.0), ("s", 1.0)];
+    units
+        .iter()
+        .find_map(|(unit, scale)| Some(s.strip_suffix(unit)?.trim().parse::<f64>().ok()? * scale))
+}
+
+/// A point in time for --since and --until: either that long ago (e.g. `24h`, `90min`, `2d`), or a local date
+/// and time (`2026-10-15`, which is its midnight, `2026-10-15 09:30` or `2026-10-15T09:30:00`).
+fn parse_time(s: &str) -> Result<SystemTime, String> {
+    use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone};
+
+    let s = s.trim();
+    if let Some(secs) = secs_with_unit(s) {
+        return Duration::try_from_secs_f64(secs)
+            .ok()
+            .and_then(|ago| SystemTime::now().checked_sub(ago))
+            .ok_or_else(|| format!("`{}` ago isn't a time: expected a length like 24h or 2d", s));
+    }
+    let formats = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"];
+    let date_time = formats
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0));
+    match date_time.and_then(|x| Local.from_local_datetime(&x).earliest()) {
+        Some(time) => Ok(time.into()),
+        None => Err(format!("expected a time like 24h, 2d, 2026-10-15 or 2026-10-15 09:30, got `{}`", s)),
+    }
+}
+
+/// ffmpeg arguments, split like a POSIX shell would split them.
+fn parse_ffmpeg_args(s: &str) -> Result<Vec<String>, String> {
+    shlex::split(s).ok_or_else(|| format!("couldn't split `{}` into arguments: check its quotes", s))
+}
+
+impl StitchArgs {
+    /// Every input, in order: each `-i`, then each positional one. `$STITCHER_INPUT_PATH` (which the
+    /// dotfile can set) stands in when neither was given.
+    pub fn input_paths(&self) -> Vec<PathBuf> {
+        let paths = self.input_path.iter().chain(&self.inputs).cloned().collect::<Vec<_>>();
+        match paths.is_empty() {
+            true => std::env::var_os("STITCHER_INPUT_PATH").map(PathBuf::from).into_iter().collect(),
+            false => paths,
+        }
+    }
+}
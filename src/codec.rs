@@ -0,0 +1,196 @@
+/// The audio encoder to use for each output extension when re-encoding, rather than relying on
+/// ffmpeg's per-container default (which depends on how ffmpeg was built, and is sometimes surprising).
+const DEFAULT_AUDIO_CODECS: &[(&str, &str)] = &[
+    ("aac", "aac"),
+    ("flac", "flac"),
+    ("m4a", "aac"),
+    ("m4b", "aac"),
+    ("mp3", "libmp3lame"),
+    ("ogg", "libvorbis"),
+    ("opus", "libopus"),
+    ("wav", "pcm_s16le"),
+];
+
+/// Default audio codec for an output extension, in any case, if stitcher has one on file.
+pub fn default_audio_codec(ext: &str) -> Option<&'static str> {
+    DEFAULT_AUDIO_CODECS
+        .iter()
+        .find(|(known_ext, _)| known_ext.eq_ignore_ascii_case(ext))
+        .map(|(_, codec)| *codec)
+}
+
+/// The encoders that take a VBR quality, the option they take it with, and the range it goes over.
+/// lower is better for LAME, higher for the rest.
+const VBR_QUALITY: &[(&str, &str, f64, f64)] = &[
+    ("libmp3lame", "-q:a", 0.0, 9.0),
+    ("libvorbis", "-q:a", -1.0, 10.0),
+    ("aac", "-q:a", 0.1, 2.0),
+    ("libfdk_aac", "-vbr", 1.0, 5.0),
+];
+
+/// The encoders that take `-compression_level`, and its range. it trades encoding time for size (or, for
+/// LAME, quality), never the sound of a lossless one.
+const COMPRESSION_LEVELS: &[(&str, u8, u8)] = &[("flac", 0, 12), ("libmp3lame", 0, 9), ("libopus", 0, 10)];
+
+/// What each lossy encoder gets when no bitrate or quality is given, instead of ffmpeg's own defaults,
+/// which are on the low side (e.g. 128k for MP3) or, for Vorbis, depend on the build.
+const DEFAULT_QUALITY_ARGS: &[(&str, &[&str])] = &[
+    ("libmp3lame", &["-q:a", "2"]),
+    ("libvorbis", &["-q:a", "5"]),
+    ("aac", &["-b:a", "192k"]),
+    ("libfdk_aac", &["-vbr", "4"]),
+    ("libopus", &["-b:a", "128k"]),
+];
+
+/// The arguments asking `codec` for a VBR `quality`, if it takes one and `quality` is in its range.
+pub fn vbr_quality_args(codec: &str, quality: f64) -> Result<Vec<String>, String> {
+    let (_, option, low, high) = VBR_QUALITY
+        .iter()
+        .find(|(known, ..)| *known == codec)
+        .ok_or_else(|| format!("{} doesn't take a VBR quality. give it a --bitrate instead", codec))?;
+    match (*low..=*high).contains(&quality) {
+        true => Ok(vec![option.to_string(), quality.to_string()]),
+        false => Err(format!("{}'s VBR quality goes from {} to {}, not {}", codec, low, high, quality)),
+    }
+}
+
+/// The arguments asking `codec` for compression `level`, if it takes one and `level` is in its range.
+pub fn compression_level_args(codec: &str, level: u8) -> Result<Vec<String>, String> {
+    let (_, low, high) = COMPRESSION_LEVELS
+        .iter()
+        .find(|(known, ..)| *known == codec)
+        .ok_or_else(|| format!("{} doesn't take a compression level", codec))?;
+    match (*low..=*high).contains(&level) {
+        true => Ok(vec![String::from("-compression_level"), level.to_string()]),
+        false => Err(format!("{}'s compression level goes from {} to {}, not {}", codec, low, high, level)),
+    }
+}
+
+/// The bitrate or quality arguments `codec` gets by default, if any.
+pub fn default_quality_args(codec: &str) -> &'static [&'static str] {
+    DEFAULT_QUALITY_ARGS
+        .iter()
+        .find(|(known, _)| *known == codec)
+        .map_or(&[], |(_, args)| *args)
+}
+
+/// A bitrate like `192k`, `192` (both kbit/s) or `1.5M`, in kbit/s.
+pub fn parse_bitrate(s: &str) -> Result<u32, String> {
+    let s = s.trim();
+    let (number, scale) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 1.0),
+        Some((i, 'm' | 'M')) => (&s[..i], 1000.0),
+        _ => (s, 1.0),
+    };
+    match number.parse::<f64>().map(|x| (x * scale).round()) {
+        Ok(kbps) if kbps >= 1.0 && kbps <= u32::MAX as f64 => Ok(kbps as u32),
+        _ => Err(format!("expected a bitrate like 192k, got `{}`", s)),
+    }
+}
+
+/// Lowest and highest audio bitrates `bitrate_for_target_size` will pick, in kbit/s.
+pub const MIN_TARGET_BITRATE_KBPS: u32 = 32;
+pub const MAX_TARGET_BITRATE_KBPS: u32 = 320;
+
+/// Share of the output taken up by container framing and headers rather than audio.
+const CONTAINER_OVERHEAD: f64 = 0.03;
+
+/// The audio bitrate (kbit/s) that makes `duration_secs` of audio come out at roughly `target_bytes`,
+/// clamped to a sane range - below ~32k nothing sounds good, above 320k most encoders stop accepting it.
+pub fn bitrate_for_target_size(target_bytes: u64, duration_secs: f64) -> u32 {
+    if duration_secs <= 0.0 {
+        return MAX_TARGET_BITRATE_KBPS;
+    }
+
+    let audio_bits = target_bytes as f64 * 8.0 * (1.0 - CONTAINER_OVERHEAD);
+    let kbps = (audio_bits / duration_secs / 1000.0).floor() as u32;
+    kbps.clamp(MIN_TARGET_BITRATE_KBPS, MAX_TARGET_BITRATE_KBPS)
+}
+
+/// Roughly the bitrate each lossy encoder comes out at with its `DEFAULT_QUALITY_ARGS`, in kbit/s.
+const TYPICAL_KBPS: &[(&str, f64)] = &[
+    ("libmp3lame", 190.0),
+    ("libvorbis", 160.0),
+    ("aac", 192.0),
+    ("libfdk_aac", 128.0),
+    ("libopus", 128.0),
+];
+
+/// Roughly the bitrate `codec` encodes audio in `format` at, by default, in kbit/s. PCM is exact; FLAC
+/// usually comes to a bit over half of it.
+pub fn typical_kbps(codec: &str, format: crate::probe::AudioFormat) -> Option<f64> {
+    let pcm_kbps = format.sample_rate as f64 * format.channels as f64 * 16.0 / 1000.0;
+    match codec {
+        "pcm_s16le" => Some(pcm_kbps),
+        "flac" => Some(pcm_kbps * 0.6),
+        codec => TYPICAL_KBPS.iter().find(|(known, _)| *known == codec).map(|(_, kbps)| *kbps),
+    }
+}
+
+/// About how many bytes `duration_secs` of audio at `kbps` comes to, container and all.
+pub fn size_for_bitrate(kbps: f64, duration_secs: f64) -> u64 {
+    (kbps * 1000.0 / 8.0 * duration_secs.max(0.0) * (1.0 + CONTAINER_OVERHEAD)) as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_default_audio_codec_per_extension() {
+        let expected = [
+            ("aac", "aac"),
+            ("flac", "flac"),
+            ("m4a", "aac"),
+            ("m4b", "aac"),
+            ("mp3", "libmp3lame"),
+            ("ogg", "libvorbis"),
+            ("opus", "libopus"),
+            ("wav", "pcm_s16le"),
+        ];
+        for (ext, codec) in expected {
+            assert_eq!(default_audio_codec(ext), Some(codec), "wrong default codec for .{}", ext);
+        }
+
+        assert_eq!(default_audio_codec("txt"), None);
+    }
+
+    #[test]
+    pub fn test_quality_args_per_codec() {
+        assert_eq!(vbr_quality_args("libmp3lame", 2.0), Ok(vec![String::from("-q:a"), String::from("2")]));
+        assert_eq!(vbr_quality_args("libfdk_aac", 5.0), Ok(vec![String::from("-vbr"), String::from("5")]));
+        assert!(vbr_quality_args("libmp3lame", 10.0).is_err());
+        assert!(vbr_quality_args("pcm_s16le", 2.0).is_err());
+
+        assert_eq!(compression_level_args("flac", 12), Ok(vec![String::from("-compression_level"), String::from("12")]));
+        assert!(compression_level_args("libopus", 11).is_err());
+        assert!(compression_level_args("aac", 5).is_err());
+
+        assert_eq!(default_quality_args("libopus"), ["-b:a", "128k"]);
+        assert!(default_quality_args("flac").is_empty());
+
+        assert_eq!(parse_bitrate("192k"), Ok(192));
+        assert_eq!(parse_bitrate("96"), Ok(96));
+        assert_eq!(parse_bitrate("1.5M"), Ok(1500));
+        assert!(parse_bitrate("fast").is_err());
+    }
+
+    #[test]
+    pub fn test_bitrate_for_target_size() {
+        // one hour into 50MB: 50_000_000 * 8 * 0.97 / 3600 / 1000 = 107.7
+        //
+        assert_eq!(bitrate_for_target_size(50_000_000, 3600.0), 107);
+
+        assert_eq!(bitrate_for_target_size(1_000_000, 3600.0), MIN_TARGET_BITRATE_KBPS);
+        assert_eq!(bitrate_for_target_size(500_000_000, 60.0), MAX_TARGET_BITRATE_KBPS);
+    }
+
+    #[test]
+    pub fn test_size_estimates() {
+        let cd = crate::probe::AudioFormat { sample_rate: 44100, channels: 2 };
+        assert_eq!(typical_kbps("pcm_s16le", cd), Some(1411.2));
+        assert_eq!(typical_kbps("libopus", cd), Some(128.0));
+        assert_eq!(typical_kbps("pcm_f32le", cd), None);
+        assert_eq!(size_for_bitrate(128.0, 60.0), 988_800);
+    }
+}
@@ -0,0 +1,135 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::{error::StitcherError, formats, sort};
+
+/// The TOML config file.
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    /// What to use when the command line doesn't say.
+    #[serde(default)]
+    pub defaults: Defaults,
+    /// Extra input extensions, or overrides for the built-in ones, keyed by extension.
+    #[serde(default)]
+    pub extensions: BTreeMap<String, formats::Format>,
+}
+
+/// The config file's `[defaults]` section. anything given on the command line wins over these.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Defaults {
+    /// The ffmpeg binary to use, tried before the built-in places (but after `$STITCHER_FFMPEG`).
+    pub ffmpeg: Option<PathBuf>,
+    /// Directory to write an output that wasn't named into, when `--output-dir` isn't given.
+    pub output_dir: Option<PathBuf>,
+    /// File name, without the extension, for an output that wasn't named, when `--name-template` isn't given.
+    pub name_template: Option<String>,
+    /// Order to stitch in when `--sort` isn't given.
+    pub sort: Option<sort::SortOrder>,
+}
+
+/// Where the config file is read from when `--config` isn't given: `stitcher/config.toml` under
+/// `$XDG_CONFIG_HOME`, or under `~/.config` when that isn't set.
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("stitcher").join("config.toml"))
+}
+
+/// Parse a config file's `contents`. a leading `~` in its paths is expanded to `$HOME`, like a shell would.
+pub fn parse(contents: &str) -> Result<Config, String> {
+    let mut config: Config = toml::from_str(contents).map_err(|e| format!("failed to parse the config: {}", e))?;
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    for path in [&mut config.defaults.ffmpeg, &mut config.defaults.output_dir].into_iter().flatten() {
+        *path = expand_home(path, home.as_deref());
+    }
+    Ok(config)
+}
+
+/// `path` with a leading `~` (alone, or as `~/...`) replaced by `home`. left as it is without a `home`.
+fn expand_home(path: &Path, home: Option<&Path>) -> PathBuf {
+    let mut components = path.components();
+    match (components.next(), home) {
+        (Some(std::path::Component::Normal(first)), Some(home)) if first == "~" => home.join(components.as_path()),
+        _ => path.to_path_buf(),
+    }
+}
+
+pub fn load(path: &Path) -> Result<Config, StitcherError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| StitcherError::io(format!("reading the config {}", path.to_string_lossy()), e))?;
+    parse(&contents).map_err(StitcherError::InvalidConfig)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_parse_extensions() {
+        let config = parse(
+            r#"
+            [extensions]
+            wv = { codec = "wavpack", copy_safe = false }
+
+            [extensions.aiff]
+            codec = "pcm_s16be"
+            "#,
+        )
+        .expect("expected the config to parse");
+
+        assert_eq!(
+            config.extensions.get("wv"),
+            Some(&formats::Format { codec: Some(String::from("wavpack")), copy_safe: false })
+        );
+        assert_eq!(
+            config.extensions.get("aiff"),
+            Some(&formats::Format { codec: Some(String::from("pcm_s16be")), copy_safe: true })
+        );
+
+        assert!(parse("").expect("an empty config is fine").extensions.is_empty());
+        assert_eq!(parse("").expect("an empty config is fine").defaults, Defaults::default());
+        assert!(parse("[extensions]\nwv = 3").is_err());
+    }
+
+    #[test]
+    pub fn test_parse_defaults() {
+        let config = parse(
+            r#"
+            [defaults]
+            ffmpeg = "/opt/ffmpeg/bin/ffmpeg"
+            output_dir = "~/stitched"
+            name_template = "session {date}"
+            sort = "sidecar:order"
+            "#,
+        )
+        .expect("expected the config to parse");
+
+        let home = PathBuf::from(std::env::var_os("HOME").expect("this test expects $HOME to be set"));
+        assert_eq!(
+            config.defaults,
+            Defaults {
+                ffmpeg: Some(PathBuf::from("/opt/ffmpeg/bin/ffmpeg")),
+                output_dir: Some(home.join("stitched")),
+                name_template: Some(String::from("session {date}")),
+                sort: Some(sort::SortOrder::SidecarOrder),
+            }
+        );
+
+        assert!(parse("[defaults]\nsort = \"sideways\"").is_err());
+        assert!(parse("[defaults]\nffmpge = \"/bin/ffmpeg\"").is_err(), "expected a typo'd key to be refused");
+    }
+
+    #[test]
+    pub fn test_expand_home() {
+        let home = Some(Path::new("/home/sam"));
+        assert_eq!(expand_home(Path::new("~/bin/ffmpeg"), home), Path::new("/home/sam/bin/ffmpeg"));
+        assert_eq!(expand_home(Path::new("~"), home), Path::new("/home/sam"));
+        assert_eq!(expand_home(Path::new("~sam/bin"), home), Path::new("~sam/bin"));
+        assert_eq!(expand_home(Path::new("stitched/~"), home), Path::new("stitched/~"));
+        assert_eq!(expand_home(Path::new("~/stitched"), None), Path::new("~/stitched"));
+    }
+}
@@ -0,0 +1,261 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long to wait between discovery attempts on a flaky filesystem.
+pub const RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// The filesystem calls discovery makes, so tests can stand in a filesystem that misbehaves.
+pub trait DirReader {
+    /// The paths of every entry in `dir`. an error reading any single entry fails the whole listing,
+    /// rather than quietly dropping that file.
+    fn read_dir(&self, dir: &Path) -> std::io::Result<Vec<PathBuf>>;
+
+    /// Whether `path` is a directory to descend into. symlinks to directories aren't, so a link
+    /// cycle can't make the walk go on forever.
+    fn is_dir(&self, path: &Path) -> bool;
+}
+
+/// The real filesystem.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RealFs;
+
+impl DirReader for RealFs {
+    fn read_dir(&self, dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+        std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|x| x.path()))
+            .collect()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        std::fs::symlink_metadata(path).is_ok_and(|x| x.is_dir())
+    }
+}
+
+/// How to walk the input directory.
+#[derive(Debug, Default, Clone)]
+pub struct WalkOptions {
+    /// Descend into subdirectories.
+    pub recursive: bool,
+    /// With `recursive`, only find files at most this many levels below the root (1 is the root's own entries).
+    pub max_depth: Option<usize>,
+    /// Subdirectories matching any of these (relative to the input directory) aren't descended into at all.
+    pub prune: Vec<glob::Pattern>,
+    /// How many times to retry a failed directory listing.
+    pub retries: u32,
+    /// Fail on a subdirectory (or playlist entry) that can't be read, instead of skipping it with a warning.
+    pub strict: bool,
+}
+
+/// Every file under `root`, per `options`. the order doesn't depend on the filesystem: each directory's
+/// entries go by file name, with a subdirectory's files in place of the subdirectory itself. if `root`
+/// can't be listed that's an error; a subdirectory that can't be listed is skipped with a warning, or is an
+/// error too with `strict`.
+pub fn walk(fs: &dyn DirReader, root: &Path, options: &WalkOptions) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in sorted(read_dir_with_retries(fs, root, options.retries, RETRY_DELAY)?) {
+        walk_entry(fs, root, entry, 1, options, &mut files)?;
+    }
+    Ok(files)
+}
+
+/// Walk `entry`, which is `depth` levels below the root.
+fn walk_entry(
+    fs: &dyn DirReader,
+    root: &Path,
+    entry: PathBuf,
+    depth: usize,
+    options: &WalkOptions,
+    files: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    if !fs.is_dir(&entry) {
+        files.push(entry);
+        return Ok(());
+    }
+
+    let too_deep = options.max_depth.is_some_and(|max_depth| depth >= max_depth);
+    if !options.recursive || too_deep || is_pruned(root, &entry, &options.prune) {
+        return Ok(());
+    }
+
+    match read_dir_with_retries(fs, &entry, options.retries, RETRY_DELAY) {
+        Ok(entries) => {
+            for child in sorted(entries) {
+                walk_entry(fs, root, child, depth + 1, options, files)?;
+            }
+        }
+        Err(e) if options.strict => {
+            return Err(std::io::Error::new(e.kind(), format!("couldn't list {}: {}", entry.to_string_lossy(), e)))
+        }
+        Err(e) => log::warn!("skipping {}: {}", entry.to_string_lossy(), e),
+    }
+    Ok(())
+}
+
+fn sorted(mut entries: Vec<PathBuf>) -> Vec<PathBuf> {
+    entries.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    entries
+}
+
+/// Whether `dir` matches a prune pattern. a pattern matches the directory itself (`drafts`, `**/drafts`)
+/// or everything inside it (`**/drafts/**`); both prune the whole subtree.
+fn is_pruned(root: &Path, dir: &Path, prune: &[glob::Pattern]) -> bool {
+    let relative = dir.strip_prefix(root).unwrap_or(dir);
+    let inside = relative.join("_");
+    let match_options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+
+    prune.iter().any(|pattern| {
+        pattern.matches_path_with(relative, match_options) || pattern.matches_path_with(&inside, match_options)
+    })
+}
+
+/// List `dir`, trying again up to `retries` more times (waiting `delay` in between) if it fails.
+/// network mounts can fail a stat or a listing transiently, which otherwise looks like missing files.
+pub fn read_dir_with_retries(
+    fs: &dyn DirReader,
+    dir: &Path,
+    retries: u32,
+    delay: Duration,
+) -> std::io::Result<Vec<PathBuf>> {
+    let mut attempt = 0;
+    loop {
+        match fs.read_dir(dir) {
+            Ok(entries) => return Ok(entries),
+            Err(e) if attempt < retries => {
+                attempt += 1;
+                log::warn!(
+                    "failed to read {} ({}), retrying ({}/{})",
+                    dir.to_string_lossy(),
+                    e,
+                    attempt,
+                    retries
+                );
+                std::thread::sleep(delay);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+
+    /// Fails the first `failures` listings, then lists a single file.
+    struct FlakyFs {
+        failures: Cell<u32>,
+    }
+
+    impl DirReader for FlakyFs {
+        fn read_dir(&self, dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+            if self.failures.get() > 0 {
+                self.failures.set(self.failures.get() - 1);
+                return Err(std::io::Error::other("stale file handle"));
+            }
+            Ok(vec![dir.join("click-1.wav")])
+        }
+
+        fn is_dir(&self, _path: &Path) -> bool {
+            false
+        }
+    }
+
+    /// An in-memory tree that records every directory it's asked to list.
+    struct TreeFs {
+        dirs: Vec<(&'static str, Vec<&'static str>)>,
+        listed: RefCell<Vec<PathBuf>>,
+    }
+
+    impl DirReader for TreeFs {
+        fn read_dir(&self, dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+            self.listed.borrow_mut().push(dir.to_path_buf());
+            let (_, entries) = self
+                .dirs
+                .iter()
+                .find(|(path, _)| Path::new(path) == dir)
+                .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))?;
+            Ok(entries.iter().map(|entry| dir.join(entry)).collect())
+        }
+
+        fn is_dir(&self, path: &Path) -> bool {
+            self.dirs.iter().any(|(dir, _)| Path::new(dir) == path)
+        }
+    }
+
+    #[test]
+    pub fn test_discovery_retries_a_transient_failure() {
+        let dir = Path::new("/mnt/nfs/sessions");
+
+        let fs = FlakyFs { failures: Cell::new(1) };
+        let found = read_dir_with_retries(&fs, dir, 2, Duration::ZERO)
+            .expect("expected the retry to get past a single failure");
+        assert_eq!(found, vec![dir.join("click-1.wav")]);
+
+        let fs = FlakyFs { failures: Cell::new(1) };
+        assert!(read_dir_with_retries(&fs, dir, 0, Duration::ZERO).is_err());
+    }
+
+    #[test]
+    pub fn test_pruned_directories_are_not_descended_into() {
+        let fs = TreeFs {
+            dirs: vec![
+                ("/in", vec!["a.wav", "drafts", "day1"]),
+                ("/in/drafts", vec!["rough.wav", "older"]),
+                ("/in/drafts/older", vec!["rougher.wav"]),
+                ("/in/day1", vec!["b.wav", "drafts"]),
+                ("/in/day1/drafts", vec!["c.wav"]),
+            ],
+            listed: RefCell::new(vec![]),
+        };
+        let options = WalkOptions {
+            recursive: true,
+            prune: vec![glob::Pattern::new("**/drafts/**").expect("valid pattern")],
+            ..Default::default()
+        };
+
+        let files = walk(&fs, Path::new("/in"), &options).expect("expected the walk to succeed");
+        assert_eq!(files, ["/in/a.wav", "/in/day1/b.wav"].map(PathBuf::from));
+        assert_eq!(
+            *fs.listed.borrow(),
+            ["/in", "/in/day1"].map(PathBuf::from),
+            "expected the walker to never list a pruned directory"
+        );
+    }
+
+    #[test]
+    pub fn test_walk_order_and_max_depth() {
+        let fs = TreeFs {
+            dirs: vec![
+                ("/in", vec!["z.wav", "b", "a.wav"]),
+                ("/in/b", vec!["y.wav", "c", "x.wav"]),
+                ("/in/b/c", vec!["deep.wav"]),
+            ],
+            listed: RefCell::new(vec![]),
+        };
+        let recursive = WalkOptions {
+            recursive: true,
+            ..Default::default()
+        };
+
+        let files = walk(&fs, Path::new("/in"), &recursive).expect("expected the walk to succeed");
+        let expected = ["/in/a.wav", "/in/b/c/deep.wav", "/in/b/x.wav", "/in/b/y.wav", "/in/z.wav"];
+        assert_eq!(files, expected.map(PathBuf::from), "expected name order, whatever order the listing came in");
+
+        let shallow = WalkOptions {
+            max_depth: Some(2),
+            ..recursive.clone()
+        };
+        let files = walk(&fs, Path::new("/in"), &shallow).expect("expected the walk to succeed");
+        assert_eq!(files, ["/in/a.wav", "/in/b/x.wav", "/in/b/y.wav", "/in/z.wav"].map(PathBuf::from));
+
+        let top_only = WalkOptions {
+            max_depth: Some(1),
+            ..recursive
+        };
+        let files = walk(&fs, Path::new("/in"), &top_only).expect("expected the walk to succeed");
+        assert_eq!(files, ["/in/a.wav", "/in/z.wav"].map(PathBuf::from));
+    }
+}
@@ -0,0 +1,86 @@
+use std::path::Path;
+
+/// Project-local settings file, read from the current directory before the CLI is parsed.
+pub const DOTFILE_NAME: &str = ".stitcher.env";
+
+/// Parse simple `KEY=VALUE` lines. blank lines and `#` comments are skipped, and values may be
+/// wrapped in single or double quotes. lines without an `=` are ignored.
+pub fn parse(contents: &str) -> Vec<(String, String)> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            let key = key.trim();
+            if key.is_empty() {
+                return None;
+            }
+            Some((key.to_string(), unquote(value.trim()).to_string()))
+        })
+        .collect()
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+            return &value[1..value.len() - 1];
+        }
+    }
+
+    // an unquoted value can carry a trailing comment
+    //
+    match value.split_once(" #") {
+        Some((value, _comment)) => value.trim_end(),
+        None => value,
+    }
+}
+
+/// Load `path` into the process environment, if it exists. variables that are already set win over
+/// the file, and CLI flags win over both. must run before any other threads are started.
+pub fn load(path: &Path) -> Result<(), String> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("failed to read {}: {:?}", path.to_string_lossy(), e)),
+    };
+
+    for (key, value) in parse(&contents) {
+        if std::env::var_os(&key).is_none() {
+            std::env::set_var(key, value);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_parse_dotfile() {
+        let contents = "\
+# paths for this project
+
+STITCHER_FFMPEG=/opt/ffmpeg/bin/ffmpeg
+STITCHER_FFPROBE = \"/opt/ffmpeg/bin/ff probe\"
+  # indented comment
+STITCHER_INPUT_PATH='./sessions/today'
+EMPTY=
+NOT_A_SETTING
+TRAILING=abc # note
+";
+
+        let expected = [
+            ("STITCHER_FFMPEG", "/opt/ffmpeg/bin/ffmpeg"),
+            ("STITCHER_FFPROBE", "/opt/ffmpeg/bin/ff probe"),
+            ("STITCHER_INPUT_PATH", "./sessions/today"),
+            ("EMPTY", ""),
+            ("TRAILING", "abc"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+
+        assert_eq!(parse(contents), expected);
+    }
+}
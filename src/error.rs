@@ -0,0 +1,163 @@
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
+use crate::options::ValidationError;
+
+/// Why a stitch failed, by kind, so a caller can tell the failures apart. the CLI exits with
+/// a different status for each (see `exit_code`).
+#[derive(Debug)]
+pub enum StitcherError {
+    /// None of these paths ran as ffmpeg.
+    FfmpegNotFound(Vec<PathBuf>),
+    /// ffprobe wasn't found, and something asked for needs it. says what.
+    FfprobeNotFound(String),
+    /// There's nothing to stitch. says why.
+    NoInputs(String),
+    /// An input can't be stitched as it is: it's unreadable, doesn't match the others, or ffmpeg warned about
+    /// it under --strict. says why.
+    BadInput(String),
+    /// The options don't work, for all of these reasons.
+    InvalidOptions(Vec<ValidationError>),
+    /// The config file doesn't parse. says why.
+    InvalidConfig(String),
+    /// Reading or writing a file failed, while doing `context`.
+    Io { context: String, error: std::io::Error },
+    /// The output is already there, and overwriting it wasn't allowed.
+    OutputExists(PathBuf),
+    /// ffmpeg ran, but didn't succeed. `errors` are the lines of its stderr that say why.
+    FfmpegFailed { status: ExitStatus, errors: Vec<String> },
+    /// Ctrl+C stopped the stitch.
+    Interrupted,
+    /// `failed` of the `total` folders of a --batch run failed to stitch, the first of them with `exit_code`.
+    BatchFailed { failed: usize, total: usize, exit_code: u8 },
+    /// Anything else, as a message.
+    Other(String),
+}
+
+/// Process exit statuses, one per kind of failure. they follow BSD's sysexits.h, like the lockfile's 75.
+pub const EXIT_USAGE: u8 = 64;
+pub const EXIT_DATA: u8 = 65;
+pub const EXIT_NO_INPUT: u8 = 66;
+pub const EXIT_UNAVAILABLE: u8 = 69;
+pub const EXIT_SOFTWARE: u8 = 70;
+pub const EXIT_CANT_CREATE: u8 = 73;
+pub const EXIT_IO: u8 = 74;
+pub const EXIT_CONFIG: u8 = 78;
+
+impl StitcherError {
+    /// A `StitcherError::Io` for `error`, which happened while doing `context` (e.g. "writing the concat list").
+    pub fn io(context: impl Into<String>, error: std::io::Error) -> Self {
+        StitcherError::Io { context: context.into(), error }
+    }
+
+    /// A `StitcherError::FfmpegFailed` for a run that exited with `status`, explained by its `stderr`.
+    pub fn ffmpeg_failed(status: ExitStatus, stderr: &str) -> Self {
+        StitcherError::FfmpegFailed { status, errors: crate::ffmpeg::error_lines(stderr) }
+    }
+
+    /// The process exit status for this kind of failure.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            StitcherError::InvalidOptions(_) => EXIT_USAGE,
+            StitcherError::InvalidConfig(_) => EXIT_CONFIG,
+            StitcherError::NoInputs(_) => EXIT_NO_INPUT,
+            StitcherError::BadInput(_) => EXIT_DATA,
+            StitcherError::FfmpegNotFound(_) | StitcherError::FfprobeNotFound(_) => EXIT_UNAVAILABLE,
+            StitcherError::FfmpegFailed { .. } => EXIT_SOFTWARE,
+            StitcherError::OutputExists(_) => EXIT_CANT_CREATE,
+            StitcherError::Io { .. } => EXIT_IO,
+            StitcherError::Interrupted => crate::interrupt::EXIT_INTERRUPTED,
+            StitcherError::BatchFailed { exit_code, .. } => *exit_code,
+            StitcherError::Other(_) => 1,
+        }
+    }
+}
+
+impl std::fmt::Display for StitcherError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StitcherError::FfmpegNotFound(checked) => {
+                write!(f, "failed to find a valid ffmpeg binary. checked paths: {:?}", checked)
+            }
+            StitcherError::FfprobeNotFound(reason) => write!(f, "{}", reason),
+            StitcherError::NoInputs(reason) => write!(f, "{}", reason),
+            StitcherError::BadInput(reason) => write!(f, "{}", reason),
+            StitcherError::InvalidOptions(errors) => {
+                let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+                write!(f, "invalid options: {}", errors.join("; "))
+            }
+            StitcherError::InvalidConfig(reason) => write!(f, "{}", reason),
+            StitcherError::Io { context, error } => write!(f, "failed {}: {}", context, error),
+            StitcherError::OutputExists(path) => {
+                write!(f, "{} already exists. pass --overwrite to replace it", path.to_string_lossy())
+            }
+            StitcherError::FfmpegFailed { status, errors } => {
+                write!(f, "did not concatenate the files: exit not ok: {:?}", status)?;
+                match errors.is_empty() {
+                    true => Ok(()),
+                    false => write!(f, ". ffmpeg said: {}", errors.join("; ")),
+                }
+            }
+            StitcherError::Interrupted => write!(f, "interrupted"),
+            StitcherError::BatchFailed { failed, total, .. } => write!(f, "{} of {} folders failed to stitch", failed, total),
+            StitcherError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for StitcherError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StitcherError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_every_kind_exits_differently() {
+        let errors = [
+            StitcherError::FfmpegNotFound(vec![PathBuf::from("/bin/ffmpeg")]),
+            StitcherError::NoInputs(String::from("found no files!")),
+            StitcherError::BadInput(String::from("a.wav isn't a readable audio file")),
+            StitcherError::OutputExists(PathBuf::from("out.wav")),
+            StitcherError::InvalidOptions(vec![ValidationError::MissingOutputExtension]),
+            StitcherError::InvalidConfig(String::from("failed to parse the config: expected `=`")),
+            StitcherError::io("writing the concat list", std::io::Error::from(std::io::ErrorKind::PermissionDenied)),
+            StitcherError::Interrupted,
+            StitcherError::Other(String::from("something else")),
+        ];
+
+        let mut codes = errors.iter().map(|e| e.exit_code()).collect::<Vec<_>>();
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            let failed = StitcherError::ffmpeg_failed(ExitStatus::from_raw(256), "[wav @ 0x1] Unsupported codec id 1\n");
+            assert!(failed.to_string().ends_with("ffmpeg said: [wav @ 0x1] Unsupported codec id 1"), "got: {}", failed);
+            codes.push(failed.exit_code());
+        }
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len() + usize::from(cfg!(unix)), "expected a distinct exit status per kind of failure");
+        assert!(!codes.contains(&0));
+
+        // ffprobe missing is the same kind of failure as ffmpeg missing, and a --batch run fails the way its
+        // first failed folder did
+        //
+        assert_eq!(StitcherError::FfprobeNotFound(String::from("--strict needs ffprobe")).exit_code(), EXIT_UNAVAILABLE);
+        assert_eq!(StitcherError::BatchFailed { failed: 2, total: 5, exit_code: EXIT_DATA }.exit_code(), EXIT_DATA);
+
+        assert_eq!(
+            StitcherError::InvalidOptions(vec![
+                ValidationError::MissingOutputExtension,
+                ValidationError::FlacRecompressNeedsFlacOutput,
+            ])
+            .to_string(),
+            "invalid options: the output file needs an extension, like .wav; --flac-recompress needs a .flac output file"
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::StitcherError;
+use crate::order_report;
+
+/// Where static builds are downloaded from. every release publishes a `checksums.sha256` next to its archives.
+pub const RELEASES_URL: &str = "https://github.com/BtbN/FFmpeg-Builds/releases/download/latest";
+
+/// Where `fetch` puts ffmpeg and ffprobe by default: where `default_ffmpeg_paths` looks last.
+pub const VENDOR_DIR: &str = "./vendor/ffmpeg";
+
+/// The binaries copied out of a build.
+const BINARIES: &[&str] = &["ffmpeg", "ffprobe"];
+
+/// A static ffmpeg build for one platform.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Build {
+    /// File name of the `.tar.xz` archive. it unpacks to a directory of the same name, minus the extension.
+    pub archive: &'static str,
+}
+
+impl Build {
+    pub fn archive_url(&self) -> String {
+        format!("{}/{}", RELEASES_URL, self.archive)
+    }
+
+    pub fn checksums_url(&self) -> String {
+        format!("{}/checksums.sha256", RELEASES_URL)
+    }
+
+    /// The directory inside the archive that holds `bin/`.
+    fn root_dir(&self) -> &str {
+        self.archive.trim_end_matches(".tar.xz")
+    }
+}
+
+/// The build for an OS and CPU architecture, as named by `std::env::consts`, if there's one to download.
+pub fn build_for(os: &str, arch: &str) -> Option<Build> {
+    let archive = match (os, arch) {
+        ("linux", "x86_64") => "ffmpeg-master-latest-linux64-gpl.tar.xz",
+        ("linux", "aarch64") => "ffmpeg-master-latest-linuxarm64-gpl.tar.xz",
+        _ => return None,
+    };
+    Some(Build { archive })
+}
+
+/// The expected hash of `archive` from a `sha256sum`-style checksums file (`hash  name` lines).
+pub fn parse_checksums(contents: &str, archive: &str) -> Option<String> {
+    contents.lines().find_map(|line| {
+        let (hash, name) = line.trim().split_once(char::is_whitespace)?;
+        match name.trim().trim_start_matches('*') == archive {
+            true => Some(hash.to_lowercase()),
+            false => None,
+        }
+    })
+}
+
+/// Check that `file` hashes to `expected_sha256`.
+pub fn verify(file: &Path, expected_sha256: &str) -> Result<(), StitcherError> {
+    let actual = order_report::sha256_file(file)?;
+    match actual.eq_ignore_ascii_case(expected_sha256) {
+        true => Ok(()),
+        false => Err(StitcherError::Other(format!(
+            "checksum mismatch for {}: expected {}, got {}",
+            file.to_string_lossy(),
+            expected_sha256,
+            actual
+        ))),
+    }
+}
+
+/// Download `build`, check it against its published checksum, and put its ffmpeg and ffprobe in
+/// `dest_dir`, executable. downloads with `curl` and unpacks with `tar`. returns the path of ffmpeg.
+pub fn fetch(build: &Build, dest_dir: &Path) -> Result<PathBuf, StitcherError> {
+    let work_dir = std::env::temp_dir().join(format!("stitcher_fetch_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&work_dir);
+    std::fs::create_dir_all(&work_dir)
+        .map_err(|e| StitcherError::io(format!("creating {}", work_dir.to_string_lossy()), e))?;
+
+    // the download is big, so the scratch copy goes whether or not it worked out
+    //
+    let result = fetch_into(build, &work_dir, dest_dir);
+    let _ = std::fs::remove_dir_all(&work_dir);
+    result
+}
+
+fn fetch_into(build: &Build, work_dir: &Path, dest_dir: &Path) -> Result<PathBuf, StitcherError> {
+    let archive = work_dir.join(build.archive);
+    run(Command::new("curl").arg("-fL").arg("-o").arg(&archive).arg(build.archive_url()))?;
+
+    let checksums = run(Command::new("curl").arg("-fsSL").arg(build.checksums_url()))?;
+    let expected = parse_checksums(&checksums, build.archive)
+        .ok_or_else(|| StitcherError::Other(format!("no checksum published for {}", build.archive)))?;
+    verify(&archive, &expected)?;
+
+    run(Command::new("tar").arg("-xJf").arg(&archive).arg("-C").arg(work_dir))?;
+
+    std::fs::create_dir_all(dest_dir)
+        .map_err(|e| StitcherError::io(format!("creating {}", dest_dir.to_string_lossy()), e))?;
+    for name in BINARIES {
+        let from = work_dir.join(build.root_dir()).join("bin").join(name);
+        let to = dest_dir.join(name);
+        std::fs::copy(&from, &to).map_err(|e| {
+            StitcherError::io(format!("copying {} to {}", from.to_string_lossy(), to.to_string_lossy()), e)
+        })?;
+        make_executable(&to)?;
+    }
+
+    Ok(dest_dir.join("ffmpeg"))
+}
+
+/// Run `command`, returning its stdout, or its stderr as the error if it fails.
+fn run(command: &mut Command) -> Result<String, StitcherError> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .output()
+        .map_err(|e| StitcherError::io(format!("running {}", program), e))?;
+
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        false => Err(StitcherError::Other(format!(
+            "{} failed ({:?}): {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))),
+    }
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<(), StitcherError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+        .map_err(|e| StitcherError::io(format!("making {} executable", path.to_string_lossy()), e))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<(), StitcherError> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_build_for_platform() {
+        let build = build_for("linux", "x86_64").expect("expected a build for 64-bit linux");
+        assert_eq!(
+            build.archive_url(),
+            "https://github.com/BtbN/FFmpeg-Builds/releases/download/latest/ffmpeg-master-latest-linux64-gpl.tar.xz"
+        );
+        assert_eq!(build.root_dir(), "ffmpeg-master-latest-linux64-gpl");
+        assert_eq!(build_for("plan9", "mips"), None);
+    }
+
+    #[test]
+    pub fn test_checksums_are_parsed_and_verified() {
+        let checksums = "\
+            1111111111111111111111111111111111111111111111111111111111111111  ffmpeg-master-latest-win64-gpl.zip\n\
+            BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD *ffmpeg-master-latest-linux64-gpl.tar.xz\n";
+        let expected = parse_checksums(checksums, "ffmpeg-master-latest-linux64-gpl.tar.xz")
+            .expect("expected the archive's checksum");
+        assert_eq!(expected, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(parse_checksums(checksums, "other.tar.xz"), None);
+
+        let file = std::env::temp_dir().join(format!("stitcher_test_fetch_{}.tar.xz", std::process::id()));
+        std::fs::write(&file, "abc").expect("failed to write a scratch file");
+        assert!(verify(&file, &expected).is_ok());
+        assert!(verify(&file, "1111111111111111111111111111111111111111111111111111111111111111").is_err());
+
+        let _ = std::fs::remove_file(file);
+    }
+}
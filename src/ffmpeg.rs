@@ -0,0 +1,348 @@
+use std::ffi::OsString;
+use std::io::{BufRead, Read, Write};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Arc;
+
+use shared_child::SharedChild;
+
+use crate::{error::StitcherError, interrupt, progress};
+
+/// ffmpeg errors that a stream copy can hit on inputs which decode fine, so re-encoding is worth a try.
+/// anything else (missing files, bad arguments, full disks...) fails the same way on a retry.
+const RECOVERABLE_COPY_ERRORS: &[&str] = &[
+    "Invalid data found when processing input",
+    "Header missing",
+];
+
+/// ffmpeg warnings that it carries on past, but that usually mean the output has a glitch in it -
+/// timestamps going backwards at a join, a frame that didn't decode, a guessed duration.
+const PROBLEM_WARNINGS: &[&str] = &[
+    "Non-monotonous DTS",
+    "non monotonically increasing dts",
+    "Queue input is backward in time",
+    "Error while decoding stream",
+    "Estimating duration from bitrate",
+];
+
+/// What the lines ffmpeg explains a failure with contain. it says a lot more on stderr, so a failed run's
+/// error only carries these (see `error_lines`).
+const ERROR_PATTERNS: &[&str] = &[
+    "Unsupported codec",
+    "Invalid data found",
+    "No such file or directory",
+    "Permission denied",
+    "Impossible to open",
+    "Unknown encoder",
+    "Unknown decoder",
+    "not supported",
+    "Invalid argument",
+    "No space left on device",
+    "Error ",
+    "Error:",
+];
+
+/// How many of the last lines of stderr `error_lines` falls back to when none of them look like an error.
+const FALLBACK_ERROR_LINES: usize = 3;
+
+/// The result of one ffmpeg invocation.
+#[derive(Debug)]
+pub struct FfmpegRun {
+    pub status: ExitStatus,
+    /// Everything ffmpeg wrote to stderr.
+    pub stderr: String,
+}
+
+impl FfmpegRun {
+    /// `Ok` if ffmpeg exited successfully, `StitcherError::Interrupted` if Ctrl+C stopped it, or a
+    /// `StitcherError::FfmpegFailed` explained by its stderr.
+    pub fn check(&self) -> Result<(), StitcherError> {
+        match self.status.success() {
+            true => Ok(()),
+            false if interrupt::requested() => Err(StitcherError::Interrupted),
+            false => Err(StitcherError::ffmpeg_failed(self.status, &self.stderr)),
+        }
+    }
+}
+
+/// How to report progress while ffmpeg runs.
+#[derive(Debug, Clone, Copy)]
+pub enum Progress {
+    /// Leave ffmpeg's own stats output alone.
+    Inherit,
+    /// Read the `-progress pipe:1` stream and re-emit each block as a JSON line on stderr.
+    Json { total_duration_secs: Option<f64> },
+    /// Read the `-progress pipe:1` stream and redraw a progress bar on stderr for each block.
+    Bar { total_duration_secs: Option<f64> },
+}
+
+impl Progress {
+    /// The total duration to work percent complete out from, when progress is read from ffmpeg at all.
+    fn reads_stream(&self) -> Option<Option<f64>> {
+        match self {
+            Progress::Inherit => None,
+            Progress::Json { total_duration_secs } | Progress::Bar { total_duration_secs } => Some(*total_duration_secs),
+        }
+    }
+}
+
+/// `ffmpeg_bin_path` and `args` as one line, each quoted for a POSIX shell if it needs it, so the command
+/// can be pasted and run.
+pub fn command_line(ffmpeg_bin_path: &Path, args: &[OsString]) -> String {
+    std::iter::once(ffmpeg_bin_path.as_os_str())
+        .chain(args.iter().map(|x| x.as_os_str()))
+        .map(|x| shell_quote(&x.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `command_line`, with its stdin read from `stdin_file` if there is one, e.g. `ffmpeg ... < list.txt`.
+pub fn command_line_with_stdin(ffmpeg_bin_path: &Path, args: &[OsString], stdin_file: Option<&Path>) -> String {
+    let command = command_line(ffmpeg_bin_path, args);
+    match stdin_file {
+        Some(file) => format!("{} < {}", command, shell_quote(&file.to_string_lossy())),
+        None => command,
+    }
+}
+
+fn shell_quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/' | ':' | '=' | ',' | '+' | '@'));
+    match safe {
+        true => String::from(arg),
+        false => format!("'{}'", arg.replace('\'', "'\\''")),
+    }
+}
+
+/// Run ffmpeg with `args`, keeping a copy of its stderr. the stderr is also passed through to ours
+/// as it arrives, unless `quiet` is set.
+pub fn run(ffmpeg_bin_path: &Path, args: &[OsString], progress: Progress, quiet: bool) -> Result<FfmpegRun, StitcherError> {
+    run_with_stdin(ffmpeg_bin_path, args, None, progress, quiet)
+}
+
+/// `run`, writing `stdin` (if there is one) to ffmpeg's stdin, for an input of `pipe:0`.
+pub fn run_with_stdin(
+    ffmpeg_bin_path: &Path,
+    args: &[OsString],
+    stdin: Option<&str>,
+    progress: Progress,
+    quiet: bool,
+) -> Result<FfmpegRun, StitcherError> {
+    log::debug!("running {}", command_line(ffmpeg_bin_path, args));
+    let mut command = Command::new(ffmpeg_bin_path);
+    command.args(args).stderr(Stdio::piped());
+    if progress.reads_stream().is_some() {
+        command.stdout(Stdio::piped());
+    }
+    if stdin.is_some() {
+        command.stdin(Stdio::piped());
+    }
+
+    let child = SharedChild::spawn(&mut command)
+        .map(Arc::new)
+        .map_err(|e| StitcherError::io(format!("running {}", ffmpeg_bin_path.to_string_lossy()), e))?;
+    // Ctrl+C kills it, rather than leaving it running after we've gone
+    //
+    let _tracked = interrupt::track(child.clone());
+
+    // written on its own thread too, and closed when done so ffmpeg sees the end of it. if ffmpeg
+    // exits without reading it all, the write fails, and the exit status says why
+    //
+    let stdin_writer = stdin.map(|contents| {
+        let mut pipe = child.take_stdin().expect("ffmpeg stdin was requested as piped");
+        let contents = contents.to_owned();
+        std::thread::spawn(move || {
+            let _ = pipe.write_all(contents.as_bytes());
+        })
+    });
+
+    // stderr is drained on its own thread so a chatty ffmpeg can't block on a full pipe
+    // while we're busy reading progress from stdout
+    //
+    let mut stderr = child.take_stderr().expect("ffmpeg stderr was requested as piped");
+    let stderr_reader = std::thread::spawn(move || {
+        let mut collected = vec![];
+        let mut buf = [0u8; 4096];
+        while let Ok(n @ 1..) = stderr.read(&mut buf) {
+            if !quiet {
+                let _ = std::io::stderr().write_all(&buf[..n]);
+            }
+            collected.extend_from_slice(&buf[..n]);
+        }
+        String::from_utf8_lossy(&collected).into_owned()
+    });
+
+    if let Some(total_duration_secs) = progress.reads_stream() {
+        let stdout = child.take_stdout().expect("ffmpeg stdout was requested as piped");
+        let mut parser = progress::ProgressParser::default();
+        let mut drew_bar = false;
+        for line in std::io::BufReader::new(stdout).lines().map_while(Result::ok) {
+            if let Some(block) = parser.feed_line(&line) {
+                let event = progress::ProgressEvent::from_ffmpeg(&block, total_duration_secs);
+                match progress {
+                    Progress::Bar { .. } => {
+                        // redraw in place, so the bar stays on one line
+                        //
+                        eprint!("\r{}", event.to_bar_line());
+                        drew_bar = true;
+                    }
+                    _ => eprintln!("{}", event.to_json_line()),
+                }
+            }
+        }
+        if drew_bar {
+            eprintln!();
+        }
+    }
+
+    let status = child
+        .wait()
+        .map_err(|e| StitcherError::io(format!("waiting for {}", ffmpeg_bin_path.to_string_lossy()), e))?;
+    let stderr = stderr_reader.join().unwrap_or_default();
+    if let Some(writer) = stdin_writer {
+        let _ = writer.join();
+    }
+
+    Ok(FfmpegRun { status, stderr })
+}
+
+/// Something that runs ffmpeg, so tests can check the commands issued without an ffmpeg binary.
+pub trait Runner {
+    fn run(&self, args: &[OsString]) -> Result<FfmpegRun, StitcherError>;
+}
+
+/// The real ffmpeg, with its output passed through unless `quiet`.
+#[derive(Debug, Clone)]
+pub struct Ffmpeg {
+    pub bin_path: std::path::PathBuf,
+    pub quiet: bool,
+}
+
+impl Runner for Ffmpeg {
+    fn run(&self, args: &[OsString]) -> Result<FfmpegRun, StitcherError> {
+        run(&self.bin_path, args, Progress::Inherit, self.quiet)
+    }
+}
+
+/// Whether a failed copy-concat's stderr shows one of the errors a re-encode is known to get past.
+pub fn is_recoverable_copy_error(stderr: &str) -> bool {
+    RECOVERABLE_COPY_ERRORS
+        .iter()
+        .any(|pattern| stderr.contains(pattern))
+}
+
+/// The lines of a run's stderr that match one of the known problem warnings.
+pub fn problem_warnings(stderr: &str) -> Vec<&str> {
+    stderr
+        .lines()
+        .filter(|line| PROBLEM_WARNINGS.iter().any(|pattern| line.contains(pattern)))
+        .collect()
+}
+
+/// The lines of a failed run's stderr that say what went wrong, in order and without repeats. when none
+/// match a known error, the last few lines stand in, since ffmpeg usually says why right before giving up.
+pub fn error_lines(stderr: &str) -> Vec<String> {
+    let lines = stderr
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with("size=") && *line != "Conversion failed!")
+        .collect::<Vec<_>>();
+
+    let mut errors = lines
+        .iter()
+        .filter(|line| ERROR_PATTERNS.iter().any(|pattern| line.contains(pattern)))
+        .map(|line| line.to_string())
+        .collect::<Vec<_>>();
+    if errors.is_empty() {
+        errors = lines.iter().rev().take(FALLBACK_ERROR_LINES).rev().map(|line| line.to_string()).collect();
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    errors.retain(|line| seen.insert(line.clone()));
+    errors
+}
+
+/// Report the problem warnings in a successful run's stderr. they're printed as warnings, or fail
+/// the run when `strict` is set.
+pub fn check_warnings(stderr: &str, strict: bool) -> Result<(), StitcherError> {
+    let warnings = problem_warnings(stderr);
+    if warnings.is_empty() {
+        return Ok(());
+    }
+
+    match strict {
+        true => Err(StitcherError::BadInput(format!(
+            "ffmpeg warned about the output (--strict or --strict-ffmpeg): {}",
+            warnings.join("; ")
+        ))),
+        false => {
+            for warning in warnings {
+                log::warn!("ffmpeg: {}", warning.trim());
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    pub fn test_stdin_is_written_to_ffmpeg() {
+        // `sh` stands in for ffmpeg, echoing what it's given on stdin to stderr, where it's collected
+        //
+        let args = ["-c", "cat >&2"].map(OsString::from);
+        let run = run_with_stdin(Path::new("sh"), &args, Some("file '/rec/a.wav'\n"), Progress::Inherit, true)
+            .expect("expected sh to run");
+        assert!(run.status.success());
+        assert_eq!(run.stderr, "file '/rec/a.wav'\n");
+    }
+
+    #[test]
+    pub fn test_recoverable_copy_errors() {
+        let id3_junk = "[mp3 @ 0x55d0c8a0] Header missing\n\
+            /tmp/stitcher_list_Xb3kQ9.txt: Invalid data found when processing input\n";
+        assert!(is_recoverable_copy_error(id3_junk));
+
+        let fatal = "[concat @ 0x55d0c8a0] Impossible to open 'missing.wav'\n\
+            /tmp/stitcher_list_Xb3kQ9.txt: No such file or directory\n";
+        assert!(!is_recoverable_copy_error(fatal));
+    }
+
+    #[test]
+    pub fn test_error_lines_explain_a_failure() {
+        let stderr = "ffmpeg version 6.0 Copyright (c) 2000-2023 the FFmpeg developers\n\
+            [concat @ 0x55d0c8a0] Impossible to open 'missing.wav'\n\
+            /tmp/stitcher_list_Xb3kQ9.txt: No such file or directory\n\
+            [concat @ 0x55d0c8a0] Impossible to open 'missing.wav'\n";
+        assert_eq!(
+            error_lines(stderr),
+            ["[concat @ 0x55d0c8a0] Impossible to open 'missing.wav'", "/tmp/stitcher_list_Xb3kQ9.txt: No such file or directory"]
+        );
+
+        let unrecognized = "ffmpeg version 6.0\n  libavutil 58.2.100\nout.xyz: something unexpected\nConversion failed!\n";
+        assert_eq!(error_lines(unrecognized), ["ffmpeg version 6.0", "libavutil 58.2.100", "out.xyz: something unexpected"]);
+        assert!(error_lines("").is_empty());
+    }
+
+    #[test]
+    pub fn test_strict_ffmpeg_makes_problem_warnings_fatal() {
+        let stderr = "Input #0, concat, from '/tmp/stitcher_list_Xb3kQ9.txt':\n\
+            [mp3 @ 0x55d0c8a0] Estimating duration from bitrate, this may be inaccurate\n\
+            size=    1024kB time=00:01:05.00 bitrate= 128.0kbits/s\n";
+        assert_eq!(problem_warnings(stderr).len(), 1);
+
+        assert!(check_warnings(stderr, false).is_ok());
+        match check_warnings(stderr, true) {
+            Err(StitcherError::BadInput(e)) => assert!(e.contains("Estimating duration"), "expected the warning, got: {}", e),
+            Err(e) => panic!("expected a bad input error, got: {}", e),
+            Ok(_) => panic!("expected --strict-ffmpeg to fail on a problem warning"),
+        }
+
+        assert!(check_warnings("size=    1024kB time=00:01:05.00\n", true).is_ok());
+    }
+}
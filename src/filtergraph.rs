@@ -0,0 +1,229 @@
+use crate::probe::AudioFormat;
+use crate::split::SilenceSettings;
+
+/// Label of the joined audio stream that `concat_graph` produces, for use with `-map`.
+pub const OUTPUT_LABEL: &str = "[out]";
+
+/// Single-pass loudnorm settings used to bring each clip to a common loudness (-16 LUFS, podcast-ish).
+/// loudnorm works at 192kHz internally, so it's resampled back down to `LOUDNORM_EACH_SAMPLE_RATE` afterwards.
+pub const LOUDNORM_EACH: &str = "loudnorm=I=-16:TP=-1.5:LRA=11,aresample=48000";
+/// Every `--normalize-each` method leaves its inputs at this rate.
+pub const LOUDNORM_EACH_SAMPLE_RATE: u32 = 48000;
+
+/// One piece of the joined output.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    /// The audio of ffmpeg input `index`, run through `filters` (none passes it through untouched).
+    Input { index: usize, filters: Vec<String> },
+    /// Generated silence, this many seconds long.
+    Silence { secs: f64 },
+}
+
+/// Build a `-filter_complex` graph that prepares each segment on its own and then joins them, in order,
+/// with the concat filter. silence is generated in `silence_format`, which has to match the inputs
+/// (after their filters) for concat to accept it. with a `crossfade_secs` above zero, each input fades
+/// into the next one over that long with acrossfade, instead of cutting; generated silence still cuts.
+pub fn concat_graph(segments: &[Segment], silence_format: &AudioFormat, crossfade_secs: f64) -> String {
+    let mut graph = String::new();
+    let mut concat_inputs = String::new();
+
+    for (i, segment) in segments.iter().enumerate() {
+        match segment {
+            Segment::Input { index, filters } => {
+                let chain = match filters.is_empty() {
+                    true => String::from("anull"),
+                    false => filters.join(","),
+                };
+                graph.push_str(&format!("[{}:a]{}[s{}];", index, chain, i));
+            }
+            Segment::Silence { secs } => {
+                graph.push_str(&format!(
+                    "anullsrc=channel_layout={}:sample_rate={},atrim=duration={}[s{}];",
+                    silence_format.channel_layout(),
+                    silence_format.sample_rate,
+                    secs,
+                    i
+                ));
+            }
+        }
+        concat_inputs.push_str(&format!("[s{}]", i));
+    }
+
+    if crossfade_secs > 0.0 {
+        graph.push_str(&crossfade_joins(segments, crossfade_secs));
+        return graph;
+    }
+
+    graph.push_str(&format!(
+        "{}concat=n={}:v=0:a=1{}",
+        concat_inputs,
+        segments.len(),
+        OUTPUT_LABEL
+    ));
+    graph
+}
+
+/// `graph`, from `concat_graph`, with `filters` run over the joined audio before it comes out as `OUTPUT_LABEL`.
+pub fn with_output_filters(graph: &str, filters: &[String]) -> String {
+    if filters.is_empty() {
+        return String::from(graph);
+    }
+
+    let joined = graph.strip_suffix(OUTPUT_LABEL).unwrap_or(graph);
+    format!("{}[joined];[joined]{}{}", joined, filters.join(","), OUTPUT_LABEL)
+}
+
+/// Join the prepared segments `[s0]`, `[s1]`... two at a time, left to right, crossfading wherever
+/// two inputs meet. the last join is labelled `OUTPUT_LABEL`.
+fn crossfade_joins(segments: &[Segment], crossfade_secs: f64) -> String {
+    if segments.len() == 1 {
+        return format!("[s0]anull{}", OUTPUT_LABEL);
+    }
+
+    let mut joins = vec![];
+    let mut joined = String::from("[s0]");
+    for i in 1..segments.len() {
+        let label = match i == segments.len() - 1 {
+            true => String::from(OUTPUT_LABEL),
+            false => format!("[j{}]", i),
+        };
+        let join = match (&segments[i - 1], &segments[i]) {
+            (Segment::Input { .. }, Segment::Input { .. }) => format!("acrossfade=d={}", crossfade_secs),
+            _ => String::from("concat=n=2:v=0:a=1"),
+        };
+        joins.push(format!("{}[s{}]{}{}", joined, i, join, label));
+        joined = label;
+    }
+    joins.join(";")
+}
+
+/// Filters that cut the silence off the start and end of one input: everything below `silence.noise_db`, up to
+/// the first sound that lasts at least `silence.min_secs` (silenceremove's `start_duration`), so a shorter bump
+/// in the room noise is trimmed along with it. silenceremove only trims from the start, so the end is done by
+/// reversing the audio, trimming its start, and reversing it back. pauses in the middle are left alone.
+pub fn trim_silence_filters(silence: &SilenceSettings) -> Vec<String> {
+    let trim_start = format!(
+        "silenceremove=start_periods=1:start_duration={}:start_threshold={}dB",
+        silence.min_secs, silence.noise_db
+    );
+    vec![trim_start.clone(), String::from("areverse"), trim_start, String::from("areverse")]
+}
+
+/// How much shorter crossfading makes the output than its segments put together, in seconds:
+/// one `crossfade_secs` per pair of neighbouring inputs.
+pub fn crossfade_overlap_secs(segments: &[Segment], crossfade_secs: f64) -> f64 {
+    if crossfade_secs <= 0.0 {
+        return 0.0;
+    }
+
+    let fades = segments
+        .windows(2)
+        .filter(|pair| matches!(pair, [Segment::Input { .. }, Segment::Input { .. }]))
+        .count();
+    fades as f64 * crossfade_secs
+}
+
+/// afade filters for the joined audio: in from silence over the first `fade_in_secs`, and out to silence over
+/// the last `fade_out_secs` of the `duration_secs` it lasts. a zero length is no fade. without a duration
+/// there's nowhere to start the fade out, so there's none: afade would start it at the beginning instead.
+pub fn fade_filters(fade_in_secs: f64, fade_out_secs: f64, duration_secs: Option<f64>) -> Vec<String> {
+    let mut filters = vec![];
+    if fade_in_secs > 0.0 {
+        filters.push(format!("afade=t=in:st=0:d={}", fade_in_secs));
+    }
+    if fade_out_secs > 0.0 {
+        if let Some(duration) = duration_secs {
+            filters.push(format!("afade=t=out:st={}:d={}", (duration - fade_out_secs).max(0.0), fade_out_secs));
+        }
+    }
+    filters
+}
+
+/// Total length of the generated silence in `segments`, in seconds.
+pub fn silence_secs(segments: &[Segment]) -> f64 {
+    segments
+        .iter()
+        .map(|segment| match segment {
+            Segment::Silence { secs } => *secs,
+            Segment::Input { .. } => 0.0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_concat_graph() {
+        let segments = vec![
+            Segment::Input { index: 0, filters: vec![String::from("volume=2")] },
+            Segment::Silence { secs: 1.5 },
+            Segment::Input { index: 1, filters: vec![] },
+        ];
+        let format = AudioFormat { sample_rate: 44100, channels: 1 };
+        assert_eq!(
+            concat_graph(&segments, &format, 0.0),
+            "[0:a]volume=2[s0];\
+             anullsrc=channel_layout=mono:sample_rate=44100,atrim=duration=1.5[s1];\
+             [1:a]anull[s2];\
+             [s0][s1][s2]concat=n=3:v=0:a=1[out]"
+        );
+    }
+
+    #[test]
+    pub fn test_output_filters_run_over_the_joined_audio() {
+        let graph = "[0:a]anull[s0];[1:a]anull[s1];[s0][s1]concat=n=2:v=0:a=1[out]";
+        assert_eq!(with_output_filters(graph, &[]), graph);
+        assert_eq!(
+            with_output_filters(graph, &[String::from("loudnorm=I=-16"), String::from("aresample=48000")]),
+            "[0:a]anull[s0];[1:a]anull[s1];[s0][s1]concat=n=2:v=0:a=1[joined];[joined]loudnorm=I=-16,aresample=48000[out]"
+        );
+    }
+
+    #[test]
+    pub fn test_fade_filters() {
+        assert_eq!(
+            fade_filters(2.0, 3.5, Some(60.0)),
+            vec![String::from("afade=t=in:st=0:d=2"), String::from("afade=t=out:st=56.5:d=3.5")]
+        );
+        assert_eq!(fade_filters(0.0, 5.0, Some(3.0)), vec![String::from("afade=t=out:st=0:d=5")]);
+        assert!(fade_filters(0.0, 0.0, None).is_empty());
+        assert!(fade_filters(0.0, 5.0, None).is_empty());
+    }
+
+    #[test]
+    pub fn test_trim_silence_filters() {
+        let filters = trim_silence_filters(&SilenceSettings { noise_db: -50.0, min_secs: 0.5 });
+        assert_eq!(
+            filters.join(","),
+            "silenceremove=start_periods=1:start_duration=0.5:start_threshold=-50dB,areverse,\
+             silenceremove=start_periods=1:start_duration=0.5:start_threshold=-50dB,areverse"
+        );
+    }
+
+    #[test]
+    pub fn test_crossfade_graph() {
+        let segments = vec![
+            Segment::Silence { secs: 1.0 },
+            Segment::Input { index: 0, filters: vec![] },
+            Segment::Input { index: 1, filters: vec![] },
+            Segment::Input { index: 2, filters: vec![] },
+        ];
+        let graph = concat_graph(&segments, &AudioFormat::default(), 2.5);
+        assert!(
+            graph.ends_with(
+                "[s0][s1]concat=n=2:v=0:a=1[j1];\
+                 [j1][s2]acrossfade=d=2.5[j2];\
+                 [j2][s3]acrossfade=d=2.5[out]"
+            ),
+            "expected the inputs to crossfade and the silence to cut, got {}",
+            graph
+        );
+        assert_eq!(crossfade_overlap_secs(&segments, 2.5), 5.0);
+        assert_eq!(crossfade_overlap_secs(&segments, 0.0), 0.0);
+
+        let single = [Segment::Input { index: 0, filters: vec![] }];
+        assert_eq!(concat_graph(&single, &AudioFormat::default(), 2.5), "[0:a]anull[s0];[s0]anull[out]");
+    }
+}
@@ -0,0 +1,29 @@
+use std::path::Path;
+
+use super::probe::{AudioFormat, Probe, ProbeError, StreamParams};
+
+/// A stand-in `Probe` for tests, shared by the library's and the CLI's, that finds the same for every file:
+/// `duration` seconds long, with a PCM audio stream in `format`. `None` for either is a probe that reads the file
+/// but can't find it, like ffprobe on a stream with no length in its header, or on a file with no audio.
+pub struct FixedProbe {
+    pub duration: Option<f64>,
+    pub format: Option<AudioFormat>,
+}
+
+impl Probe for FixedProbe {
+    fn duration(&self, file: &Path) -> Result<f64, ProbeError> {
+        self.duration
+            .ok_or_else(|| ProbeError::Failed(format!("ffprobe reported no duration for {}", file.to_string_lossy())))
+    }
+
+    fn audio_format(&self, file: &Path) -> Result<AudioFormat, ProbeError> {
+        self.stream_params(file).map(|params| params.format)
+    }
+
+    fn stream_params(&self, file: &Path) -> Result<StreamParams, ProbeError> {
+        match self.format {
+            Some(format) => Ok(StreamParams { codec: String::from("pcm_s16le"), format }),
+            None => Err(ProbeError::Failed(format!("ffprobe found no audio stream in {}", file.to_string_lossy()))),
+        }
+    }
+}
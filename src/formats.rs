@@ -0,0 +1,126 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::codec;
+
+/// The input extensions stitcher looks for out of the box. all of them stream copy cleanly, as long as
+/// every input shares the same codec and parameters.
+pub const BUILTIN_INPUTS: &[&str] = &["aac", "flac", "m4a", "mp3", "ogg", "opus", "wav"];
+
+/// The input extensions stitcher looks for in video mode, instead of `BUILTIN_INPUTS`.
+pub const BUILTIN_VIDEO_INPUTS: &[&str] = &["mkv", "mov", "mp4"];
+
+/// Output extension when the inputs don't all share one.
+pub const FALLBACK_OUTPUT_EXTENSION: &str = "wav";
+
+/// `FALLBACK_OUTPUT_EXTENSION` for video mode. Matroska holds whatever the inputs carry.
+pub const FALLBACK_VIDEO_OUTPUT_EXTENSION: &str = "mkv";
+
+/// What the config file says about one extension.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Format {
+    /// Audio encoder to use when re-encoding into this extension.
+    pub codec: Option<String>,
+    /// Whether inputs of this type join with a stream copy. a run that includes one that doesn't re-encodes.
+    #[serde(default = "copy_safe_default")]
+    pub copy_safe: bool,
+}
+
+fn copy_safe_default() -> bool {
+    true
+}
+
+/// The extensions stitcher picks up as inputs and the codecs it encodes them with: the built-in
+/// ones, plus any from the config file's `[extensions]` section (which win over the built-ins).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FormatTable {
+    configured: BTreeMap<String, Format>,
+    video: bool,
+}
+
+impl FormatTable {
+    pub fn with_configured(configured: BTreeMap<String, Format>) -> Self {
+        FormatTable { configured, video: false }
+    }
+
+    /// Look for video containers (`BUILTIN_VIDEO_INPUTS`) instead of audio files.
+    pub fn with_video(self, video: bool) -> Self {
+        FormatTable { video, ..self }
+    }
+
+    /// Whether the inputs are video containers, with their video streams stitched along with the audio.
+    pub fn is_video(&self) -> bool {
+        self.video
+    }
+
+    /// The built-in input extensions, for the kind of media being stitched.
+    pub fn builtin_inputs(&self) -> &'static [&'static str] {
+        match self.video {
+            true => BUILTIN_VIDEO_INPUTS,
+            false => BUILTIN_INPUTS,
+        }
+    }
+
+    /// Whether files with this extension are picked up as inputs.
+    pub fn is_supported(&self, ext: &str) -> bool {
+        self.builtin_inputs().iter().any(|x| x.eq_ignore_ascii_case(ext)) || self.configured(ext).is_some()
+    }
+
+    /// Default audio codec when re-encoding into this extension.
+    pub fn default_codec(&self, ext: &str) -> Option<&str> {
+        self.configured(ext)
+            .and_then(|format| format.codec.as_deref())
+            .or_else(|| codec::default_audio_codec(ext))
+    }
+
+    /// Whether inputs with this extension can be stream copied.
+    pub fn is_copy_safe(&self, ext: &str) -> bool {
+        self.configured(ext).is_none_or(|format| format.copy_safe)
+    }
+
+    /// What the config file says about this extension, whatever its case.
+    fn configured(&self, ext: &str) -> Option<&Format> {
+        self.configured.iter().find(|(x, _)| x.eq_ignore_ascii_case(ext)).map(|(_, format)| format)
+    }
+}
+
+/// The extension to give an output that wasn't named: the (lowercased) one every input shares, so a
+/// stream copy stays in the inputs' own container, or `fallback` when they're mixed.
+pub fn default_output_extension(inputs: &[PathBuf], fallback: &str) -> String {
+    let mut extensions = inputs
+        .iter()
+        .map(|input| input.extension().map(|x| x.to_string_lossy().to_lowercase()));
+
+    match extensions.next() {
+        Some(Some(first)) if extensions.all(|ext| ext.as_deref() == Some(first.as_str())) => first,
+        _ => String::from(fallback),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_default_output_extension_follows_the_inputs() {
+        let inputs = |names: &[&str]| names.iter().map(PathBuf::from).collect::<Vec<_>>();
+
+        let fallback = FALLBACK_OUTPUT_EXTENSION;
+
+        assert_eq!(default_output_extension(&inputs(&["a.opus", "b.OPUS"]), fallback), "opus");
+        assert_eq!(default_output_extension(&inputs(&["a.m4a", "b.mp3"]), fallback), fallback);
+        assert_eq!(default_output_extension(&inputs(&[]), fallback), fallback);
+    }
+
+    #[test]
+    pub fn test_video_mode_swaps_the_builtin_inputs() {
+        let audio = FormatTable::default();
+        assert!(audio.is_supported("wav") && audio.is_supported("flac") && audio.is_supported("FLAC"));
+        assert!(!audio.is_supported("mp4"));
+
+        let video = FormatTable::default().with_video(true);
+        assert!(video.is_supported("mp4") && video.is_supported("mov") && video.is_supported("mkv"));
+        assert!(!video.is_supported("wav"), "expected loose audio files to be left out of a video stitch");
+    }
+}
@@ -0,0 +1,128 @@
+use std::path::{Path, PathBuf};
+
+use crate::{error::StitcherError, options::StitchOptions};
+
+/// How long to wait for a message from the pipeline between checks for Ctrl+C, in milliseconds.
+const INTERRUPT_CHECK_MS: u64 = 100;
+
+/// The GStreamer elements that encode (and, where the encoder doesn't, mux) each output extension the
+/// GStreamer backend can write.
+const ENCODERS: &[(&str, &str)] = &[
+    ("wav", "wavenc"),
+    ("flac", "flacenc"),
+    ("mp3", "lamemp3enc ! xingmux"),
+    ("ogg", "vorbisenc ! oggmux"),
+    ("opus", "opusenc ! oggmux"),
+    ("m4a", "avenc_aac ! mp4mux"),
+];
+
+/// The encoding end of the pipeline for an output with extension `ext`, if the GStreamer backend can write it.
+pub fn encoder(ext: &str) -> Option<&'static str> {
+    ENCODERS.iter().find(|(x, _)| x.eq_ignore_ascii_case(ext)).map(|(_, encoder)| *encoder)
+}
+
+/// A `gst-launch-1.0` pipeline that decodes each of `files`, plays them one after the other through a
+/// `concat`, and encodes the result into `output`. each input is linked to its own numbered concat pad,
+/// so they play in order however long their decoders take to start.
+pub fn pipeline_description(files: &[PathBuf], output: &Path) -> Result<String, String> {
+    let ext = crate::options::output_extension(output).unwrap_or_default();
+    let encoder = encoder(&ext).ok_or_else(|| format!("the GStreamer backend can't write .{} files", ext))?;
+
+    let mut description = format!(
+        "concat name=c ! audioconvert ! audioresample ! {} ! filesink location={}",
+        encoder,
+        location(output)?
+    );
+    for (i, file) in files.iter().enumerate() {
+        description.push_str(&format!(
+            " filesrc location={} ! decodebin ! audioconvert ! audioresample ! c.sink_{}",
+            location(file)?,
+            i
+        ));
+    }
+    Ok(description)
+}
+
+/// `path` as a quoted value in a pipeline description.
+fn location(path: &Path) -> Result<String, String> {
+    let path = path.to_str().ok_or_else(|| format!("{} isn't valid UTF-8", path.to_string_lossy()))?;
+    Ok(format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\"")))
+}
+
+/// Join `files`, in order, into `options.output` by running the `pipeline_description` pipeline in-process.
+/// the audio is decoded and encoded again, with the output extension's encoder from `ENCODERS`. returns
+/// the path of the joined file.
+pub fn stitch(files: &[PathBuf], options: &StitchOptions) -> Result<PathBuf, StitcherError> {
+    use ::gstreamer as gst;
+    use gst::prelude::*;
+
+    if files.is_empty() {
+        return Err(StitcherError::NoInputs(String::from("found no files!")));
+    }
+    if !options.overwrite && options.output.exists() {
+        return Err(StitcherError::OutputExists(options.output.clone()));
+    }
+    let description = pipeline_description(files, &options.output)?;
+    gst::init().map_err(|e| StitcherError::Other(format!("failed to initialize GStreamer: {}", e)))?;
+
+    log::debug!("running the pipeline: {}", description);
+    let pipeline = gst::parse::launch(&description)
+        .map_err(|e| StitcherError::Other(format!("failed to build the GStreamer pipeline: {}", e)))?;
+    let result = run(&pipeline);
+    let _ = pipeline.set_state(gst::State::Null);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&options.output);
+    }
+    result.map(|()| options.output.clone())
+}
+
+/// Play `pipeline` until it ends, or fails.
+fn run(pipeline: &::gstreamer::Element) -> Result<(), StitcherError> {
+    use ::gstreamer as gst;
+    use gst::prelude::*;
+
+    let bus = pipeline
+        .bus()
+        .ok_or_else(|| StitcherError::Other(String::from("the GStreamer pipeline has no bus")))?;
+    pipeline
+        .set_state(gst::State::Playing)
+        .map_err(|e| StitcherError::Other(format!("failed to start the GStreamer pipeline: {}", e)))?;
+
+    // the pipeline runs in-process, so Ctrl+C has no child to kill: the bus is polled, and the flag checked in
+    // between, so `stitch` can stop the pipeline and remove the partial output
+    //
+    loop {
+        if crate::interrupt::requested() {
+            return Err(StitcherError::Interrupted);
+        }
+        let Some(message) = bus.timed_pop(gst::ClockTime::from_mseconds(INTERRUPT_CHECK_MS)) else {
+            continue;
+        };
+        match message.view() {
+            gst::MessageView::Eos(..) => return Ok(()),
+            gst::MessageView::Error(error) => {
+                let debug = error.debug().map(|x| format!(" ({})", x)).unwrap_or_default();
+                return Err(StitcherError::Other(format!("GStreamer failed: {}{}", error.error(), debug)));
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_pipeline_links_inputs_in_order() {
+        let files = [PathBuf::from("a.mp3"), PathBuf::from("takes/\"b\".wav")];
+        let description = pipeline_description(&files, Path::new("out.flac")).expect("expected a pipeline");
+        assert_eq!(
+            description,
+            "concat name=c ! audioconvert ! audioresample ! flacenc ! filesink location=\"out.flac\" \
+             filesrc location=\"a.mp3\" ! decodebin ! audioconvert ! audioresample ! c.sink_0 \
+             filesrc location=\"takes/\\\"b\\\".wav\" ! decodebin ! audioconvert ! audioresample ! c.sink_1"
+        );
+        assert!(pipeline_description(&files, Path::new("out.mka")).is_err());
+    }
+}
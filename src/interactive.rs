@@ -0,0 +1,162 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, ClearType},
+};
+
+use stitcher::error::StitcherError;
+
+/// Key help shown under the list.
+const HELP: &str = "↑/↓ select   shift+↑/↓ or J/K move   d delete   enter stitch   q cancel";
+
+/// The list being reordered, with the selected row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reorder {
+    pub files: Vec<PathBuf>,
+    pub selected: usize,
+}
+
+/// What a key press leaves the reorder doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Continue,
+    Confirm,
+    Cancel,
+}
+
+impl Reorder {
+    pub fn new(files: Vec<PathBuf>) -> Self {
+        Reorder { files, selected: 0 }
+    }
+
+    /// Apply one key press.
+    pub fn handle(&mut self, key: KeyEvent) -> Step {
+        let shifted = key.modifiers.contains(KeyModifiers::SHIFT);
+        match key.code {
+            KeyCode::Up if shifted => self.move_selected(-1),
+            KeyCode::Down if shifted => self.move_selected(1),
+            KeyCode::Char('K') => self.move_selected(-1),
+            KeyCode::Char('J') => self.move_selected(1),
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.selected = (self.selected + 1).min(self.last()),
+            KeyCode::Char('d') | KeyCode::Delete | KeyCode::Backspace => self.delete_selected(),
+            KeyCode::Enter => return Step::Confirm,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Step::Cancel,
+            KeyCode::Char('q') | KeyCode::Esc => return Step::Cancel,
+            _ => (),
+        }
+        Step::Continue
+    }
+
+    fn last(&self) -> usize {
+        self.files.len().saturating_sub(1)
+    }
+
+    /// Swap the selected file with its neighbour `by` rows away, keeping it selected.
+    fn move_selected(&mut self, by: isize) {
+        let Some(to) = self.selected.checked_add_signed(by).filter(|to| *to < self.files.len()) else {
+            return;
+        };
+        self.files.swap(self.selected, to);
+        self.selected = to;
+    }
+
+    fn delete_selected(&mut self) {
+        if self.selected < self.files.len() {
+            self.files.remove(self.selected);
+            self.selected = self.selected.min(self.last());
+        }
+    }
+}
+
+/// Show `files` in a full-screen list on stderr to reorder and prune, and return the list as it was
+/// confirmed, or `None` if it was cancelled.
+pub fn reorder(files: Vec<PathBuf>) -> Result<Option<Vec<PathBuf>>, StitcherError> {
+    let mut stderr = std::io::stderr();
+    terminal::enable_raw_mode().map_err(|e| StitcherError::io("setting up the terminal", e))?;
+    let _ = execute!(stderr, terminal::EnterAlternateScreen, cursor::Hide);
+
+    // the terminal goes back to normal however the loop ends
+    //
+    let result = run(Reorder::new(files), &mut stderr);
+
+    let _ = execute!(stderr, cursor::Show, terminal::LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+    result
+}
+
+fn run(mut state: Reorder, out: &mut impl Write) -> Result<Option<Vec<PathBuf>>, StitcherError> {
+    loop {
+        draw(&state, out).map_err(|e| StitcherError::io("drawing the file list", e))?;
+
+        let event = event::read().map_err(|e| StitcherError::io("reading a key press", e))?;
+        let Event::Key(key) = event else {
+            continue;
+        };
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+
+        match state.handle(key) {
+            Step::Continue => (),
+            Step::Confirm => return Ok(Some(state.files)),
+            Step::Cancel => return Ok(None),
+        }
+    }
+}
+
+fn draw(state: &Reorder, out: &mut impl Write) -> std::io::Result<()> {
+    let (_, rows) = terminal::size().unwrap_or((80, 24));
+
+    // a title line above and the help below, and the list scrolled to keep the selection in view
+    //
+    let visible = (rows as usize).saturating_sub(3).max(1);
+    let first = state.selected.saturating_sub(visible - 1);
+
+    queue!(out, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+    queue!(out, Print(format!("{} files, in stitching order\r\n", state.files.len())))?;
+    for (i, file) in state.files.iter().enumerate().skip(first).take(visible) {
+        let line = format!("{:>4}  {}\r\n", i + 1, file.to_string_lossy());
+        match i == state.selected {
+            true => queue!(out, SetAttribute(Attribute::Reverse), Print(line), SetAttribute(Attribute::Reset))?,
+            false => queue!(out, Print(line))?,
+        }
+    }
+    queue!(out, cursor::MoveTo(0, rows.saturating_sub(1)), Print(HELP))?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn press(state: &mut Reorder, code: KeyCode, modifiers: KeyModifiers) -> Step {
+        state.handle(KeyEvent::new(code, modifiers))
+    }
+
+    #[test]
+    pub fn test_reorder_keys() {
+        let mut state = Reorder::new(["a.wav", "b.wav", "c.wav"].map(PathBuf::from).to_vec());
+
+        assert_eq!(press(&mut state, KeyCode::Up, KeyModifiers::SHIFT), Step::Continue);
+        assert_eq!(state.files, ["a.wav", "b.wav", "c.wav"].map(PathBuf::from));
+
+        press(&mut state, KeyCode::Down, KeyModifiers::SHIFT);
+        press(&mut state, KeyCode::Char('J'), KeyModifiers::SHIFT);
+        assert_eq!(state.files, ["b.wav", "c.wav", "a.wav"].map(PathBuf::from));
+        assert_eq!(state.selected, 2);
+
+        press(&mut state, KeyCode::Char('k'), KeyModifiers::NONE);
+        press(&mut state, KeyCode::Char('d'), KeyModifiers::NONE);
+        assert_eq!(state.files, ["b.wav", "a.wav"].map(PathBuf::from));
+        assert_eq!(state.selected, 1);
+
+        assert_eq!(press(&mut state, KeyCode::Enter, KeyModifiers::NONE), Step::Confirm);
+        assert_eq!(press(&mut state, KeyCode::Esc, KeyModifiers::NONE), Step::Cancel);
+    }
+}
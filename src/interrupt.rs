@@ -0,0 +1,84 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use shared_child::SharedChild;
+
+/// Process exit status after Ctrl+C: 128 plus SIGINT's number, as shells report it.
+pub const EXIT_INTERRUPTED: u8 = 130;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Every ffmpeg (or other child) that's running, so the handler can stop them.
+static RUNNING: Mutex<Vec<Arc<SharedChild>>> = Mutex::new(vec![]);
+
+/// Handle Ctrl+C (and SIGTERM): note that it was pressed, and kill whatever child is running. the process
+/// isn't exited here, so nothing skips its cleanup: the stitch waiting on a child fails with
+/// `StitcherError::Interrupted`, and the work done in-process (like `wav::join`) checks `requested` as it
+/// goes. either way the partial output and temp files are removed on the way out, and `main` exits with
+/// `EXIT_INTERRUPTED`.
+pub fn install() -> Result<(), String> {
+    ctrlc::set_handler(|| {
+        REQUESTED.store(true, Ordering::SeqCst);
+        let running = RUNNING.lock().map(|x| x.clone()).unwrap_or_default();
+        for child in running {
+            let _ = child.kill();
+        }
+    })
+    .map_err(|e| format!("failed to install the Ctrl-C handler: {:?}", e))
+}
+
+/// Whether Ctrl+C has been pressed.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// The flag `install`'s handler sets, for loops (like `watch::watch`) that check it themselves.
+pub fn flag() -> &'static AtomicBool {
+    &REQUESTED
+}
+
+/// Keeps `child` where the handler can kill it, until this drops.
+pub(crate) struct Tracked(Arc<SharedChild>);
+
+/// Track `child` until the returned guard drops. if Ctrl+C came in just before it started, it's killed
+/// straight away.
+pub(crate) fn track(child: Arc<SharedChild>) -> Tracked {
+    if let Ok(mut running) = RUNNING.lock() {
+        running.push(child.clone());
+    }
+    if requested() {
+        let _ = child.kill();
+    }
+    Tracked(child)
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        if let Ok(mut running) = RUNNING.lock() {
+            running.retain(|child| !Arc::ptr_eq(child, &self.0));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_children_are_tracked_while_running() {
+        let mut command = std::process::Command::new("sleep");
+        command.arg("5");
+        let Ok(child) = SharedChild::spawn(&mut command).map(Arc::new) else {
+            return;
+        };
+        let is_tracked = |child: &Arc<SharedChild>| RUNNING.lock().expect("a lock").iter().any(|x| Arc::ptr_eq(x, child));
+
+        let tracked = track(child.clone());
+        assert!(is_tracked(&child));
+        drop(tracked);
+        assert!(!is_tracked(&child));
+
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
@@ -0,0 +1,2074 @@
+//! Stitch audio files together with ffmpeg.
+//!
+//! The `stitcher` binary is a thin CLI over this crate. to embed the stitching in another tool, find
+//! ffmpeg with a `Stitcher`, describe the work as a `StitchJob`, and run it:
+//!
+//! ```no_run
+//! use stitcher::{options::StitchOptions, StitchJob, Stitcher};
+//!
+//! let stitcher = Stitcher::discover()?;
+//! let options = StitchOptions {
+//!     output: "joined.wav".into(),
+//!     ..Default::default()
+//! };
+//! let job = StitchJob::from_dir("./sounds", &Default::default(), options);
+//! let stitched = stitcher.stitch(&job)?;
+//! # Ok::<(), stitcher::error::StitcherError>(())
+//! ```
+//!
+//! the lower-level pieces (`look_for_files`, `find_valid_ffmpeg_binary`, `stitch_files`) are public too.
+
+pub mod chapters;
+pub mod codec;
+pub mod config;
+pub mod discovery;
+pub mod error;
+pub mod fetch;
+pub mod ffmpeg;
+pub mod filtergraph;
+#[cfg(test)]
+mod fixed_probe;
+pub mod formats;
+#[cfg(feature = "gstreamer")]
+pub mod gstreamer;
+pub mod interrupt;
+#[cfg(feature = "libav")]
+pub mod libav;
+pub mod normalize;
+pub mod options;
+pub mod order_report;
+pub mod playlist;
+pub mod probe;
+pub mod progress;
+pub mod selection;
+pub mod sort;
+pub mod split;
+#[cfg(feature = "symphonia")]
+pub mod symphonia_probe;
+pub mod tags;
+pub mod watch;
+pub mod wav;
+pub mod waveform;
+
+use std::{
+    ffi::OsString,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use error::StitcherError;
+use options::{Backend, StitchOptions};
+
+/// Stitches files with one ffmpeg binary, or, without one, joins WAVs itself.
+#[derive(Debug, Clone)]
+pub struct Stitcher {
+    /// The ffmpeg binary, or the paths that were checked for one without finding it.
+    ffmpeg_bin_path: Result<PathBuf, Vec<PathBuf>>,
+}
+
+impl Stitcher {
+    /// Use the ffmpeg binary at `ffmpeg_bin_path`. it isn't checked until a stitch runs.
+    pub fn new(ffmpeg_bin_path: impl Into<PathBuf>) -> Self {
+        Stitcher { ffmpeg_bin_path: Ok(ffmpeg_bin_path.into()) }
+    }
+
+    /// A stitcher for when no ffmpeg was found at any of `checked`. it can only join WAVs that need nothing
+    /// but copying (see `wav::can_join`); anything else fails with `StitcherError::FfmpegNotFound`.
+    pub fn without_ffmpeg(checked: Vec<PathBuf>) -> Self {
+        Stitcher { ffmpeg_bin_path: Err(checked) }
+    }
+
+    /// Find ffmpeg where the CLI looks for it, per `default_ffmpeg_paths`.
+    pub fn discover() -> Result<Self, StitcherError> {
+        find_valid_ffmpeg_binary(default_ffmpeg_paths()).map(Stitcher::new)
+    }
+
+    /// The ffmpeg binary, or `StitcherError::FfmpegNotFound` for a stitcher made `without_ffmpeg`.
+    pub fn ffmpeg_bin_path(&self) -> Result<&Path, StitcherError> {
+        match &self.ffmpeg_bin_path {
+            Ok(path) => Ok(path),
+            Err(checked) => Err(StitcherError::FfmpegNotFound(checked.clone())),
+        }
+    }
+
+    /// Check `job`'s options, then stitch its files. returns the stitched file, and which of the files are in it.
+    pub fn stitch(&self, job: &StitchJob) -> Result<Stitched, StitcherError> {
+        if interrupt::requested() {
+            return Err(StitcherError::Interrupted);
+        }
+        job.options.check()?;
+        if job.files.is_empty() {
+            return Err(StitcherError::NoInputs(String::from("found no files!")));
+        }
+        // only the CLI backend probes the inputs, and leaves out the ones it can't read: the others stitch them all.
+        // `check` has made sure any backend but the CLI is built in
+        //
+        let everything = |output| Stitched { output, inputs: job.files.clone() };
+        match job.options.backend {
+            Backend::Cli => (),
+            #[cfg(feature = "libav")]
+            Backend::Libav => return libav::stitch(&job.files, &job.options).map(everything),
+            #[cfg(not(feature = "libav"))]
+            Backend::Libav => unreachable!("validated out"),
+            #[cfg(feature = "gstreamer")]
+            Backend::Gstreamer => return gstreamer::stitch(&job.files, &job.options).map(everything),
+            #[cfg(not(feature = "gstreamer"))]
+            Backend::Gstreamer => unreachable!("validated out"),
+        }
+        match self.ffmpeg_bin_path() {
+            Ok(ffmpeg_bin_path) => stitch_files(ffmpeg_bin_path.to_path_buf(), job.files.clone(), &job.options),
+            // joining the WAVs directly checks nothing but their headers
+            //
+            Err(e) if job.options.strict => {
+                log::warn!("--strict checks the inputs with ffprobe, so it can't join WAVs without ffmpeg");
+                Err(e)
+            }
+            Err(_) if wav::can_join(&job.files, &job.options) => {
+                log::info!("no ffmpeg found, so joining the WAVs directly");
+                wav::join(&job.files, &job.options.output, job.options.overwrite).map(everything)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// What a stitch made: the output, and the inputs that went into it, in order. an input that couldn't be read is
+/// skipped with a warning rather than failing the stitch, so these can be fewer than the job's files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stitched {
+    pub output: PathBuf,
+    pub inputs: Vec<PathBuf>,
+}
+
+/// One stitch: the files to join, in order, and how.
+#[derive(Debug, Default, Clone)]
+pub struct StitchJob {
+    pub files: Vec<PathBuf>,
+    pub options: StitchOptions,
+}
+
+impl StitchJob {
+    pub fn new(files: Vec<PathBuf>, options: StitchOptions) -> Self {
+        StitchJob { files, options }
+    }
+
+    /// A job for every supported file in `dir` (per `options.formats`), in the order the walk finds them.
+    pub fn from_dir(dir: impl Into<PathBuf>, walk_options: &discovery::WalkOptions, options: StitchOptions) -> Self {
+        let files = look_for_files(dir.into(), walk_options, &options.formats);
+        StitchJob { files, options }
+    }
+}
+
+/// Where to look for ffmpeg, in order.
+pub fn default_ffmpeg_paths() -> Vec<PathBuf> {
+    ffmpeg_paths(None)
+}
+
+/// Where to look for ffmpeg, in order: `$STITCHER_FFMPEG`, then `configured` (e.g. from the config file),
+/// then `ffmpeg` on `$PATH`, then /bin/ffmpeg (`%ProgramFiles%\ffmpeg\bin\ffmpeg.exe` on Windows), then
+/// ./vendor/ffmpeg/ffmpeg (`ffmpeg.exe` on Windows).
+pub fn ffmpeg_paths(configured: Option<PathBuf>) -> Vec<PathBuf> {
+    let mut ffmpeg_paths = vec![];
+    if let Some(from_env) = std::env::var_os("STITCHER_FFMPEG") {
+        ffmpeg_paths.push(PathBuf::from(from_env));
+    }
+    ffmpeg_paths.extend(configured);
+    ffmpeg_paths.extend(find_on_path("ffmpeg"));
+    ffmpeg_paths.extend(system_ffmpeg_path());
+    ffmpeg_paths.push(Path::new(fetch::VENDOR_DIR).join(exe_name("ffmpeg")));
+    ffmpeg_paths
+}
+
+/// The file name of the program `name` on this platform: `name.exe` on Windows, just `name` elsewhere.
+pub fn exe_name(name: &str) -> String {
+    format!("{}{}", name, std::env::consts::EXE_SUFFIX)
+}
+
+/// Where ffmpeg is installed system-wide, when it isn't on `$PATH`.
+#[cfg(not(windows))]
+fn system_ffmpeg_path() -> Option<PathBuf> {
+    Some(PathBuf::from("/bin/ffmpeg"))
+}
+
+#[cfg(windows)]
+fn system_ffmpeg_path() -> Option<PathBuf> {
+    let program_files = std::env::var_os("ProgramFiles")?;
+    Some(PathBuf::from(program_files).join("ffmpeg").join("bin").join("ffmpeg.exe"))
+}
+
+/// The first file called `name` in one of the `$PATH` directories, like `which`.
+pub fn find_on_path(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    find_in_dirs(name, std::env::split_paths(&path))
+}
+
+/// The first file called `name` (or `name.exe`, on Windows) in one of `dirs`.
+fn find_in_dirs(name: &str, dirs: impl IntoIterator<Item = PathBuf>) -> Option<PathBuf> {
+    dirs.into_iter()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .flat_map(|dir| [dir.join(name), dir.join(name).with_extension(std::env::consts::EXE_EXTENSION)])
+        .find(|candidate| candidate.is_file())
+}
+
+/// Whether `output` exists and was modified no earlier than every one of `inputs`.
+pub fn is_up_to_date(output: &Path, inputs: &[PathBuf]) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|x| x.modified()).ok();
+    let Some(output_modified) = modified(output) else {
+        return false;
+    };
+
+    inputs
+        .iter()
+        .all(|input| modified(input).is_some_and(|input_modified| input_modified <= output_modified))
+}
+
+/// The first of `paths_to_check` that runs as ffmpeg.
+pub fn find_valid_ffmpeg_binary(
+    paths_to_check: Vec<std::path::PathBuf>,
+) -> Result<std::path::PathBuf, StitcherError> {
+    // try to run a help command, return Ok on first 0 status code
+    //
+    for path in &paths_to_check {
+        let output = Command::new(path).arg("-h").output();
+        if output.is_ok_and(|x| x.status.success()) {
+            return Ok(path.to_path_buf());
+        }
+    }
+
+    Err(StitcherError::FfmpegNotFound(paths_to_check))
+}
+
+/// Find the supported files in `in_path`, walking it per `walk_options`. if `in_path` is an M3U playlist,
+/// its entries are the files instead, in playlist order; entries that don't exist are skipped with a warning.
+/// any other file is just itself, if it's supported.
+pub fn look_for_files(
+    in_path: std::path::PathBuf,
+    walk_options: &discovery::WalkOptions,
+    formats: &formats::FormatTable,
+) -> Vec<std::path::PathBuf> {
+    find_files(in_path, walk_options, formats).unwrap_or_default()
+}
+
+/// `look_for_files`, for `walk_options.strict`: anything it would skip or come up empty on (a directory or
+/// playlist that can't be read, a playlist entry that doesn't exist) is an error instead. without `strict`
+/// this never fails.
+pub fn find_files(
+    in_path: std::path::PathBuf,
+    walk_options: &discovery::WalkOptions,
+    formats: &formats::FormatTable,
+) -> Result<Vec<std::path::PathBuf>, StitcherError> {
+    let found = match (playlist::is_playlist(&in_path), in_path.is_file()) {
+        (true, true) => playlist_files(&in_path, walk_options.strict),
+        (false, true) => Ok(vec![in_path]),
+        (_, false) => discovery::walk(&discovery::RealFs, &in_path, walk_options)
+            .map_err(|e| format!("couldn't read {}: {}", in_path.to_string_lossy(), e)),
+    };
+
+    match found {
+        Err(e) if walk_options.strict => Err(StitcherError::NoInputs(e)),
+        Err(_) => Ok(vec![]),
+        Ok(result) => Ok(result
+            .into_iter()
+            .filter_map(|path| filter_supported_extensions(path, formats))
+            .collect()),
+    }
+}
+
+/// The entries of the playlist at `path` that exist. a missing one is skipped with a warning, or is an error
+/// with `strict`; so is a playlist that can't be read at all.
+fn playlist_files(path: &Path, strict: bool) -> Result<Vec<PathBuf>, String> {
+    let entries = match playlist::load(path) {
+        Ok(entries) => entries,
+        Err(e) if strict => return Err(e),
+        Err(e) => {
+            log::warn!("{}", e);
+            return Ok(vec![]);
+        }
+    };
+
+    let mut found = vec![];
+    for entry in entries {
+        match (entry.is_file(), strict) {
+            (true, _) => found.push(entry),
+            (false, true) => return Err(format!("playlist entry {} doesn't exist", entry.to_string_lossy())),
+            (false, false) => log::warn!("skipping missing playlist entry {}", entry.to_string_lossy()),
+        }
+    }
+    Ok(found)
+}
+
+/// Keep only the files whose file name exactly matches one of `names`, in the order of `names`.
+/// names that match nothing are a warning, or an error when `strict` is set.
+pub fn select_only(files: Vec<PathBuf>, names: &[String], strict: bool) -> Result<Vec<PathBuf>, StitcherError> {
+    let mut selected = vec![];
+    let mut missing = vec![];
+
+    for name in names {
+        match files.iter().find(|file| file.file_name().is_some_and(|x| x == name.as_str())) {
+            Some(file) => selected.push(file.clone()),
+            None => missing.push(name.as_str()),
+        }
+    }
+
+    if !missing.is_empty() {
+        let message = format!("--only names matched no files: {}", missing.join(", "));
+        match strict {
+            true => return Err(StitcherError::NoInputs(message)),
+            false => log::warn!("{}", message),
+        }
+    }
+
+    Ok(selected)
+}
+
+/// A file `dedupe` dropped, because it has the same contents as `same_as`, which was kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Duplicate {
+    pub file: PathBuf,
+    pub same_as: PathBuf,
+}
+
+/// Drop the files whose contents are exactly those of a file before them, by SHA-256, keeping the first
+/// of each. returns the files kept, in order, and the ones dropped.
+pub fn dedupe(files: Vec<PathBuf>) -> Result<(Vec<PathBuf>, Vec<Duplicate>), StitcherError> {
+    let mut first_with_hash = std::collections::HashMap::new();
+    let mut kept = vec![];
+    let mut skipped = vec![];
+
+    for file in files {
+        if interrupt::requested() {
+            return Err(StitcherError::Interrupted);
+        }
+        let hash = order_report::sha256_file(&file)?;
+        match first_with_hash.get(&hash) {
+            Some(first) => skipped.push(Duplicate { file, same_as: PathBuf::clone(first) }),
+            None => {
+                first_with_hash.insert(hash, file.clone());
+                kept.push(file);
+            }
+        }
+    }
+
+    Ok((kept, skipped))
+}
+
+fn filter_supported_extensions(path: PathBuf, formats: &formats::FormatTable) -> Option<PathBuf> {
+    match formats.is_supported(path.extension()?.to_str()?) {
+        true => Some(path),
+        false => None,
+    }
+}
+
+/// Join `files`, in order, into `options.output`. the inputs are probed with whatever `probe::find_probe`
+/// finds; the checks that need durations are skipped when it finds nothing. returns the stitched file, and which
+/// of `files` are in it.
+pub fn stitch_files(
+    ffmpeg_bin_path: std::path::PathBuf,
+    files: Vec<std::path::PathBuf>,
+    options: &StitchOptions,
+) -> Result<Stitched, StitcherError> {
+    let probe = probe::find_probe(&ffmpeg_bin_path, options.probe_timeout, options.input_format.as_deref());
+    stitch_files_with_probe(ffmpeg_bin_path, probe.as_deref(), files, options)
+}
+
+/// `stitch_files`, with the prober passed in. `None` means there's nothing to probe with, so every
+/// check that needs durations is skipped.
+pub fn stitch_files_with_probe(
+    ffmpeg_bin_path: std::path::PathBuf,
+    probe: Option<&dyn probe::Probe>,
+    files: Vec<std::path::PathBuf>,
+    options: &StitchOptions,
+) -> Result<Stitched, StitcherError> {
+    if !options.overwrite && !options.writes_to_stdout() && options.output.exists() {
+        return Err(StitcherError::OutputExists(options.output.clone()));
+    }
+
+    // --strict is a promise that every input was checked before ffmpeg runs, which takes something to check with
+    //
+    if options.strict && probe.is_none() {
+        return Err(StitcherError::FfprobeNotFound(String::from(
+            "--strict needs ffprobe to check the inputs before stitching, but it wasn't found",
+        )));
+    }
+
+    // the total input duration drives percent complete, and catches a batch of empty/corrupt files
+    // before ffmpeg turns it into an empty output
+    //
+    let probed = match probe {
+        Some(probe) => probe::probe_inputs(probe, files, options.strict),
+        None => Ok(probe::ProbedInputs { files, durations: None, skipped: vec![] }),
+    };
+    if interrupt::requested() {
+        return Err(StitcherError::Interrupted);
+    }
+    let probe::ProbedInputs { files, durations: input_durations, skipped } = probed?;
+
+    // from here on an input only counts for the part of it that's used
+    //
+    let input_durations = input_durations.map(|durations| {
+        files
+            .iter()
+            .zip(durations)
+            .map(|(file, duration)| options.trims.get(file).map_or(duration, |trim| trim.trimmed_secs(duration)))
+            .collect::<Vec<_>>()
+    });
+    let total_duration_secs = input_durations.as_ref().map(|durations| durations.iter().sum::<f64>());
+    if files.is_empty() {
+        return Err(StitcherError::NoInputs(String::from("found no files that could be probed!")));
+    }
+    if total_duration_secs.is_some_and(|total| total <= 0.0) {
+        return Err(StitcherError::NoInputs(String::from(
+            "the inputs have no audio: every file probed to a duration of zero",
+        )));
+    }
+
+    // acrossfade can't fade over more than the whole of an input, so each one has to outlast the crossfade
+    //
+    if let Some(durations) = input_durations.as_ref().filter(|_| options.crossfade_secs > 0.0 && files.len() > 1) {
+        let too_short = files.iter().zip(durations).find(|(_, duration)| **duration <= options.crossfade_secs);
+        if let Some((file, _)) = too_short {
+            let file = file.to_string_lossy().into_owned();
+            return Err(StitcherError::InvalidOptions(vec![options::ValidationError::CrossfadeOverInput(file)]));
+        }
+    }
+
+    // with -v, say what each input is before anything runs
+    //
+    if let Some(probe) = probe.filter(|_| log::log_enabled!(log::Level::Debug)) {
+        for (i, file) in files.iter().enumerate() {
+            let params = probe.stream_params(file).map_or_else(|e| e.to_string(), |params| params.to_string());
+            let duration = input_durations.as_ref().map_or(String::from("unknown duration"), |x| format!("{:.3}s", x[i]));
+            log::debug!("{}: {}, {}", file.to_string_lossy(), params, duration);
+        }
+    }
+
+    // fill in the settings that depend on the inputs
+    //
+    let mut options = options.clone();
+
+    // inputs in another format than the one asked for get transcoded into it
+    //
+    if !options.encodes() && transcodes_into_format(&files, &options) {
+        options.reencode = true;
+    }
+
+    copy_input_tags(probe, &files, &mut options)?;
+
+    // a target size becomes a bitrate, spread over however long the inputs are
+    //
+    if let Some(target_bytes) = options.target_size_bytes {
+        let total = total_duration_secs.ok_or_else(|| {
+            StitcherError::BadInput(String::from(
+                "--target-size needs the total input duration, but the inputs couldn't be probed",
+            ))
+        })?;
+        options.bitrate_kbps = Some(codec::bitrate_for_target_size(target_bytes, total));
+    }
+
+    // a stream copy of inputs with different codecs or formats makes a broken file, so convert them
+    // all to the first one's format instead. --strict refuses, unless a conversion was asked for anyway
+    //
+    if !options.formats.is_video() && options.conform_format.is_none() {
+        if let Some(mismatch) = probe.and_then(|probe| probe::find_mismatch(probe, &files)) {
+            if !options.encodes() && options.resample.is_none() {
+                match options.strict {
+                    true => {
+                        return Err(StitcherError::BadInput(format!(
+                            "the inputs don't match, so they can't be stream copied (--strict): {}. pass --reencode to convert them",
+                            mismatch
+                        )))
+                    }
+                    false => log::warn!("the inputs don't match ({}), re-encoding instead of copying", mismatch),
+                }
+            }
+            options.conform_format = Some(mismatch.first.1.format);
+        }
+    }
+
+    // a chosen sample rate applies to every input, matching or not
+    //
+    if let Some(resample) = options.resample.filter(|_| !options.formats.is_video()) {
+        let probe = probe.ok_or_else(|| {
+            StitcherError::FfprobeNotFound(String::from("--resample needs ffprobe to read the inputs' sample rates"))
+        })?;
+        let formats = files.iter().filter_map(|file| probe.audio_format(file).ok()).collect::<Vec<_>>();
+        let first = options.conform_format.or(formats.first().copied()).unwrap_or_default();
+        let sample_rate = resample.pick(&formats).unwrap_or(first.sample_rate);
+        if options.conform_format.is_some() || formats.iter().any(|format| format.sample_rate != sample_rate) {
+            log::info!("resampling every input to {}Hz", sample_rate);
+            options.conform_format = Some(probe::AudioFormat { sample_rate, ..first });
+        }
+    }
+
+    // silence only joins cleanly if it matches the inputs, so take the format they're converted to,
+    // or the first one's
+    //
+    if options.generates_silence() && options.silence_format.is_none() {
+        let format = options
+            .conform_format
+            .or_else(|| probe.zip(files.first()).and_then(|(probe, first)| probe.audio_format(first).ok()));
+        match (format, options.strict) {
+            (Some(_), _) => (),
+            (None, true) => {
+                return Err(StitcherError::BadInput(String::from(
+                    "couldn't probe the input format to generate silence in (--strict)",
+                )))
+            }
+            (None, false) => log::warn!("couldn't probe the input format, generating silence as 48kHz stereo"),
+        }
+        options.silence_format = Some(format.unwrap_or_default());
+    }
+
+    // peak normalization needs a measuring pass over every input before the gains are known
+    //
+    if options.normalize_each && options.normalize_method.needs_measurement() {
+        let input_format = options.input_format.as_deref();
+        options.peak_volumes_db = normalize::measure_peaks(&ffmpeg_bin_path, &files, input_format, &options.trims)?;
+    }
+
+    // two-pass loudness normalization: measure the joined audio, then normalize it linearly by that much
+    //
+    if options.normalize_lufs.is_some() && options.loudness.is_none() {
+        options.loudness = Some(measure_loudness(&ffmpeg_bin_path, &files, &options)?);
+    }
+
+    // an input type the config marks as not copy-safe can't go through a stream copy
+    //
+    let copy_unsafe = files.iter().any(|file| {
+        let ext = file.extension().map(|x| x.to_string_lossy().into_owned()).unwrap_or_default();
+        !options.formats.is_copy_safe(&ext)
+    });
+    if copy_unsafe && !options.encodes() {
+        options.reencode = true;
+    }
+
+    // a fade out starts that long before the end, so the end has to be known
+    //
+    if options.fade_out_secs > 0.0 && options.output_duration_secs.is_none() {
+        let duration = expected_output_secs(&files, total_duration_secs, &options).ok_or_else(|| {
+            StitcherError::BadInput(String::from(
+                "--fade-out needs the duration of every input, but the inputs couldn't be probed",
+            ))
+        })?;
+        options.output_duration_secs = Some(duration);
+    }
+    let options = &options;
+
+    let output_file_path = options.output.as_path();
+
+    // chapters (and a cue sheet's tracks) start where each input does, so they need every input's duration.
+    // the scratch files are deleted when they drop, so they're cleaned up however the stitch ends
+    //
+    let chapters = match options.chapters || options.cue || options.chapters_out.is_some() {
+        false => None,
+        true => {
+            let durations = input_durations.as_ref().ok_or_else(|| {
+                let flag = match (options.chapters, options.cue) {
+                    (true, _) => "--chapters",
+                    (false, true) => "--cue",
+                    (false, false) => "--chapters-out",
+                };
+                StitcherError::BadInput(format!(
+                    "{} needs the duration of every input, but the inputs couldn't be probed",
+                    flag
+                ))
+            })?;
+            Some(chapters::from_inputs(&files, durations, options.pre_gap_secs, options.spacing_secs()))
+        }
+    };
+    let mut chapters_file = match (&chapters, options.chapters) {
+        (Some(chapters), true) => Some(scratch_file("chapters", &chapters::ffmetadata(chapters))?),
+        _ => None,
+    };
+    // a printed command reads its temp files, so they're kept for it to be rerun as it is
+    //
+    let keep_temp = options.keep_temp || options.show_command;
+    if let Some(file) = chapters_file.as_mut().filter(|_| keep_temp) {
+        keep_scratch_file(file, "chapters");
+    }
+    let chapters_file_path = chapters_file.as_deref().unwrap_or(Path::new(PLANNED_CHAPTERS_FILE_PATH));
+
+    let output_duration_secs = expected_output_secs(&files, total_duration_secs, options);
+
+    let progress = match (options.progress_json, options.progress_bar) {
+        (true, _) => ffmpeg::Progress::Json { total_duration_secs: output_duration_secs },
+        (false, true) => ffmpeg::Progress::Bar { total_duration_secs: output_duration_secs },
+        (false, false) => ffmpeg::Progress::Inherit,
+    };
+
+    // per-input processing feeds every file to ffmpeg directly and joins them in a filter graph,
+    // so there's no list file to write
+    //
+    if options.needs_filter_graph() {
+        let mut args = build_filter_graph_args(&files, chapters_file_path, options);
+
+        // a graph for a huge batch can be too long for the command line, so it goes in a file instead
+        //
+        let mut graph_script_file = scratch_file("graph", "")?;
+        if let Some(graph) = move_long_graph_to_script(&mut args, &graph_script_file) {
+            if let Err(e) = std::fs::write(&graph_script_file, graph) {
+                return Err(StitcherError::io("writing the filter graph to a temp file", e));
+            }
+            if keep_temp {
+                keep_scratch_file(&mut graph_script_file, "filter graph");
+            }
+        }
+
+        if options.show_command {
+            eprintln!("{}", ffmpeg::command_line(&ffmpeg_bin_path, &args));
+        }
+        let run = ffmpeg::run(&ffmpeg_bin_path, &args, progress, options.quiet)?;
+
+        check_stitch_run(&run, output_file_path)?;
+        check_stitch_warnings(&run, output_file_path, options)?;
+        write_chapter_files(options, chapters.as_deref())?;
+        warn_skipped(&skipped);
+        return Ok(Stitched { output: PathBuf::from(output_file_path), inputs: files });
+    }
+
+    // the list of files to stitch goes to ffmpeg on its stdin, so there's no temp file to write or clean up.
+    // unless it's asked for, to rerun the command by hand: then it's written out too, and left behind
+    //
+    let list = concat_list(&files, &options.trims)?;
+    log::debug!("concat list:\n{}", list.trim_end());
+    let list_file = match keep_temp {
+        false => None,
+        true => {
+            let mut file = scratch_file("list", &list)?;
+            keep_scratch_file(&mut file, "concat list");
+            Some(file)
+        }
+    };
+    let show_command = |args: &[OsString]| {
+        if options.show_command {
+            eprintln!("{}", ffmpeg::command_line_with_stdin(&ffmpeg_bin_path, args, list_file.as_deref()));
+        }
+    };
+
+    // run the command
+    //
+    let args = build_ffmpeg_args(chapters_file_path, options);
+    show_command(&args);
+    let mut run = ffmpeg::run_with_stdin(&ffmpeg_bin_path, &args, Some(&list), progress, options.quiet)?;
+
+    // some inputs that break a stream copy (e.g. mp3s with ID3 junk) go through fine when re-encoded.
+    // only retry for errors known to be fixable that way - a missing file won't appear on a second attempt.
+    // --strict fails instead, rather than quietly make a different output than was asked for
+    //
+    if !run.status.success() && !interrupt::requested() && !options.strict {
+        if let Some(retry_options) = reencode_retry_options(options, &run.stderr) {
+            log::warn!("stream copy failed on unreadable input data, retrying with a re-encode");
+            remove_partial_output(output_file_path);
+            let args = build_ffmpeg_args(chapters_file_path, &retry_options);
+            show_command(&args);
+            run = ffmpeg::run_with_stdin(&ffmpeg_bin_path, &args, Some(&list), progress, options.quiet)?;
+        }
+    }
+
+    // check the result
+    //
+    check_stitch_run(&run, output_file_path)?;
+    check_stitch_warnings(&run, output_file_path, options)?;
+    write_chapter_files(options, chapters.as_deref())?;
+    warn_skipped(&skipped);
+
+    Ok(Stitched { output: PathBuf::from(output_file_path), inputs: files })
+}
+
+/// List the inputs that were left out of the stitch again, once it's done: by the end of a long one, the
+/// warnings from when each was skipped have scrolled away.
+fn warn_skipped(skipped: &[probe::ProbeError]) {
+    if skipped.is_empty() {
+        return;
+    }
+    let reasons = skipped.iter().map(|e| format!("\n  {}", e)).collect::<String>();
+    log::warn!("skipped {} unreadable input(s), which the output is missing:{}", skipped.len(), reasons);
+}
+
+/// `run.check()`, removing what ffmpeg had written of `output` if Ctrl+C stopped it partway. the temp files
+/// are removed as the error returns, when they drop.
+fn check_stitch_run(run: &ffmpeg::FfmpegRun, output: &Path) -> Result<(), StitcherError> {
+    let result = run.check();
+    if let Err(StitcherError::Interrupted) = result {
+        remove_partial_output(output);
+    }
+    result
+}
+
+/// `ffmpeg::check_warnings`, for --strict-ffmpeg (or --strict). an output ffmpeg warned about is removed when
+/// that fails, so a rerun doesn't find it in the way.
+fn check_stitch_warnings(run: &ffmpeg::FfmpegRun, output: &Path, options: &StitchOptions) -> Result<(), StitcherError> {
+    let result = ffmpeg::check_warnings(&run.stderr, options.strict_ffmpeg || options.strict);
+    if result.is_err() {
+        remove_partial_output(output);
+    }
+    result
+}
+
+/// Remove what a failed ffmpeg run had written of `output`, so it's neither taken for a finished stitch nor
+/// in the way of the next attempt, which doesn't overwrite.
+fn remove_partial_output(output: &Path) {
+    if output != Path::new(options::STDOUT_OUTPUT) {
+        let _ = std::fs::remove_file(output);
+    }
+}
+
+/// Write `chapters` out as whichever files `options` asks for: a CUE sheet next to the output, for `cue`,
+/// and an FFMETADATA file at `chapters_out`.
+fn write_chapter_files(options: &StitchOptions, chapters: Option<&[chapters::Chapter]>) -> Result<(), StitcherError> {
+    let Some(chapters) = chapters else {
+        return Ok(());
+    };
+
+    let mut files = vec![];
+    if options.cue {
+        files.push((options::cue_sheet_path(&options.output), chapters::cue_sheet(chapters, &options.output, &options.metadata)));
+    }
+    if let Some(path) = &options.chapters_out {
+        files.push((path.clone(), chapters::ffmetadata(chapters)));
+    }
+    for (path, contents) in files {
+        std::fs::write(&path, contents).map_err(|e| StitcherError::io(format!("writing {}", path.to_string_lossy()), e))?;
+    }
+    Ok(())
+}
+
+/// The analysis pass of `normalize_lufs`: join `files` the way the stitch will, through loudnorm's analysis,
+/// into ffmpeg's null muxer, and read back what loudnorm measured.
+fn measure_loudness(
+    ffmpeg_bin_path: &Path,
+    files: &[PathBuf],
+    options: &StitchOptions,
+) -> Result<normalize::LoudnessMeasurement, StitcherError> {
+    // nothing is kept, so encode to cheap PCM and leave out what only matters to a real output
+    //
+    let analysis_options = StitchOptions {
+        output: PathBuf::from("-"),
+        overwrite: true,
+        progress_json: false,
+        progress_bar: false,
+        codec: Some(String::from("pcm_s16le")),
+        bitrate_kbps: None,
+        vbr_quality: None,
+        compression_level: None,
+        chapters: false,
+        cue: false,
+        chapters_out: None,
+        metadata: vec![],
+        loudness: None,
+        fade_in_secs: 0.0,
+        fade_out_secs: 0.0,
+        ..options.clone()
+    };
+    let mut args = build_filter_graph_args(files, Path::new(PLANNED_CHAPTERS_FILE_PATH), &analysis_options);
+    args.pop();
+    args.extend(os_args(&["-f", "null", "-"]));
+
+    let graph_script_file = scratch_file("graph", "")?;
+    if let Some(graph) = move_long_graph_to_script(&mut args, &graph_script_file) {
+        if let Err(e) = std::fs::write(&graph_script_file, graph) {
+            return Err(StitcherError::io("writing the filter graph to a temp file", e));
+        }
+    }
+
+    let run = ffmpeg::run(ffmpeg_bin_path, &args, ffmpeg::Progress::Inherit, true)?;
+    run.check()?;
+    normalize::parse_loudness(&run.stderr)
+        .ok_or_else(|| StitcherError::Other(String::from("failed to measure the loudness: no loudnorm report")))
+}
+
+/// Leave `file` behind after the run, for `keep_temp`, and say where it is.
+fn keep_scratch_file(file: &mut tempfile::TempPath, kind: &str) {
+    file.disable_cleanup(true);
+    log::info!("kept the {} at {}", kind, file.to_string_lossy());
+}
+
+/// Write `contents` to a new, uniquely named file in the OS temp dir, so neither a read-only working
+/// directory nor a second stitcher running at the same time gets in the way. the file is deleted when
+/// the returned path drops.
+fn scratch_file(kind: &str, contents: &str) -> Result<tempfile::TempPath, StitcherError> {
+    let context = format!("writing the {} to a temp file", kind);
+    let mut file = tempfile::Builder::new()
+        .prefix(&format!("stitcher_{}_", kind))
+        .suffix(".txt")
+        .tempfile()
+        .map_err(|e| StitcherError::io(context.clone(), e))?;
+    file.write_all(contents.as_bytes())
+        .and_then(|()| file.flush())
+        .map_err(|e| StitcherError::io(context, e))?;
+    Ok(file.into_temp_path())
+}
+
+/// The contents of the list file ffmpeg's concat demuxer reads: a `file` line per input, in order, each
+/// followed by `inpoint`/`outpoint` lines if it's in `trims`. ffmpeg reads a relative path as relative to
+/// the list, which comes in on stdin, so each path is made absolute first.
+pub fn concat_list(
+    files: &[PathBuf],
+    trims: &std::collections::HashMap<PathBuf, playlist::Trim>,
+) -> Result<String, StitcherError> {
+    let mut list = String::new();
+    for path in files {
+        let absolute = std::path::absolute(path)
+            .map_err(|e| StitcherError::io(format!("resolving {}", path.to_string_lossy()), e))?;
+        let file = absolute.to_str().ok_or_else(|| {
+            StitcherError::Other(format!("{} isn't valid UTF-8, so it can't go in the concat list", path.to_string_lossy()))
+        })?;
+        list.push_str(&format!("file {}\n", concat_quote(file)?));
+        if let Some(trim) = trims.get(path) {
+            if let Some(secs) = trim.start_secs {
+                list.push_str(&format!("inpoint {}\n", secs));
+            }
+            if let Some(secs) = trim.end_secs {
+                list.push_str(&format!("outpoint {}\n", secs));
+            }
+        }
+    }
+    Ok(list)
+}
+
+/// `path` quoted for a concat list: in single quotes, where nothing is special but a single quote, which
+/// has to close the quotes, be escaped, and open them again (`'it'\''s.wav'`). the list is read a line
+/// at a time, so a path with a line break in it can't be listed at all.
+fn concat_quote(path: &str) -> Result<String, StitcherError> {
+    if path.contains(['\n', '\r']) {
+        return Err(StitcherError::Other(format!("{:?} has a line break in its name, so it can't go in the concat list", path)));
+    }
+    Ok(format!("'{}'", path.replace('\'', "'\\''")))
+}
+
+/// The ffmpeg arguments a stitch of `files` with `options` would run, without running anything.
+/// settings that depend on probing the inputs (like a `target_size_bytes` bitrate) only show up
+/// if they're already filled in.
+pub fn planned_ffmpeg_args(files: &[PathBuf], options: &StitchOptions) -> Vec<OsString> {
+    let options = &StitchOptions {
+        reencode: options.reencode || transcodes_into_format(files, options),
+        ..options.clone()
+    };
+    let chapters_file_path = Path::new(PLANNED_CHAPTERS_FILE_PATH);
+    match options.needs_filter_graph() {
+        true => build_filter_graph_args(files, chapters_file_path, options),
+        false => build_ffmpeg_args(chapters_file_path, options),
+    }
+}
+
+/// Roughly how big stitching `files` makes the output, in bytes, before anything runs: the inputs put
+/// together for a stream copy (only the part of a trimmed one that's used), or the output's length at the
+/// bitrate it's encoded at. `None` when that can't be worked out, like an encode or a trim without a `probe` to
+/// measure the inputs.
+pub fn estimate_output_size(probe: Option<&dyn probe::Probe>, files: &[PathBuf], options: &StitchOptions) -> Option<u64> {
+    // GStreamer always decodes and encodes again
+    //
+    let encodes = options.encodes() || transcodes_into_format(files, options) || options.backend == options::Backend::Gstreamer;
+    if !encodes {
+        return files
+            .iter()
+            .map(|file| {
+                let size_bytes = std::fs::metadata(file).ok()?.len();
+                let Some(trim) = options.trims.get(file) else {
+                    return Some(size_bytes);
+                };
+                let duration = probe?.duration(file).ok().filter(|secs| *secs > 0.0)?;
+                Some((size_bytes as f64 * trim.trimmed_secs(duration) / duration) as u64)
+            })
+            .sum();
+    }
+    if let Some(target_bytes) = options.target_size_bytes {
+        return Some(target_bytes);
+    }
+
+    let probe = probe?;
+    let duration_secs = planned_output_secs(probe, files, options)?;
+
+    let codec = options.codec.as_deref().or_else(|| options.formats.default_codec(&options.output_format()?))?;
+    let format = options.conform_format.or_else(|| probe.audio_format(files.first()?).ok()).unwrap_or_default();
+    let kbps = options.bitrate_kbps.map(f64::from).or_else(|| codec::typical_kbps(codec, format))?;
+    Some(codec::size_for_bitrate(kbps, duration_secs))
+}
+
+/// How long stitching `files` makes the output, in seconds, from what `probe` finds before anything runs: the
+/// inputs' trimmed lengths, plus any generated silence, less any crossfades. `None` if any input can't be probed.
+pub fn planned_output_secs(probe: &dyn probe::Probe, files: &[PathBuf], options: &StitchOptions) -> Option<f64> {
+    let total_duration_secs = files
+        .iter()
+        .map(|file| {
+            let duration = probe.duration(file).ok()?;
+            Some(options.trims.get(file).map_or(duration, |trim| trim.trimmed_secs(duration)))
+        })
+        .sum::<Option<f64>>();
+    expected_output_secs(files, total_duration_secs, options)
+}
+
+/// Read the tags `options.copy_metadata` asks for from `files`, and put them under the ones given outright.
+pub fn copy_input_tags(
+    probe: Option<&dyn probe::Probe>,
+    files: &[PathBuf],
+    options: &mut StitchOptions,
+) -> Result<(), StitcherError> {
+    if let Some(copy) = options.copy_metadata {
+        let probe = probe.ok_or_else(|| {
+            StitcherError::FfprobeNotFound(String::from("--copy-metadata needs ffprobe to read the inputs' tags"))
+        })?;
+        let copied = tags::from_inputs(probe, files, copy, options.strict).map_err(StitcherError::BadInput)?;
+        options.metadata = tags::under(&options.metadata, copied);
+    }
+    Ok(())
+}
+
+/// The FFMETADATA file of chapters shown by `planned_ffmpeg_args`, with `--chapters`. a real stitch
+/// writes it under a unique name in the temp dir.
+const PLANNED_CHAPTERS_FILE_PATH: &str = "stitcher_chapters.txt";
+
+/// `-y` to let ffmpeg replace an existing output, if `options.overwrite` allows it, otherwise `-n`, so
+/// ffmpeg fails rather than clobbering (or prompting about) one that turned up after the check.
+fn overwrite_flag(options: &StitchOptions) -> &'static str {
+    match options.overwrite {
+        true => "-y",
+        false => "-n",
+    }
+}
+
+/// Assemble the ffmpeg arguments for concatenating the files in the concat list, read from stdin, into
+/// `options.output`, reading chapters from `chapters_file_path` if `options.chapters` is on.
+fn build_ffmpeg_args(chapters_file_path: &Path, options: &StitchOptions) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec![];
+
+    if options.reports_progress() {
+        args.extend(os_args(&["-progress", "pipe:1", "-nostats"]));
+    }
+
+    // video mode copies the video streams along with the audio, so there's nothing to strip
+    //
+    let video = options.formats.is_video();
+    args.push(OsString::from(overwrite_flag(options)));
+    if !options.keep_cover && !video {
+        args.push(OsString::from("-vn"));
+    }
+
+    // the list comes in over a pipe, so the files in it have to be let through as well
+    //
+    args.extend(os_args(&["-f", "concat", "-safe", "0", "-protocol_whitelist", "file,pipe", "-i", "pipe:0"]));
+    if options.chapters {
+        args.extend(os_args(&["-f", "ffmetadata", "-i"]));
+        args.push(chapters_file_path.as_os_str().to_owned());
+    }
+
+    // `-vn` throws away cover art along with real video. to keep the cover, map the audio plus only
+    // the streams flagged as attached pictures, and keep that flag on them in the output
+    //
+    if options.keep_cover && !video {
+        args.extend(os_args(&["-map", "0:a", "-map", "0:disp:attached_pic?", "-disposition:v", "attached_pic"]));
+    }
+    if options.chapters {
+        args.extend(os_args(&["-map_metadata", "1", "-map_chapters", "1"]));
+    }
+
+    push_codec_args(&mut args, options);
+    push_output_args(&mut args, options);
+    args
+}
+
+/// How long the output of stitching `files` comes out, from their `total_duration_secs`: generated silence
+/// makes it longer than the inputs put together, crossfades shorter.
+fn expected_output_secs(files: &[PathBuf], total_duration_secs: Option<f64>, options: &StitchOptions) -> Option<f64> {
+    let segments = plan_segments(files, options);
+    total_duration_secs.map(|total| {
+        total + filtergraph::silence_secs(&segments) - filtergraph::crossfade_overlap_secs(&segments, options.crossfade_secs)
+    })
+}
+
+/// Assemble the ffmpeg arguments for running each file through its own filter chain, then joining
+/// them with the concat filter into `options.output`. chapters come from `chapters_file_path` if `options.chapters` is on.
+fn build_filter_graph_args(files: &[PathBuf], chapters_file_path: &Path, options: &StitchOptions) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec![];
+
+    if options.reports_progress() {
+        args.extend(os_args(&["-progress", "pipe:1", "-nostats"]));
+    }
+
+    args.push(OsString::from(overwrite_flag(options)));
+    for file in files {
+        if let Some(format) = &options.input_format {
+            args.extend(os_args(&["-f", format]));
+        }
+        if let Some(trim) = options.trims.get(file) {
+            if let Some(secs) = trim.start_secs {
+                args.extend(os_args(&["-ss", &secs.to_string()]));
+            }
+            if let Some(secs) = trim.end_secs {
+                args.extend(os_args(&["-to", &secs.to_string()]));
+            }
+        }
+        args.push(OsString::from("-i"));
+        args.push(file.as_os_str().to_owned());
+    }
+    if options.chapters {
+        args.extend(os_args(&["-f", "ffmetadata", "-i"]));
+        args.push(chapters_file_path.as_os_str().to_owned());
+    }
+
+    let mut silence_format = options.silence_format.unwrap_or_default();
+    if options.normalize_each {
+        silence_format.sample_rate = filtergraph::LOUDNORM_EACH_SAMPLE_RATE;
+    }
+
+    let graph = filtergraph::concat_graph(&plan_segments(files, options), &silence_format, options.crossfade_secs);
+    let mut output_filters = match options.normalize_lufs {
+        Some(target_lufs) => normalize::loudnorm_filters(target_lufs, options.loudness.as_ref()),
+        None => vec![],
+    };
+    output_filters.extend(filtergraph::fade_filters(
+        options.fade_in_secs,
+        options.fade_out_secs,
+        options.output_duration_secs,
+    ));
+    args.push(OsString::from("-filter_complex"));
+    args.push(OsString::from(filtergraph::with_output_filters(&graph, &output_filters)));
+    args.extend(os_args(&["-map", filtergraph::OUTPUT_LABEL]));
+    if options.chapters {
+        let chapters_input = files.len().to_string();
+        args.extend(os_args(&["-map_metadata", &chapters_input, "-map_chapters", &chapters_input]));
+    }
+
+    push_codec_args(&mut args, options);
+    push_output_args(&mut args, options);
+    args
+}
+
+/// Push the output's tags, then the output file, with its muxer where ffmpeg's guess from the extension
+/// isn't good enough.
+fn push_output_args(args: &mut Vec<OsString>, options: &StitchOptions) {
+    // with the tags copied over outright, ffmpeg mustn't add the first input's on its own. chapters map the
+    // chapters file's instead, which has none
+    //
+    if options.copy_metadata.is_some() && !options.chapters {
+        args.extend(os_args(&["-map_metadata", "-1"]));
+    }
+    for (key, value) in &options.metadata {
+        args.push(OsString::from("-metadata"));
+        args.push(OsString::from(format!("{}={}", key, value)));
+    }
+
+    // `.mka` is audio-only Matroska, and `.m4b` an audiobook MP4. name the muxer rather than rely on
+    // ffmpeg mapping the extension to it. stdout has no extension at all, so it always gets one, and MP4s
+    // written there are fragmented, since a pipe can't be seeked back to write the index at the start
+    //
+    match (options.writes_to_stdout(), options.output_format().as_deref()) {
+        (true, Some(ext)) => {
+            args.extend(os_args(&["-f", muxer(ext)]));
+            if matches!(muxer(ext), "ipod" | "mp4" | "mov") {
+                args.extend(os_args(&["-movflags", "frag_keyframe+empty_moov"]));
+            }
+        }
+        (false, Some(ext)) if ["mka", "m4b"].iter().any(|x| x.eq_ignore_ascii_case(ext)) => {
+            args.extend(os_args(&["-f", muxer(ext)]))
+        }
+        _ => (),
+    }
+    // last, so they can override anything above
+    //
+    args.extend(options.extra_ffmpeg_args.iter().map(OsString::from));
+    match options.writes_to_stdout() {
+        true => args.push(OsString::from("pipe:1")),
+        false => args.push(options.output.as_os_str().to_owned()),
+    }
+}
+
+/// Extensions whose ffmpeg muxer goes by another name. any other extension names its own muxer.
+const MUXERS: &[(&str, &str)] = &[("mka", "matroska"), ("mkv", "matroska"), ("m4a", "ipod"), ("m4b", "ipod"), ("aac", "adts")];
+
+/// The ffmpeg muxer that writes files with extension `ext`.
+fn muxer(ext: &str) -> &str {
+    MUXERS.iter().find(|(x, _)| x.eq_ignore_ascii_case(ext)).map_or(ext, |(_, muxer)| *muxer)
+}
+
+/// Past this many bytes of arguments, the filter graph is passed in a `-filter_complex_script` file rather
+/// than inline. comfortably under Linux's 128KiB limit on a single argument and the smallest common ARG_MAX.
+const MAX_INLINE_ARGS_BYTES: usize = 64 * 1024;
+
+/// If `args` add up to more than `MAX_INLINE_ARGS_BYTES`, swap the inline `-filter_complex` graph for
+/// `-filter_complex_script script_path`, and return the graph to write to that file.
+fn move_long_graph_to_script(args: &mut [OsString], script_path: &Path) -> Option<String> {
+    let total_bytes = args.iter().map(|arg| arg.len() + 1).sum::<usize>();
+    if total_bytes <= MAX_INLINE_ARGS_BYTES {
+        return None;
+    }
+
+    let flag_at = args.iter().position(|arg| arg == "-filter_complex")?;
+    let graph = std::mem::replace(&mut args[flag_at + 1], script_path.as_os_str().to_owned());
+    args[flag_at] = OsString::from("-filter_complex_script");
+    Some(graph.to_string_lossy().into_owned())
+}
+
+/// Lay out the pieces of the output, in order: each input with its own filters, plus any generated silence.
+fn plan_segments(files: &[PathBuf], options: &StitchOptions) -> Vec<filtergraph::Segment> {
+    let mut segments = vec![];
+
+    if options.pre_gap_secs > 0.0 {
+        segments.push(filtergraph::Segment::Silence { secs: options.pre_gap_secs });
+    }
+
+    for (index, file) in files.iter().enumerate() {
+        if index > 0 && options.gap_secs > 0.0 {
+            segments.push(filtergraph::Segment::Silence { secs: options.gap_secs });
+        }
+
+        let mut filters = vec![];
+        if let Some(silence) = &options.trim_silence {
+            filters.extend(filtergraph::trim_silence_filters(silence));
+        }
+        if let Some(format) = options.conform_format {
+            filters.push(format!("aresample={}", format.sample_rate));
+            filters.push(format!("aformat=channel_layouts={}", format.channel_layout()));
+        }
+        if options.normalize_each {
+            let max_volume_db = options.peak_volumes_db.get(index).copied();
+            filters.extend(normalize::filters(options.normalize_method, max_volume_db));
+        }
+        if let Some(gain_db) = options.gains_db.get(file) {
+            filters.push(format!("volume={}dB", gain_db));
+        }
+        segments.push(filtergraph::Segment::Input { index, filters });
+    }
+
+    if options.post_gap_secs > 0.0 {
+        segments.push(filtergraph::Segment::Silence { secs: options.post_gap_secs });
+    }
+
+    segments
+}
+
+/// Push the output codec arguments: a stream copy, or the audio encoder to use.
+fn push_codec_args(args: &mut Vec<OsString>, options: &StitchOptions) {
+    // copy-concatenated FLAC can carry misaligned frames between the joined files, so optionally
+    // decode and re-encode it. FLAC is lossless so nothing is lost doing this
+    //
+    let output_ext = options.output_format();
+    let encoding = match (options.flac_recompress, output_ext.as_deref()) {
+        (Some(level), Some(ext)) if ext.eq_ignore_ascii_case("flac") => {
+            args.extend(os_args(&["-c:a", "flac", "-compression_level", &level.to_string()]));
+            true
+        }
+        // pick the codec ourselves where we can, rather than leaving it to ffmpeg's default for the container
+        //
+        _ if options.encodes() => {
+            let codec = options
+                .codec
+                .as_deref()
+                .or_else(|| options.formats.default_codec(output_ext.as_deref()?));
+            if let Some(codec) = codec {
+                args.extend(os_args(&["-c:a", codec]));
+            }
+            args.extend(quality_args(codec, options).into_iter().map(OsString::from));
+            true
+        }
+        _ => {
+            args.extend(os_args(&["-c", "copy"]));
+            false
+        }
+    };
+
+    if encoding && options.keep_cover {
+        args.extend(os_args(&["-c:v", "copy"]));
+    }
+}
+
+/// The bitrate or quality to encode with `codec` at: what was asked for, or the codec's default. a quality or
+/// level the codec doesn't take has already been turned away by `StitchOptions::validate`.
+fn quality_args(codec: Option<&str>, options: &StitchOptions) -> Vec<String> {
+    let mut args = match (options.bitrate_kbps, options.vbr_quality, codec) {
+        (Some(kbps), _, _) => vec![String::from("-b:a"), format!("{}k", kbps)],
+        (None, Some(quality), Some(codec)) => codec::vbr_quality_args(codec, quality).unwrap_or_default(),
+        (None, _, Some(codec)) => codec::default_quality_args(codec).iter().map(|x| x.to_string()).collect(),
+        (None, _, None) => vec![],
+    };
+    if let Some((level, codec)) = options.compression_level.zip(codec) {
+        args.extend(codec::compression_level_args(codec, level).unwrap_or_default());
+    }
+    args
+}
+
+/// Whether any of `files` isn't already in `options.format`, so copying it into the output won't do.
+fn transcodes_into_format(files: &[PathBuf], options: &StitchOptions) -> bool {
+    let Some(format) = &options.format else {
+        return false;
+    };
+    files
+        .iter()
+        .any(|file| !options::output_extension(file).is_some_and(|ext| ext.eq_ignore_ascii_case(format)))
+}
+
+/// The options to retry a failed copy-concat with, if its stderr shows an error a re-encode can get past.
+fn reencode_retry_options(options: &StitchOptions, ffmpeg_stderr: &str) -> Option<StitchOptions> {
+    if options.encodes() || !ffmpeg::is_recoverable_copy_error(ffmpeg_stderr) {
+        return None;
+    }
+
+    Some(StitchOptions {
+        reencode: true,
+        ..options.clone()
+    })
+}
+
+fn os_args(args: &[&str]) -> Vec<OsString> {
+    args.iter().map(OsString::from).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixed_probe::FixedProbe;
+
+    #[test]
+    pub fn test_finding_files() {
+        let sounds_dir_path = test_path_wav();
+        let files = look_for_files(sounds_dir_path, &Default::default(), &Default::default());
+        let expected_len = 3;
+        let actual_len = files.len();
+        assert!(
+            actual_len == expected_len,
+            "expected `look_for_files` to find {} files in the sounds directory",
+            expected_len
+        );
+    }
+
+    #[test]
+    fn test_finding_valid_ffmpeg_binary() {
+        use std::path::PathBuf;
+        match find_valid_ffmpeg_binary(vec![PathBuf::from("/bin/ffmpeg"), PathBuf::from("./vendor/ffmpeg/ffmpeg")]) {
+            Err(_) => panic!("test expected to receive a valid ffmpeg binary path from `find_valid_ffmpeg_binary`"),
+            Ok(_path) => (),
+        }
+    }
+
+    #[test]
+    pub fn test_find_in_dirs() {
+        let dir = scratch_dir("find_in_dirs");
+        let bin = dir.join("bin");
+        std::fs::create_dir_all(bin.join("ffmpeg-docs")).expect("failed to create a scratch directory");
+        std::fs::write(bin.join("ffmpeg"), "").expect("failed to write a scratch file");
+
+        let dirs = [PathBuf::new(), dir.join("missing"), dir.clone(), bin.clone()];
+        assert_eq!(find_in_dirs("ffmpeg", dirs.clone()), Some(bin.join("ffmpeg")));
+        assert_eq!(find_in_dirs("ffmpeg-docs", dirs), None, "expected a directory not to count");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn expensive_test_stitching_files() {
+        let ffmpeg_exe_path = match find_valid_ffmpeg_binary(
+            vec![PathBuf::from("/bin/ffmpeg"), PathBuf::from("./vendor/ffmpeg/ffmpeg")]) {
+            Err(_) => panic!("test expected to receive a valid ffmpeg binary path from `find_valid_ffmpeg_binary`"),
+            Ok(path) => path,
+        };
+
+        let input_files = look_for_files(test_path_wav(), &Default::default(), &Default::default());
+        let expected_output_path = std::path::PathBuf::from("./TEST_OUTPUT.wav");
+        let actual_output_path = stitch_files(
+            ffmpeg_exe_path,
+            input_files,
+            &StitchOptions {
+                output: expected_output_path.clone(),
+                ..Default::default()
+            },
+        )
+            .expect("test failed to run stitch_files")
+            .output;
+
+        assert!(
+            actual_output_path == expected_output_path,
+            "expected `stitch_files` to produce an output file at {}, got {}",
+            expected_output_path.to_string_lossy(),
+            actual_output_path.to_string_lossy()
+        );
+
+        if let Err(e) = std::fs::remove_file(actual_output_path) {
+            panic!("failed to clean up the `actual output path`!: {}", e);
+        }
+    }
+
+    #[test]
+    #[allow(clippy::useless_vec, clippy::assertions_on_constants)]
+    pub fn test_filter_supported_extensions() {
+        type T = Vec<PathBuf>;
+
+        let paths = vec!["file1.txt", "file2.wav", "file3.mp3", "file4.rs", "file5"]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<T>();
+
+        let expected_supported = vec!["file2.wav", "file3.mp3"]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<T>();
+
+        let actual = paths
+            .into_iter()
+            .filter_map(|path| filter_supported_extensions(path, &Default::default()))
+            .collect::<T>();
+
+        if !expected_supported.eq(&actual) {
+            dbg!(&expected_supported);
+            dbg!(&actual);
+            assert!(
+                false,
+                "expected the filtered extensions to match the supported values"
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_flac_recompress_args() {
+        let options = StitchOptions {
+            flac_recompress: Some(8),
+            ..test_options("out.flac")
+        };
+
+        let args = build_ffmpeg_args(Path::new("chapters.txt"), &options);
+        let expected_tail = ["-c:a", "flac", "-compression_level", "8", "out.flac"]
+            .map(OsString::from);
+        assert!(
+            args.ends_with(&expected_tail),
+            "expected FLAC output with --flac-recompress to re-encode with flac, got {:?}",
+            args
+        );
+        assert!(!args.contains(&OsString::from("copy")));
+
+        // without the flag, or for other outputs, FLAC stays a stream copy
+        //
+        let copied = build_ffmpeg_args(Path::new("chapters.txt"), &test_options("out.flac"));
+        assert!(copied.ends_with(&["-c", "copy", "out.flac"].map(OsString::from)));
+
+        let not_flac = build_ffmpeg_args(Path::new("chapters.txt"), &StitchOptions { output: PathBuf::from("out.wav"), ..options });
+        assert!(not_flac.ends_with(&["-c", "copy", "out.wav"].map(OsString::from)));
+    }
+
+    #[test]
+    pub fn test_keep_cover_args() {
+        let vn = OsString::from("-vn");
+
+        let default_args = build_ffmpeg_args(Path::new("chapters.txt"), &test_options("out.mp3"));
+        assert!(default_args.contains(&vn), "expected the default args to strip video with -vn");
+
+        let options = StitchOptions {
+            keep_cover: true,
+            ..test_options("out.mp3")
+        };
+        let args = build_ffmpeg_args(Path::new("chapters.txt"), &options);
+        assert!(!args.contains(&vn), "expected --keep-cover to omit -vn, got {:?}", args);
+
+        let attached_pic_map = os_args(&["-map", "0:disp:attached_pic?"]);
+        assert!(
+            args.windows(2).any(|pair| pair == attached_pic_map.as_slice()),
+            "expected --keep-cover to map attached pictures, got {:?}",
+            args
+        );
+    }
+
+    #[test]
+    pub fn test_video_mode_copies_every_stream() {
+        let dir = scratch_dir("video");
+        for name in ["a.mp4", "b.mov", "c.wav", "d.txt"] {
+            std::fs::write(dir.join(name), "").expect("failed to write a scratch file");
+        }
+        let formats = formats::FormatTable::default().with_video(true);
+
+        let mut found = look_for_files(dir.clone(), &Default::default(), &formats);
+        found.sort();
+        assert_eq!(found, vec![dir.join("a.mp4"), dir.join("b.mov")], "expected only the video containers to be picked up");
+
+        let options = StitchOptions {
+            formats,
+            ..test_options("out.mp4")
+        };
+        assert_eq!(options.validate(), Ok(()));
+        let args = build_ffmpeg_args(Path::new("chapters.txt"), &options);
+        assert_eq!(args, os_args(&["-n", "-f", "concat", "-safe", "0", "-protocol_whitelist", "file,pipe", "-i", "pipe:0", "-c", "copy", "out.mp4"]));
+
+        let reencoded = StitchOptions {
+            reencode: true,
+            ..options
+        };
+        assert_eq!(reencoded.validate(), Err(vec![options::ValidationError::VideoNeedsStreamCopy]));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_select_only_keeps_given_names_in_order() {
+        let dir = scratch_dir("select_only");
+        for name in ["a.wav", "b.wav", "c.wav", "d.wav", "e.wav"] {
+            std::fs::write(dir.join(name), b"").expect("failed to write a scratch file");
+        }
+
+        let names = ["d.wav", "a.wav", "c.wav"].map(String::from);
+        let selected = select_only(look_for_files(dir.clone(), &Default::default(), &Default::default()), &names, false)
+            .expect("expected every --only name to be found");
+
+        let expected = names.iter().map(|name| dir.join(name)).collect::<Vec<_>>();
+        assert_eq!(selected, expected, "expected exactly the --only files, in --only order");
+
+        let with_missing = ["a.wav", "nope.wav"].map(String::from);
+        assert!(matches!(
+            select_only(look_for_files(dir.clone(), &Default::default(), &Default::default()), &with_missing, true),
+            Err(StitcherError::NoInputs(_))
+        ));
+        assert_eq!(select_only(look_for_files(dir.clone(), &Default::default(), &Default::default()), &with_missing, false).ok(), Some(vec![dir.join("a.wav")]));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_dedupe_keeps_the_first_copy() {
+        let dir = scratch_dir("dedupe");
+        for (name, contents) in [("a.wav", "one"), ("b.wav", "two"), ("a copy.wav", "one"), ("c.wav", "one")] {
+            std::fs::write(dir.join(name), contents).expect("failed to write a scratch file");
+        }
+
+        let files = ["a.wav", "b.wav", "a copy.wav", "c.wav"].map(|name| dir.join(name)).to_vec();
+        let (kept, skipped) = dedupe(files).expect("expected every file to hash");
+        assert_eq!(kept, vec![dir.join("a.wav"), dir.join("b.wav")]);
+        assert_eq!(
+            skipped,
+            vec![
+                Duplicate { file: dir.join("a copy.wav"), same_as: dir.join("a.wav") },
+                Duplicate { file: dir.join("c.wav"), same_as: dir.join("a.wav") },
+            ]
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_reencode_retry_only_for_recoverable_errors() {
+        let options = test_options("out.mp3");
+        let invalid_data = "/tmp/stitcher_list_Xb3kQ9.txt: Invalid data found when processing input\n";
+
+        let retry = reencode_retry_options(&options, invalid_data)
+            .expect("expected an \"Invalid data found\" failure to trigger the re-encode retry");
+        assert!(retry.reencode);
+        assert!(!build_ffmpeg_args(Path::new("chapters.txt"), &retry).contains(&OsString::from("copy")));
+
+        let fatal = "/tmp/stitcher_list_Xb3kQ9.txt: No such file or directory\n";
+        assert!(reencode_retry_options(&options, fatal).is_none());
+
+        // a re-encode that fails the same way isn't retried again
+        //
+        assert!(reencode_retry_options(&retry, invalid_data).is_none());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    pub fn test_reencode_retry_replaces_a_partial_copy() {
+        let dir = scratch_dir("reencode_retry");
+        let output = dir.join("out.mp3");
+
+        // like ffmpeg, a stream copy that hits bad data partway has already started the output, and with -n
+        // an output that's there is an error
+        //
+        let ffmpeg = mock_ffmpeg(
+            &dir,
+            "cat > /dev/null\nfor last; do :; done\n\
+             if [ -e \"$last\" ]; then echo \"File '$last' already exists. Exiting.\" >&2; exit 1; fi\n\
+             case \"$*\" in\n  *\"-c copy\"*) echo partial > \"$last\"; echo \"Invalid data found when processing input\" >&2; exit 1 ;;\nesac\n\
+             echo stitched > \"$last\"\n",
+        );
+        let options = StitchOptions { quiet: true, ..test_options(&output.to_string_lossy()) };
+
+        let stitched = stitch_files_with_probe(ffmpeg, None, vec![dir.join("a.mp3"), dir.join("b.mp3")], &options)
+            .expect("expected the re-encode retry to replace the partial copy");
+        assert_eq!(stitched.output, output);
+        assert_eq!(std::fs::read_to_string(&output).expect("expected an output"), "stitched\n");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    pub fn test_strict_ffmpeg_removes_the_output_it_fails() {
+        let dir = scratch_dir("strict_ffmpeg_output");
+        let output = dir.join("out.mp3");
+        let ffmpeg = mock_ffmpeg(
+            &dir,
+            "cat > /dev/null\nfor last; do :; done\n\
+             echo \"[mp3 @ 0x55d0c8a0] Estimating duration from bitrate, this may be inaccurate\" >&2\n\
+             echo stitched > \"$last\"\n",
+        );
+        let options = StitchOptions {
+            quiet: true,
+            strict_ffmpeg: true,
+            ..test_options(&output.to_string_lossy())
+        };
+
+        let files = vec![dir.join("a.mp3"), dir.join("b.mp3")];
+        match stitch_files_with_probe(ffmpeg.clone(), None, files.clone(), &options) {
+            Err(StitcherError::BadInput(_)) => (),
+            other => panic!("expected --strict-ffmpeg to fail on the warning, got {:?}", other.map(|x| x.output)),
+        }
+        assert!(!output.exists(), "expected the flawed output to be removed");
+
+        // so the rerun gets as far as ffmpeg again, rather than stopping at OutputExists
+        //
+        let without_strict = StitchOptions { strict_ffmpeg: false, ..options };
+        assert!(stitch_files_with_probe(ffmpeg, None, files, &without_strict).is_ok());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_reencode_codec_selection() {
+        let ogg = StitchOptions {
+            reencode: true,
+            ..test_options("out.ogg")
+        };
+        assert!(build_ffmpeg_args(Path::new("chapters.txt"), &ogg).ends_with(&os_args(&["-c:a", "libvorbis", "-q:a", "5", "out.ogg"])));
+
+        let overridden = StitchOptions {
+            codec: Some(String::from("libopus")),
+            ..ogg
+        };
+        assert!(build_ffmpeg_args(Path::new("chapters.txt"), &overridden).ends_with(&os_args(&["-c:a", "libopus", "-b:a", "128k", "out.ogg"])));
+
+        let tuned = StitchOptions {
+            vbr_quality: Some(0.0),
+            compression_level: Some(0),
+            ..test_options("out.mp3")
+        };
+        assert!(tuned.encodes());
+        assert!(build_ffmpeg_args(Path::new("chapters.txt"), &tuned)
+            .ends_with(&os_args(&["-c:a", "libmp3lame", "-q:a", "0", "-compression_level", "0", "out.mp3"])));
+
+        let constant = StitchOptions {
+            bitrate_kbps: Some(256),
+            ..test_options("out.m4a")
+        };
+        assert!(build_ffmpeg_args(Path::new("chapters.txt"), &constant).ends_with(&os_args(&["-c:a", "aac", "-b:a", "256k", "out.m4a"])));
+    }
+
+    #[test]
+    pub fn test_configured_extension_is_discovered_and_encoded() {
+        let dir = scratch_dir("config_extensions");
+        for name in ["a.wv", "b.wav", "c.txt"] {
+            std::fs::write(dir.join(name), "").expect("failed to write a scratch file");
+        }
+        let config_path = dir.join("config.toml");
+        std::fs::write(&config_path, "[extensions]\nwv = { codec = \"wavpack\", copy_safe = false }\n")
+            .expect("failed to write the config");
+
+        let config = config::load(&config_path).expect("expected the config to load");
+        let formats = formats::FormatTable::with_configured(config.extensions);
+
+        let mut found = look_for_files(dir.clone(), &Default::default(), &formats);
+        found.sort();
+        assert_eq!(found, vec![dir.join("a.wv"), dir.join("b.wav")]);
+
+        let options = StitchOptions {
+            reencode: true,
+            formats,
+            ..test_options("out.wv")
+        };
+        assert_eq!(options.validate(), Ok(()));
+        assert!(build_ffmpeg_args(Path::new("chapters.txt"), &options).ends_with(&os_args(&["-c:a", "wavpack", "out.wv"])));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_copy_size_estimate_counts_only_the_trimmed_part() {
+        let hundred_seconds = FixedProbe { duration: Some(100.0), format: Some(probe::AudioFormat::default()) };
+        let dir = scratch_dir("trimmed_estimate");
+        let files = ["a.wav", "b.wav"].map(|name| dir.join(name));
+        for file in &files {
+            std::fs::write(file, vec![0u8; 1000]).expect("failed to write a scratch file");
+        }
+        let untrimmed = test_options("out.wav");
+        assert_eq!(estimate_output_size(None, &files, &untrimmed), Some(2000));
+
+        let trim = playlist::Trim { start_secs: Some(25.0), end_secs: Some(75.0) };
+        let trimmed = StitchOptions { trims: std::collections::HashMap::from([(files[1].clone(), trim)]), ..untrimmed };
+        assert_eq!(estimate_output_size(Some(&hundred_seconds), &files, &trimmed), Some(1500));
+        assert_eq!(estimate_output_size(None, &files, &trimmed), None);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_zero_total_duration_fails_before_ffmpeg() {
+        let zero_length = FixedProbe { duration: Some(0.0), format: Some(probe::AudioFormat::default()) };
+
+        // the ffmpeg path doesn't exist - reaching it would be a different error
+        //
+        let result = stitch_files_with_probe(
+            PathBuf::from("./no/such/ffmpeg"),
+            Some(&zero_length),
+            look_for_files(test_path_wav(), &Default::default(), &Default::default()),
+            &test_options("./TEST_ZERO_OUTPUT.wav"),
+        );
+
+        match result {
+            Err(e) => assert!(e.to_string().contains("duration of zero"), "expected the zero-duration error, got: {}", e),
+            Ok(_) => panic!("expected all-zero-duration inputs to be refused"),
+        }
+        assert!(!Path::new("./TEST_ZERO_OUTPUT.wav").exists());
+    }
+
+    #[test]
+    pub fn test_crossfade_longer_than_an_input_fails_before_ffmpeg() {
+        let two_seconds = FixedProbe { duration: Some(2.0), format: Some(probe::AudioFormat::default()) };
+
+        // the ffmpeg path doesn't exist - reaching it would be a different error
+        //
+        let files = look_for_files(test_path_wav(), &Default::default(), &Default::default());
+        let options = StitchOptions { crossfade_secs: 3.0, ..test_options("./TEST_CROSSFADE_OUTPUT.wav") };
+        match stitch_files_with_probe(PathBuf::from("./no/such/ffmpeg"), Some(&two_seconds), files.clone(), &options) {
+            Err(StitcherError::InvalidOptions(errors)) => {
+                assert!(matches!(errors.as_slice(), [options::ValidationError::CrossfadeOverInput(_)]))
+            }
+            Err(e) => panic!("expected the crossfade to be refused, got: {}", e),
+            Ok(_) => panic!("expected a crossfade longer than the inputs to be refused"),
+        }
+
+        let shorter = StitchOptions { crossfade_secs: 1.0, ..options };
+        let result = stitch_files_with_probe(PathBuf::from("./no/such/ffmpeg"), Some(&two_seconds), files, &shorter);
+        if let Err(StitcherError::InvalidOptions(errors)) = result {
+            panic!("expected a shorter crossfade to be allowed, got: {:?}", errors);
+        }
+        assert!(!Path::new("./TEST_CROSSFADE_OUTPUT.wav").exists());
+    }
+
+    #[test]
+    pub fn test_strict_fails_before_ffmpeg() {
+        let no_duration = FixedProbe { duration: None, format: Some(probe::AudioFormat::default()) };
+
+        // the ffmpeg path doesn't exist - reaching it would be a different error
+        //
+        let files = look_for_files(test_path_wav(), &Default::default(), &Default::default());
+        let strict = StitchOptions { strict: true, ..test_options("./TEST_STRICT_OUTPUT.wav") };
+        match stitch_files_with_probe(PathBuf::from("./no/such/ffmpeg"), Some(&no_duration), files.clone(), &strict) {
+            Err(e) => assert!(e.to_string().contains("no duration"), "expected the missing duration to fail, got: {}", e),
+            Ok(_) => panic!("expected --strict to refuse an input without a duration"),
+        }
+        match stitch_files_with_probe(PathBuf::from("./no/such/ffmpeg"), None, files, &strict) {
+            Err(e) => assert!(e.to_string().contains("needs ffprobe"), "expected the missing probe to fail, got: {}", e),
+            Ok(_) => panic!("expected --strict to refuse inputs it can't check"),
+        }
+        assert!(!Path::new("./TEST_STRICT_OUTPUT.wav").exists());
+    }
+
+    #[test]
+    pub fn test_normalize_each_filters_every_input() {
+        let files = ["a.wav", "b.wav", "c.wav"].map(PathBuf::from);
+        let options = StitchOptions {
+            normalize_each: true,
+            ..test_options("out.wav")
+        };
+
+        let args = build_filter_graph_args(&files, Path::new("chapters.txt"), &options);
+        let graph_at = args
+            .iter()
+            .position(|x| x == "-filter_complex")
+            .expect("expected --normalize-each to build a filter graph");
+        let graph = args[graph_at + 1].to_string_lossy();
+
+        for i in 0..files.len() {
+            let segment = format!("[{}:a]{}[s{}]", i, filtergraph::LOUDNORM_EACH, i);
+            assert!(graph.contains(&segment), "expected {} in the filter graph {}", segment, graph);
+        }
+        assert!(args.ends_with(&os_args(&["-map", "[out]", "-c:a", "pcm_s16le", "out.wav"])));
+    }
+
+    #[test]
+    pub fn test_peak_normalize_uses_each_measured_peak() {
+        let files = ["a.wav", "b.wav"].map(PathBuf::from);
+        let options = StitchOptions {
+            normalize_each: true,
+            normalize_method: normalize::NormalizeMethod::Peak,
+            peak_volumes_db: vec![-7.0, -1.0],
+            ..test_options("out.wav")
+        };
+
+        let segments = plan_segments(&files, &options);
+        let gain = |filters: &[&str]| filters.iter().map(|x| String::from(*x)).collect::<Vec<_>>();
+        assert_eq!(
+            segments,
+            vec![
+                filtergraph::Segment::Input { index: 0, filters: gain(&["volume=6.0dB", "aresample=48000"]) },
+                filtergraph::Segment::Input { index: 1, filters: gain(&["volume=0.0dB", "aresample=48000"]) },
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_mismatched_inputs_are_converted_to_the_first_format() {
+        let files = ["a.wav", "b.wav"].map(PathBuf::from);
+        let options = StitchOptions {
+            conform_format: Some(probe::AudioFormat { sample_rate: 44100, channels: 1 }),
+            ..test_options("out.wav")
+        };
+        assert!(options.needs_filter_graph() && options.encodes());
+
+        let conform = |index| filtergraph::Segment::Input {
+            index,
+            filters: vec![String::from("aresample=44100"), String::from("aformat=channel_layouts=mono")],
+        };
+        assert_eq!(plan_segments(&files, &options), vec![conform(0), conform(1)]);
+    }
+
+    #[test]
+    pub fn test_existing_output_is_kept_unless_overwriting() {
+        let dir = scratch_dir("overwrite");
+        let output = dir.join("out.wav");
+        std::fs::write(&output, "keep me").expect("failed to write a scratch file");
+
+        let options = test_options(&output.to_string_lossy());
+        match stitch_files_with_probe(PathBuf::from("ffmpeg"), None, vec![dir.join("a.wav")], &options) {
+            Err(StitcherError::OutputExists(path)) => assert_eq!(path, output),
+            other => panic!("expected the existing output to be refused, got {:?}", other),
+        }
+        assert_eq!(std::fs::read_to_string(&output).ok().as_deref(), Some("keep me"));
+
+        let overwrite = StitchOptions { overwrite: true, ..options };
+        assert!(build_ffmpeg_args(Path::new("chapters.txt"), &overwrite).starts_with(&os_args(&["-y"])));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    pub fn test_concat_list_quotes_awkward_names() {
+        let files = ["/rec/take 1.wav", "/rec/it's 'quoted'.wav", "/rec/#1 \\ back;slash $HOME.wav", "/rec/-dash.wav"]
+            .map(PathBuf::from);
+        assert_eq!(
+            concat_list(&files, &Default::default()).ok().as_deref(),
+            Some(
+                "file '/rec/take 1.wav'\n\
+                 file '/rec/it'\\''s '\\''quoted'\\''.wav'\n\
+                 file '/rec/#1 \\ back;slash $HOME.wav'\n\
+                 file '/rec/-dash.wav'\n"
+            )
+        );
+
+        assert!(concat_list(&[PathBuf::from("/rec/two\nlines.wav")], &Default::default()).is_err());
+    }
+
+    #[test]
+    pub fn test_trimmed_inputs() {
+        let files = ["a.wav", "b.wav"].map(PathBuf::from);
+        let trims = std::collections::HashMap::from([(
+            PathBuf::from("b.wav"),
+            playlist::Trim { start_secs: Some(2.5), end_secs: Some(60.0) },
+        )]);
+        let cwd = std::env::current_dir().expect("expected a working directory");
+        assert_eq!(
+            concat_list(&files, &trims).ok(),
+            Some(format!(
+                "file '{}'\nfile '{}'\ninpoint 2.5\noutpoint 60\n",
+                cwd.join("a.wav").to_string_lossy(),
+                cwd.join("b.wav").to_string_lossy()
+            ))
+        );
+
+        let options = StitchOptions {
+            trims,
+            normalize_each: true,
+            ..test_options("out.wav")
+        };
+        let args = build_filter_graph_args(&files, Path::new("chapters.txt"), &options);
+        let expected = os_args(&["-i", "a.wav", "-ss", "2.5", "-to", "60", "-i", "b.wav"]);
+        assert!(args.windows(expected.len()).any(|x| x == expected));
+    }
+
+    #[test]
+    pub fn test_metadata_tags_go_before_the_output() {
+        let options = StitchOptions {
+            metadata: vec![
+                (String::from("title"), String::from("Live at the Hall")),
+                (String::from("artist"), String::from("The Band")),
+            ],
+            ..test_options("out.mp3")
+        };
+        let expected = ["-metadata", "title=Live at the Hall", "-metadata", "artist=The Band", "out.mp3"];
+        assert!(build_ffmpeg_args(Path::new("chapters.txt"), &options).ends_with(&os_args(&expected)));
+
+        let options = StitchOptions {
+            extra_ffmpeg_args: ["-ar", "44100"].map(String::from).to_vec(),
+            ..options
+        };
+        let expected = ["artist=The Band", "-ar", "44100", "out.mp3"];
+        assert!(build_ffmpeg_args(Path::new("chapters.txt"), &options).ends_with(&os_args(&expected)));
+
+        // copied tags replace whatever ffmpeg would have taken from the first input
+        //
+        let copied = StitchOptions { copy_metadata: Some(tags::CopyMetadata::Merge), ..options };
+        let expected = ["-map_metadata", "-1", "-metadata", "title=Live at the Hall"];
+        assert!(build_ffmpeg_args(Path::new("chapters.txt"), &copied).windows(4).any(|x| x == os_args(&expected)));
+    }
+
+    #[test]
+    pub fn test_scratch_files_are_unique_and_cleaned_up() {
+        let first = scratch_file("list", "file a.wav\n").expect("expected a scratch file");
+        let second = scratch_file("list", "file b.wav\n").expect("expected a scratch file");
+        assert_ne!(&*first, &*second);
+        assert!(first.starts_with(std::env::temp_dir()));
+        assert_eq!(std::fs::read_to_string(&first).ok().as_deref(), Some("file a.wav\n"));
+
+        let path = first.to_path_buf();
+        drop(first);
+        assert!(!path.exists(), "expected the scratch file to be deleted when it drops");
+
+        let mut kept = second;
+        keep_scratch_file(&mut kept, "list");
+        let path = kept.to_path_buf();
+        drop(kept);
+        assert!(path.exists(), "expected a kept scratch file to be left behind");
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    pub fn test_long_filter_graph_moves_to_a_script_file() {
+        let options = StitchOptions {
+            normalize_each: true,
+            ..test_options("out.wav")
+        };
+        let script_path = Path::new("graph.txt");
+
+        let few = ["a.wav", "b.wav"].map(PathBuf::from);
+        let mut args = build_filter_graph_args(&few, Path::new("chapters.txt"), &options);
+        assert_eq!(move_long_graph_to_script(&mut args, script_path), None);
+        assert!(args.iter().any(|x| x == "-filter_complex"));
+
+        let many = (0..2000)
+            .map(|i| PathBuf::from(format!("/recordings/session/take-{:04}.wav", i)))
+            .collect::<Vec<_>>();
+        let mut args = build_filter_graph_args(&many, Path::new("chapters.txt"), &options);
+        let inline_graph = args[args.iter().position(|x| x == "-filter_complex").expect("an inline graph") + 1].clone();
+
+        let script = move_long_graph_to_script(&mut args, script_path);
+        assert_eq!(script.as_deref(), inline_graph.to_str());
+        assert!(!args.iter().any(|x| x == "-filter_complex"));
+        let flag_at = args
+            .iter()
+            .position(|x| x == "-filter_complex_script")
+            .expect("expected the script-file form above the threshold");
+        assert_eq!(args[flag_at + 1], script_path.as_os_str());
+    }
+
+    #[test]
+    pub fn test_chaptered_mka_args() {
+        let options = StitchOptions {
+            chapters: true,
+            ..test_options("book.mka")
+        };
+        assert_eq!(options.validate(), Ok(()));
+
+        let args = build_ffmpeg_args(Path::new("chapters.txt"), &options);
+        let expected = [
+            "-n", "-vn", "-f", "concat", "-safe", "0", "-protocol_whitelist", "file,pipe", "-i", "pipe:0",
+            "-f", "ffmetadata", "-i", "chapters.txt",
+            "-map_metadata", "1", "-map_chapters", "1",
+            "-c", "copy", "-f", "matroska", "book.mka",
+        ];
+        assert_eq!(args, os_args(&expected));
+
+        let wav = StitchOptions {
+            chapters: true,
+            ..test_options("book.wav")
+        };
+        assert_eq!(
+            wav.validate(),
+            Err(vec![options::ValidationError::ChaptersNotSupportedByOutput(String::from("wav"))])
+        );
+    }
+
+    #[test]
+    pub fn test_stdout_output_names_the_muxer() {
+        let options = StitchOptions {
+            format: Some(String::from("m4a")),
+            ..test_options(options::STDOUT_OUTPUT)
+        };
+        assert_eq!(options.validate(), Ok(()));
+        let args = build_ffmpeg_args(Path::new("chapters.txt"), &options);
+        let expected = ["-c", "copy", "-f", "ipod", "-movflags", "frag_keyframe+empty_moov", "pipe:1"];
+        assert!(args.ends_with(&os_args(&expected)), "got {:?}", args);
+
+        let unformatted = StitchOptions {
+            progress_bar: true,
+            ..test_options(options::STDOUT_OUTPUT)
+        };
+        assert_eq!(
+            unformatted.validate(),
+            Err(vec![
+                options::ValidationError::StdoutNeedsFormat,
+                options::ValidationError::NeedsOutputFile(String::from("--progress")),
+            ])
+        );
+    }
+
+    #[test]
+    pub fn test_planned_args_follow_the_stitch_path() {
+        let files = ["a.wav", "b.wav"].map(PathBuf::from);
+
+        let copy = test_options("out.wav");
+        assert_eq!(planned_ffmpeg_args(&files, &copy), build_ffmpeg_args(Path::new(PLANNED_CHAPTERS_FILE_PATH), &copy));
+
+        let filtered = StitchOptions {
+            normalize_each: true,
+            ..test_options("out.wav")
+        };
+        assert_eq!(planned_ffmpeg_args(&files, &filtered), build_filter_graph_args(&files, Path::new(PLANNED_CHAPTERS_FILE_PATH), &filtered));
+    }
+
+    #[test]
+    pub fn test_pre_and_post_gaps_add_silence() {
+        let files = ["a.wav", "b.wav"].map(PathBuf::from);
+        let options = StitchOptions {
+            pre_gap_secs: 1.0,
+            post_gap_secs: 2.5,
+            ..test_options("out.wav")
+        };
+
+        let segments = plan_segments(&files, &options);
+        assert_eq!(segments.len(), files.len() + 2);
+        assert_eq!(segments.first(), Some(&filtergraph::Segment::Silence { secs: 1.0 }));
+        assert_eq!(segments.last(), Some(&filtergraph::Segment::Silence { secs: 2.5 }));
+        assert_eq!(
+            filtergraph::silence_secs(&segments),
+            3.5,
+            "expected the output to grow by exactly the pre and post gaps"
+        );
+
+        // zero gaps are a no-op, and don't need a filter graph at all
+        //
+        let no_gaps = test_options("out.wav");
+        assert_eq!(filtergraph::silence_secs(&plan_segments(&files, &no_gaps)), 0.0);
+        assert!(!no_gaps.needs_filter_graph());
+    }
+
+    #[test]
+    pub fn test_gap_goes_between_inputs_only() {
+        let files = ["a.wav", "b.wav", "c.wav"].map(PathBuf::from);
+        let options = StitchOptions {
+            gap_secs: 2.0,
+            ..test_options("out.wav")
+        };
+        assert!(options.needs_filter_graph());
+
+        let input = |index| filtergraph::Segment::Input { index, filters: vec![] };
+        let gap = filtergraph::Segment::Silence { secs: 2.0 };
+        assert_eq!(
+            plan_segments(&files, &options),
+            vec![input(0), gap.clone(), input(1), gap, input(2)]
+        );
+        assert_eq!(options.spacing_secs(), 2.0);
+    }
+
+    #[test]
+    pub fn test_chapter_files_are_written_next_to_the_output() {
+        let dir = scratch_dir("chapter_files");
+        let options = StitchOptions {
+            cue: true,
+            chapters_out: Some(dir.join("chapters.txt")),
+            ..test_options(&dir.join("album.flac").to_string_lossy())
+        };
+        let chapters = chapters::from_inputs(&["01 a.flac", "02 b.flac"].map(PathBuf::from), &[10.0, 20.0], 0.0, 0.0);
+        write_chapter_files(&options, Some(&chapters)).expect("expected the chapter files to be written");
+
+        let cue = std::fs::read_to_string(dir.join("album.cue")).expect("expected a cue sheet next to the output");
+        assert!(cue.starts_with("FILE \"album.flac\" WAVE\n") && cue.contains("INDEX 01 00:10:00"), "got: {}", cue);
+        let ffmetadata = std::fs::read_to_string(dir.join("chapters.txt")).expect("expected the chapters file");
+        assert_eq!(ffmetadata, chapters::ffmetadata(&chapters));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_format_transcodes_inputs_in_other_formats() {
+        let options = StitchOptions {
+            format: Some(String::from("flac")),
+            ..test_options("out.flac")
+        };
+        assert!(!transcodes_into_format(&["a.flac", "b.FLAC"].map(PathBuf::from), &options));
+        assert!(transcodes_into_format(&["a.flac", "b.wav"].map(PathBuf::from), &options));
+        assert!(!transcodes_into_format(&["a.wav"].map(PathBuf::from), &test_options("out.flac")));
+    }
+
+    #[test]
+    pub fn test_gapless_decodes_every_input() {
+        let files = ["01.mp3", "02.mp3"].map(PathBuf::from);
+        let options = StitchOptions {
+            gapless: true,
+            ..test_options("album.mp3")
+        };
+        assert!(options.needs_filter_graph() && options.encodes());
+
+        let args = planned_ffmpeg_args(&files, &options);
+        assert!(!args.iter().any(|x| x == "concat" || x == "copy"), "expected no concat demuxer or stream copy: {:?}", args);
+        assert!(args.windows(2).any(|x| x[0] == "-c:a" && x[1] == "libmp3lame"), "expected an mp3 re-encode: {:?}", args);
+    }
+
+    #[test]
+    pub fn test_input_format_goes_before_each_input() {
+        let files = ["a.wav", "b.wav"].map(PathBuf::from);
+        let options = StitchOptions {
+            input_format: Some(String::from("s16le")),
+            ..test_options("out.wav")
+        };
+        assert!(options.needs_filter_graph());
+
+        let args = build_filter_graph_args(&files, Path::new("chapters.txt"), &options);
+        for file in &files {
+            let input_at = args
+                .iter()
+                .position(|x| x == file.as_os_str())
+                .expect("expected every file to be an input");
+            assert_eq!(
+                args[input_at - 3..input_at],
+                os_args(&["-f", "s16le", "-i"]),
+                "expected `-f s16le` right before `-i {}`",
+                file.to_string_lossy()
+            );
+        }
+    }
+
+    //
+
+    fn test_options(output: &str) -> StitchOptions {
+        StitchOptions {
+            output: PathBuf::from(output),
+            ..Default::default()
+        }
+    }
+
+    /// A stand-in ffmpeg in `dir` that runs `script` (after `#!/bin/sh`), for tests of what happens around a run.
+    #[cfg(unix)]
+    fn mock_ffmpeg(dir: &Path, script: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.join("ffmpeg");
+        std::fs::write(&path, format!("#!/bin/sh\n{}", script)).expect("failed to write the mock ffmpeg");
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).expect("failed to make the mock ffmpeg executable");
+        path
+    }
+
+    /// A fresh, empty directory under the OS temp dir, for tests that need real files.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stitcher_test_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("failed to create a scratch directory");
+        dir
+    }
+
+    fn test_path_wav() -> std::path::PathBuf {
+        let sounds_dir_path = std::path::PathBuf::from("./test/stitcher/sounds/wav");
+        std::fs::exists(&sounds_dir_path)
+            .expect("this test expects to be run from the project root");
+        sounds_dir_path
+    }
+
+    fn _test_path_mp3() -> std::path::PathBuf {
+        let sounds_dir_path = std::path::PathBuf::from("./test/stitcher/sounds/mp3");
+        std::fs::exists(&sounds_dir_path)
+            .expect("this test expects to be run from the project root");
+        sounds_dir_path
+    }
+}
@@ -0,0 +1,110 @@
+use std::path::{Path, PathBuf};
+
+use ffmpeg_next::{codec, encoder, format, media};
+
+use crate::{error::StitcherError, options::StitchOptions};
+
+/// Join `files`, in order, into `options.output` in-process with libav, stream copying the first audio
+/// stream of each, the way ffmpeg's concat demuxer would. they all have to be in the same codec. returns
+/// the path of the joined file.
+pub fn stitch(files: &[PathBuf], options: &StitchOptions) -> Result<PathBuf, StitcherError> {
+    if files.is_empty() {
+        return Err(StitcherError::NoInputs(String::from("found no files!")));
+    }
+    if !options.overwrite && options.output.exists() {
+        return Err(StitcherError::OutputExists(options.output.clone()));
+    }
+    ffmpeg_next::init().map_err(|e| StitcherError::Other(format!("failed to initialize libav: {}", e)))?;
+
+    let result = remux(files, &options.output);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&options.output);
+    }
+    result.map(|()| options.output.clone())
+}
+
+fn remux(files: &[PathBuf], output: &Path) -> Result<(), StitcherError> {
+    let mut octx = format::output(output).map_err(|e| libav_error("creating", output, e))?;
+
+    // the output's one stream takes its codec parameters from the first input's
+    //
+    let codec_id = {
+        let ictx = open(&files[0])?;
+        let stream = audio_stream(&ictx, &files[0])?;
+        let mut ost = octx.add_stream(encoder::find(codec::Id::None)).map_err(|e| libav_error("creating", output, e))?;
+        ost.set_parameters(stream.parameters());
+        // the input container's codec tag can mean something else in the output's, so let the muxer pick
+        //
+        // SAFETY: `ost` borrows `octx`, which owns the stream, so its codec parameters are allocated and valid
+        // for this write. nothing else holds a reference to them, and `codec_tag` is a plain integer field
+        unsafe {
+            (*ost.parameters().as_mut_ptr()).codec_tag = 0;
+        }
+        stream.parameters().id()
+    };
+    octx.write_header().map_err(|e| libav_error("writing", output, e))?;
+    // the muxer can change the time base when it writes the header, so it's only read after
+    //
+    let out_time_base = octx.stream(0).map(|x| x.time_base()).ok_or_else(|| {
+        StitcherError::Other(format!("failed writing {}: it has no stream", output.to_string_lossy()))
+    })?;
+
+    // each input's timestamps start where the last input ended, in the output's time base
+    //
+    let mut offset = 0;
+    for file in files {
+        log::debug!("appending {}", file.to_string_lossy());
+        let mut ictx = open(file)?;
+        let (index, file_codec_id, in_time_base) = {
+            let stream = audio_stream(&ictx, file)?;
+            (stream.index(), stream.parameters().id(), stream.time_base())
+        };
+        if file_codec_id != codec_id {
+            return Err(StitcherError::Other(format!(
+                "{} is {:?}, but {} is {:?}: the libav backend only stream copies, so every input has to be in the same codec",
+                file.to_string_lossy(),
+                file_codec_id,
+                files[0].to_string_lossy(),
+                codec_id
+            )));
+        }
+
+        let mut end = offset;
+        for (stream, mut packet) in ictx.packets() {
+            // the remux runs in-process, so Ctrl+C has no child to kill: it's noticed here, and `stitch` removes
+            // the partial output
+            //
+            if crate::interrupt::requested() {
+                return Err(StitcherError::Interrupted);
+            }
+            if stream.index() != index {
+                continue;
+            }
+            packet.rescale_ts(in_time_base, out_time_base);
+            packet.set_pts(packet.pts().map(|x| x + offset));
+            packet.set_dts(packet.dts().map(|x| x + offset));
+            end = end.max(packet.pts().or(packet.dts()).unwrap_or(end) + packet.duration());
+            packet.set_position(-1);
+            packet.set_stream(0);
+            packet.write_interleaved(&mut octx).map_err(|e| libav_error("writing", output, e))?;
+        }
+        offset = end;
+    }
+
+    octx.write_trailer().map_err(|e| libav_error("writing", output, e))
+}
+
+fn open(file: &Path) -> Result<format::context::Input, StitcherError> {
+    format::input(file).map_err(|e| libav_error("opening", file, e))
+}
+
+/// The stream libav picks as `file`'s main audio.
+fn audio_stream<'a>(ictx: &'a format::context::Input, file: &Path) -> Result<format::stream::Stream<'a>, StitcherError> {
+    ictx.streams()
+        .best(media::Type::Audio)
+        .ok_or_else(|| StitcherError::Other(format!("{} has no audio stream", file.to_string_lossy())))
+}
+
+fn libav_error(doing: &str, file: &Path, error: ffmpeg_next::Error) -> StitcherError {
+    StitcherError::Other(format!("failed {} {}: {}", doing, file.to_string_lossy(), error))
+}
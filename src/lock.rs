@@ -0,0 +1,80 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+
+/// Process exit status used when another run already holds the lockfile.
+pub const EXIT_LOCK_HELD: u8 = 75;
+
+#[derive(Debug)]
+pub enum LockError {
+    /// Another handle (usually another stitcher process) holds the lock.
+    AlreadyHeld(PathBuf),
+    Io(PathBuf, std::io::Error),
+}
+
+impl std::fmt::Display for LockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LockError::AlreadyHeld(path) => {
+                write!(f, "lockfile {} is held by another run", path.to_string_lossy())
+            }
+            LockError::Io(path, e) => {
+                write!(f, "failed to lock {}: {:?}", path.to_string_lossy(), e)
+            }
+        }
+    }
+}
+
+/// An exclusive lock on a file, held until this value is dropped.
+///
+/// The lockfile itself is left in place after the run: removing it would let a
+/// third run lock a fresh inode while a second one still holds the old one.
+#[derive(Debug)]
+pub struct Lockfile {
+    _file: File,
+}
+
+/// Try to take an exclusive OS lock on `path`, creating the file if needed.
+/// Never blocks - if the lock is already held this returns `LockError::AlreadyHeld` straight away.
+pub fn try_acquire(path: &Path) -> Result<Lockfile, LockError> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .map_err(|e| LockError::Io(path.to_path_buf(), e))?;
+
+    match file.try_lock() {
+        Ok(()) => Ok(Lockfile { _file: file }),
+        Err(TryLockError::WouldBlock) => Err(LockError::AlreadyHeld(path.to_path_buf())),
+        Err(TryLockError::Error(e)) => Err(LockError::Io(path.to_path_buf(), e)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_second_lock_attempt_fails_fast() {
+        let lock_path = std::env::temp_dir().join(format!("stitcher_lock_test_{}.lock", std::process::id()));
+
+        let held = try_acquire(&lock_path).expect("test expected to acquire a fresh lock");
+
+        let started = std::time::Instant::now();
+        match try_acquire(&lock_path) {
+            Err(LockError::AlreadyHeld(_)) => (),
+            other => panic!("expected the second lock attempt to report AlreadyHeld, got {:?}", other),
+        }
+        assert!(
+            started.elapsed() < std::time::Duration::from_secs(1),
+            "expected the second lock attempt to fail without blocking"
+        );
+
+        // once the first handle is dropped the lock can be taken again
+        //
+        drop(held);
+        try_acquire(&lock_path).expect("expected the lock to be free after the holder was dropped");
+
+        let _ = std::fs::remove_file(lock_path);
+    }
+}
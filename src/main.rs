@@ -1,151 +1,1289 @@
-#![feature(fs_try_exists)]
-#![feature(exit_status_error)]
+mod cli;
+mod dotenv;
+#[cfg(test)]
+mod fixed_probe;
+mod interactive;
+mod lock;
+mod manpage;
+mod summary;
 
 use chrono::prelude::*;
-use std::{path::PathBuf, process::Command};
-use clap::Parser;
+use std::{
+    collections::HashMap,
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
+use clap::{CommandFactory, Parser};
+use cli::{Cli, Command, CompletionsArgs, FetchArgs, ProbeArgs, SplitArgs, StitchArgs};
+use stitcher::{
+    config, dedupe, discovery, error::StitcherError, fetch, ffmpeg, find_files, formats, interrupt, is_up_to_date, look_for_files, options::{self, StitchOptions}, order_report, playlist, probe, progress, split,
+    select_only, selection, watch, wav, waveform, StitchJob, Stitched, Stitcher,
+};
+
+fn main() -> ExitCode {
+    // the dotfile only fills in environment variables, so anything given on the command line still wins
+    //
+    if let Err(e) = dotenv::load(Path::new(dotenv::DOTFILE_NAME)) {
+        eprintln!("Error: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    let cli = Cli::parse();
+    init_logging(&cli);
+    if let Err(e) = interrupt::install() {
+        log::warn!("{}", e);
+    }
+
+    // with --json, a stitch that fails still prints its result, whatever stage it failed at
+    //
+    let failure_output = match &cli.command {
+        Command::Stitch(cli_args) if cli_args.json && !cli_args.dry_run => Some(cli_args.out.clone()),
+        _ => None,
+    };
+    let result = match cli.command {
+        Command::Stitch(cli_args) if cli_args.dry_run => run_plan(cli_args),
+        Command::Stitch(cli_args) => {
+            // the lock is released when `_lock` drops, once the stitch is done
+            //
+            let _lock = match acquire_lock(&cli_args) {
+                Ok(lock) => lock,
+                Err(code) => return code,
+            };
+            run_stitch(cli_args)
+        }
+        Command::Probe(args) => run_probe(args),
+        Command::Plan(cli_args) => run_plan(cli_args),
+        Command::FetchFfmpeg(args) => run_fetch(args),
+        Command::Split(args) => run_split(args),
+        Command::Completions(args) => run_completions(args, &mut std::io::stdout()),
+        Command::Man => manpage::render(&mut std::io::stdout()).map_err(|e| StitcherError::io("printing the man page", e)),
+    };
 
-#[derive(Parser, Debug)]
-struct CliArgs {
-    /// Directory to look for files in.
-    #[arg(short, long)]
-    input_path: PathBuf,
+    // Ctrl+C doesn't exit the process, so everything could clean up on the way out. whatever error the
+    // interrupted work ended with, it's the interruption that counts
+    //
+    let result = match interrupt::requested() {
+        true => Err(StitcherError::Interrupted),
+        false => result,
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            if let Some(output) = &failure_output {
+                println!("{}", failure_json(&e, output.as_deref()));
+            }
+            eprintln!("Error: {}", e);
+            if let StitcherError::FfmpegNotFound(_) = e {
+                eprintln!("run `stitcher fetch-ffmpeg` to download one");
+            }
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
 
-    /// (optional) Name of the output file. file type should match the input file types.
-    #[arg(short, long)]
-    out: Option<PathBuf>,
+/// Send log messages to stderr, as many as `-q`/`-v` ask for: errors only, a summary (the default), then the
+/// files found, concat list and ffmpeg commands with `-v`, and everything with `-vv`. `RUST_LOG` overrides it.
+fn init_logging(cli: &Cli) {
+    let level = match (cli.quiet, cli.verbose) {
+        (true, _) => log::LevelFilter::Error,
+        (false, 0) => log::LevelFilter::Info,
+        (false, 1) => log::LevelFilter::Debug,
+        (false, _) => log::LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .format(|buf, record| match record.level() {
+            log::Level::Error => writeln!(buf, "error: {}", record.args()),
+            log::Level::Warn => writeln!(buf, "warning: {}", record.args()),
+            _ => writeln!(buf, "{}", record.args()),
+        })
+        .init();
 }
 
-fn main() -> Result<(), String> {
-    let cli_args = CliArgs::parse();
+/// Whether anything but errors and the result should be printed, ffmpeg's own output included.
+fn is_quiet(cli_args: &StitchArgs) -> bool {
+    cli_args.summary_only || !log::log_enabled!(log::Level::Info)
+}
 
-    let ffmpeg_bin_path = find_valid_ffmpeg_binary(vec![
-        PathBuf::from("/bin/ffmpeg"),
-        PathBuf::from("./vendor/ffmpeg/ffmpeg"),
-    ])?;
+/// Where the messages for a person (rather than the result, for a script) go: stderr, unless `-q` is given.
+fn messages() -> Box<dyn std::io::Write> {
+    match log::log_enabled!(log::Level::Info) {
+        true => Box::new(std::io::stderr()),
+        false => Box::new(std::io::sink()),
+    }
+}
 
-    let output_file_name = match cli_args.out {
-        Some(out) => out,
-        None => {
-            let date = Local::now().format("%d-%h-%Y %H:%M");
-            PathBuf::from(format!("STITCH_OUTPUT_{}.wav", date))
+/// Take `--lockfile`, if one was given. when it can't be had, the error is printed and the exit code to use returned.
+fn acquire_lock(cli_args: &StitchArgs) -> Result<Option<lock::Lockfile>, ExitCode> {
+    match &cli_args.lockfile {
+        None => Ok(None),
+        Some(path) => match lock::try_acquire(path) {
+            Ok(lock) => Ok(Some(lock)),
+            Err(e @ lock::LockError::AlreadyHeld(_)) => {
+                eprintln!("Error: {}", e);
+                Err(ExitCode::from(lock::EXIT_LOCK_HELD))
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                Err(ExitCode::FAILURE)
+            }
+        },
+    }
+}
+
+fn run_stitch(cli_args: StitchArgs) -> Result<(), StitcherError> {
+    let cli_args = with_file_lists(cli_args)?;
+    let config = load_config(&cli_args)?;
+    let cli_args = with_config_defaults(cli_args, &config.defaults);
+    // plain WAVs can be joined without ffmpeg, so not finding it is only an error once a stitch needs it
+    //
+    let stitcher = match find_stitcher(cli_args.ffmpeg_path.as_deref(), &config.defaults) {
+        Err(StitcherError::FfmpegNotFound(checked)) => Stitcher::without_ffmpeg(checked),
+        found => found?,
+    };
+    let stitch_options = stitch_options(&cli_args, config)?;
+
+    if cli_args.batch {
+        return stitch_batch(&cli_args, &stitcher, &stitch_options);
+    }
+    if !cli_args.watch {
+        return stitch_once(&cli_args, &stitcher, &stitch_options).map(|_| ());
+    }
+
+    // an unnamed output's name is only known once each stitch fills in its template, so what each one writes is
+    // left out of the watch as it's written
+    //
+    let watch_config = watch::WatchConfig {
+        poll_interval: Duration::from_secs(cli_args.watch_interval),
+        ignore: vec![],
+        walk: walk_options(&cli_args),
+    };
+
+    // once the first stitch has written the output, each later one replaces it. Ctrl-C stops any in-flight
+    // stitch (see `interrupt::install`), and then the watch
+    //
+    let mut stitch_options = stitch_options;
+    watch::watch(&cli_args.input_paths(), &watch_config, interrupt::flag(), || {
+        let written = stitch_once(&cli_args, &stitcher, &stitch_options)?;
+        stitch_options.overwrite = true;
+        Ok(written)
+    })?;
+    match interrupt::requested() {
+        true => Err(StitcherError::Interrupted),
+        false => Ok(()),
+    }
+}
+
+/// Print each input's duration and audio format, one tab-separated line per file.
+fn run_probe(args: ProbeArgs) -> Result<(), StitcherError> {
+    // ffprobe usually sits next to ffmpeg, but probing doesn't need ffmpeg itself
+    //
+    let ffmpeg_bin_path = find_stitcher(args.ffmpeg_path.as_deref(), &Default::default())
+        .and_then(|stitcher| stitcher.ffmpeg_bin_path().map(Path::to_path_buf))
+        .unwrap_or_else(|_| PathBuf::from("ffmpeg"));
+    let probe = probe::find_probe(&ffmpeg_bin_path, None, None)
+        .ok_or_else(|| StitcherError::FfprobeNotFound(String::from("failed to find ffprobe")))?;
+
+    let walk_options = discovery::WalkOptions {
+        recursive: args.recursive,
+        max_depth: args.max_depth.map(|x| x as usize),
+        ..Default::default()
+    };
+    let files = look_for_files(args.input_path.clone(), &walk_options, &Default::default());
+    if files.is_empty() {
+        return Err(StitcherError::NoInputs(String::from("found no files!")));
+    }
+
+    // the offsets only add up if every file that would be stitched has a duration
+    //
+    if args.durations {
+        let probe::ProbedInputs { files, durations, skipped } = probe::probe_inputs(probe.as_ref(), files, false)?;
+        let durations = durations.ok_or_else(|| {
+            StitcherError::BadInput(String::from(
+                "--durations needs the duration of every input, but some couldn't be probed",
+            ))
+        })?;
+
+        // a stitch only uses the part of a playlist entry between its trims, so that's all that counts here too
+        //
+        let trims = playlist_trims(&args.input_path);
+        let durations = files
+            .iter()
+            .zip(durations)
+            .map(|(file, duration)| trims.get(file).map_or(duration, |trim| trim.trimmed_secs(duration)))
+            .collect::<Vec<_>>();
+        print!("{}", duration_report(&files, &durations, &skipped));
+        return Ok(());
+    }
+
+    for file in &files {
+        println!("{}", probe_line(probe.as_ref(), file));
+    }
+
+    // the same check a stitch makes before deciding whether it can stream copy
+    //
+    if let Some(mismatch) = probe::find_mismatch(probe.as_ref(), &files) {
+        log::warn!("the inputs don't match ({}), so stitching them re-encodes", mismatch);
+    }
+    Ok(())
+}
+
+/// Download ffmpeg for this platform into `--dest`, and print where it went.
+fn run_fetch(args: FetchArgs) -> Result<(), StitcherError> {
+    let build = fetch::build_for(std::env::consts::OS, std::env::consts::ARCH).ok_or_else(|| {
+        StitcherError::Other(format!(
+            "no static ffmpeg build to download for {}-{}. see vendor/README.md to set one up by hand",
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        ))
+    })?;
+
+    log::info!("downloading {}", build.archive_url());
+    let ffmpeg_bin_path = fetch::fetch(&build, &args.dest)?;
+    log::info!("fetched ffmpeg and ffprobe");
+    println!("{}", ffmpeg_bin_path.to_string_lossy());
+    Ok(())
+}
+
+/// Write the completion script for `args.shell` to `out`.
+fn run_completions(args: CompletionsArgs, out: &mut dyn std::io::Write) -> Result<(), StitcherError> {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(args.shell, &mut command, name, out);
+    Ok(())
+}
+
+/// Split `args.input` into numbered parts, and print the path of each.
+fn run_split(args: SplitArgs) -> Result<(), StitcherError> {
+    let stitcher = find_stitcher(args.ffmpeg_path.as_deref(), &Default::default())?;
+    if !args.input.is_file() {
+        return Err(StitcherError::NoInputs(format!("{} isn't a file", args.input.to_string_lossy())));
+    }
+
+    let points = match (args.every, &args.chapters) {
+        (Some(secs), _) => split::SplitPoints::Every(secs),
+        (None, Some(chapters_path)) => {
+            let contents = std::fs::read_to_string(chapters_path)
+                .map_err(|e| StitcherError::io(format!("reading {}", chapters_path.to_string_lossy()), e))?;
+            split::SplitPoints::At(split::parse_chapter_starts(&contents))
+        }
+        (None, None) => {
+            let silence = split::SilenceSettings {
+                noise_db: args.silence_db,
+                min_secs: args.silence_min,
+            };
+            split::SplitPoints::At(split::silence_split_points(stitcher.ffmpeg_bin_path()?, &args.input, &silence)?)
+        }
+    };
+
+    std::fs::create_dir_all(&args.out_dir)
+        .map_err(|e| StitcherError::io(format!("creating {}", args.out_dir.to_string_lossy()), e))?;
+    let pattern = split::output_pattern(&args.input, &args.out_dir);
+
+    // parts from an earlier split would be listed with this one's, or mixed in with them if it makes fewer, so
+    // they all go first - or, without --overwrite, nothing does
+    //
+    let stale = split::existing_parts(&pattern)?;
+    if let Some(part) = stale.first().filter(|_| !args.overwrite) {
+        return Err(StitcherError::OutputExists(part.clone()));
+    }
+    for part in stale {
+        std::fs::remove_file(&part).map_err(|e| StitcherError::io(format!("removing {}", part.to_string_lossy()), e))?;
+    }
+
+    if matches!(&points, split::SplitPoints::At(times) if times.is_empty()) {
+        log::warn!("found nowhere to cut {}, so it's copied whole", args.input.to_string_lossy());
+    }
+    let run = ffmpeg::run(
+        stitcher.ffmpeg_bin_path()?,
+        &split::segment_args(&args.input, &points, &pattern, args.overwrite),
+        ffmpeg::Progress::Inherit,
+        true,
+    )?;
+    run.check()?;
+
+    // the segment muxer numbers the parts itself, so find what it wrote
+    //
+    for part in split::existing_parts(&pattern)? {
+        println!("{}", part.to_string_lossy());
+    }
+    Ok(())
+}
+
+/// `path` `\t` `duration in seconds` `\t` `sample rate` `\t` `channels` `\t` `codec`, with `-` for anything that
+/// couldn't be probed.
+fn probe_line(probe: &dyn probe::Probe, file: &Path) -> String {
+    let duration = match probe.duration(file) {
+        Ok(secs) => format!("{:.3}", secs),
+        Err(e) => {
+            log::warn!("{}", e);
+            String::from("-")
         }
     };
+    let (sample_rate, channels, codec) = match probe.stream_params(file) {
+        Ok(params) => (params.format.sample_rate.to_string(), params.format.channels.to_string(), params.codec),
+        Err(_) => (String::from("-"), String::from("-"), String::from("-")),
+    };
+
+    format!("{}\t{}\t{}\t{}\t{}", file.to_string_lossy(), duration, sample_rate, channels, codec)
+}
 
-    let files_to_stitch = look_for_files(cli_args.input_path);
-    if files_to_stitch.len() == 0 {
-        return Err(String::from("found no files!"));
+/// `start` `\t` `duration` `\t` `path` for each of `files`, where `start` is where it begins in the stitched
+/// output, then `total` `\t` `length of the output`, then `skipped` `\t` `why` for each input a stitch would leave
+/// out. times are `HH:MM:SS`.
+fn duration_report(files: &[PathBuf], durations: &[f64], skipped: &[probe::ProbeError]) -> String {
+    let mut report = String::new();
+    let mut start_secs = 0.0;
+    for (file, duration) in files.iter().zip(durations) {
+        report.push_str(&format!(
+            "{}\t{}\t{}\n",
+            progress::format_timestamp(start_secs as u64),
+            progress::format_timestamp(*duration as u64),
+            file.to_string_lossy()
+        ));
+        start_secs += duration;
     }
+    report.push_str(&format!("total\t{}\n", progress::format_timestamp(start_secs as u64)));
+    for e in skipped {
+        report.push_str(&format!("skipped\t{}\n", e));
+    }
+    report
+}
+
+/// Print the inputs in the order they'd be stitched, the concat list, then the ffmpeg command, without running it.
+fn run_plan(cli_args: StitchArgs) -> Result<(), StitcherError> {
+    let cli_args = with_file_lists(cli_args)?;
+    let config = load_config(&cli_args)?;
+    let cli_args = with_config_defaults(cli_args, &config.defaults);
+    let stitcher = find_stitcher(cli_args.ffmpeg_path.as_deref(), &config.defaults)
+        .unwrap_or_else(|_| Stitcher::without_ffmpeg(vec![]));
+    let ffmpeg_bin_path = stitcher.ffmpeg_bin_path().map(Path::to_path_buf).unwrap_or_else(|_| PathBuf::from("ffmpeg"));
+
+    let stitch_options = stitch_options(&cli_args, config)?;
+    let files = discover_files(&cli_args, &stitcher, &stitch_options)?;
+    let stitch_options = with_default_output(&cli_args, &stitch_options, &files)?;
+    let input_format = stitch_options.input_format.as_deref();
+    let probe = probe::find_probe(&ffmpeg_bin_path, stitch_options.probe_timeout, input_format);
+
+    let free_bytes = free_space(&stitch_options);
+
+    // with --max-part-duration, each part is a stitch of its own, with its own command
+    //
+    let jobs = match cli_args.max_part_duration {
+        Some(max_secs) => parts(&cli_args, &stitcher, &stitch_options, files, max_secs)?,
+        None => vec![StitchJob::new(files, stitch_options)],
+    };
+    let mut size_bytes = Some(0);
+    for job in jobs {
+        let StitchJob { files, options: mut stitch_options } = job;
+        stitcher::copy_input_tags(probe.as_deref(), &files, &mut stitch_options)?;
+
+        // a fade out starts that long before the end, which a stitch works out from the probed inputs, and so does
+        // the plan of one
+        //
+        let stitch_options = match stitch_options.fade_out_secs > 0.0 {
+            false => stitch_options,
+            true => {
+                let duration = probe
+                    .as_deref()
+                    .and_then(|probe| stitcher::planned_output_secs(probe, &files, &stitch_options))
+                    .ok_or_else(|| {
+                        StitcherError::BadInput(String::from(
+                            "--fade-out needs the duration of every input, but the inputs couldn't be probed",
+                        ))
+                    })?;
+                StitchOptions { output_duration_secs: Some(duration), ..stitch_options }
+            }
+        };
+
+        // GStreamer gets each file in its pipeline, and the filter graph path hands ffmpeg each file directly,
+        // so there's no list to show for either. the options are validated by now, so a backend that isn't built in
+        // never gets this far
+        //
+        let plan = match stitch_options.backend {
+            #[cfg(feature = "gstreamer")]
+            options::Backend::Gstreamer => {
+                let pipeline = stitcher::gstreamer::pipeline_description(&files, &stitch_options.output)?;
+                plan_text(&files, None, &format!("gst-launch-1.0 {}", pipeline))
+            }
+            _ => {
+                let args = stitcher::planned_ffmpeg_args(&files, &stitch_options);
+                let concat_list = match stitch_options.needs_filter_graph() {
+                    true => None,
+                    false => Some(stitcher::concat_list(&files, &stitch_options.trims)?),
+                };
+                plan_text(&files, concat_list.as_deref(), &ffmpeg::command_line(&ffmpeg_bin_path, &args))
+            }
+        };
+        print!("{}", plan);
 
-    stitch_files(ffmpeg_bin_path, output_file_name, files_to_stitch)?;
+        let part_bytes = stitcher::estimate_output_size(probe.as_deref(), &files, &stitch_options);
+        size_bytes = size_bytes.zip(part_bytes).map(|(x, y)| x + y);
+    }
 
+    // a stitch that won't fit is better found out now than when the disk fills up. parts all go next to each other
+    //
+    if let Some(size_bytes) = size_bytes {
+        println!("size\t{}", size_bytes);
+        if let Some(free_bytes) = free_bytes.filter(|free| *free < size_bytes) {
+            log::warn!(
+                "the output is estimated at {} bytes, but there are only {} bytes free where it goes",
+                size_bytes,
+                free_bytes
+            );
+        }
+    } else {
+        log::info!("couldn't estimate the output's size: that takes ffprobe to measure the inputs, and a known bitrate");
+    }
     Ok(())
 }
 
-//
+/// Bytes free on the volume the output goes on, if it's a file and that can be found out.
+fn free_space(stitch_options: &StitchOptions) -> Option<u64> {
+    if stitch_options.writes_to_stdout() {
+        return None;
+    }
+    let dir = stitch_options.output.parent().filter(|x| !x.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs4::available_space(dir).ok()
+}
+
+/// One `input` line per file, a `list` line per line of the concat list (if there is one), then the
+/// `command` line, tab-separated.
+fn plan_text(files: &[PathBuf], concat_list: Option<&str>, command: &str) -> String {
+    let mut text = String::new();
+    for file in files {
+        text.push_str(&format!("input\t{}\n", file.to_string_lossy()));
+    }
+    for line in concat_list.unwrap_or_default().lines() {
+        text.push_str(&format!("list\t{}\n", line));
+    }
+    text.push_str(&format!("command\t{}\n", command));
+    text
+}
+
+/// The config file given with `--config`, or the one at `config::default_path` if there is one.
+fn load_config(cli_args: &StitchArgs) -> Result<config::Config, StitcherError> {
+    match &cli_args.config {
+        Some(path) => config::load(path),
+        None => match config::default_path().filter(|path| path.is_file()) {
+            Some(path) => config::load(&path),
+            None => Ok(config::Config::default()),
+        },
+    }
+}
+
+/// `cli_args`, with anything it leaves unset that the config file's `[defaults]` covers filled in.
+fn with_config_defaults(cli_args: StitchArgs, defaults: &config::Defaults) -> StitchArgs {
+    StitchArgs {
+        sort: cli_args.sort.or(defaults.sort),
+        name_template: cli_args.name_template.or_else(|| defaults.name_template.clone()),
+        output_dir: cli_args.output_dir.or_else(|| defaults.output_dir.clone()),
+        ..cli_args
+    }
+}
+
+/// Find ffmpeg: just `--ffmpeg-path` if it was given, otherwise `$STITCHER_FFMPEG`, then the config file's,
+/// then the usual places (see `stitcher::ffmpeg_paths`).
+fn find_stitcher(ffmpeg_path: Option<&Path>, defaults: &config::Defaults) -> Result<Stitcher, StitcherError> {
+    let paths = match ffmpeg_path {
+        Some(path) => vec![path.to_path_buf()],
+        None => stitcher::ffmpeg_paths(defaults.ffmpeg.clone()),
+    };
+    stitcher::find_valid_ffmpeg_binary(paths).map(Stitcher::new)
+}
+
+/// Extension of an `--audiobook` output that wasn't named.
+const AUDIOBOOK_EXTENSION: &str = "m4b";
+
+/// Extensions an `--audiobook` output can have: the AAC-in-MP4 ones that players take chapters from.
+const AUDIOBOOK_EXTENSIONS: &[&str] = &[AUDIOBOOK_EXTENSION, "m4a"];
+
+/// Name for an output that wasn't named, when neither `--name-template` nor the config file gives one.
+const DEFAULT_NAME_TEMPLATE: &str = "STITCH_OUTPUT_{date}";
+
+/// Characters that can't be in a file name on Windows (NTFS), besides the path separators.
+const RESERVED_NAME_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*'];
+
+/// Name for an output that wasn't named: `name_template` with each `{token}` in `fields` filled in. tokens
+/// that aren't in `fields` are left for a later pass. characters Windows doesn't allow in file names
+/// become `-`, so the same template works everywhere.
+fn default_output_name(name_template: &str, fields: &[(&str, &str)]) -> String {
+    let name = fields
+        .iter()
+        .fold(name_template.to_string(), |name, (token, value)| name.replace(&format!("{{{}}}", token), value));
+    name.replace(RESERVED_NAME_CHARS, "-")
+}
+
+/// The name of the directory the first input is in (or is), for `{dirname}`.
+fn input_dir_name(cli_args: &StitchArgs) -> String {
+    let Some(input_path) = cli_args.input_paths().into_iter().next() else {
+        return String::new();
+    };
+    let dir = match input_path.is_dir() {
+        true => input_path,
+        false => input_path.parent().map(Path::to_path_buf).unwrap_or_default(),
+    };
+    let dir = std::fs::canonicalize(&dir).unwrap_or(dir);
+    dir.file_name().map(|x| x.to_string_lossy().into_owned()).unwrap_or_default()
+}
 
-fn find_valid_ffmpeg_binary(
-    paths_to_check: Vec<std::path::PathBuf>,
-) -> Result<std::path::PathBuf, String> {
-    // try to run a help command, return Ok on first 0 status code
+/// Turn the command line into `StitchOptions`, checked.
+fn stitch_options(cli_args: &StitchArgs, config: config::Config) -> Result<StitchOptions, StitcherError> {
+    let output_file_name = match &cli_args.out {
+        Some(out) => match (&cli_args.format, out.extension()) {
+            (Some(format), None) if out != Path::new(options::STDOUT_OUTPUT) => out.with_extension(format),
+            _ => out.clone(),
+        },
+        None => {
+            // the extension is a stand-in until the inputs are known, and so are the template's tokens but `{date}`,
+            // see `with_default_output`. the date is filled in now, so a --watch keeps writing the same file
+            //
+            let date = Local::now().format("%d-%h-%Y %H.%M").to_string();
+            let name_template = cli_args.name_template.as_deref().unwrap_or(DEFAULT_NAME_TEMPLATE);
+            let extension = match (cli_args.audiobook, &cli_args.format) {
+                (true, _) => AUDIOBOOK_EXTENSION,
+                (false, Some(format)) => format.as_str(),
+                (false, None) if cli_args.video => formats::FALLBACK_VIDEO_OUTPUT_EXTENSION,
+                (false, None) => formats::FALLBACK_OUTPUT_EXTENSION,
+            };
+            let name = format!("{}.{}", default_output_name(name_template, &[("date", &date)]), extension);
+            match &cli_args.output_dir {
+                Some(dir) => dir.join(name),
+                None => PathBuf::from(name),
+            }
+        }
+    };
+
+    let stitch_options = StitchOptions {
+        output: output_file_name,
+        overwrite: cli_args.overwrite || cli_args.if_newer,
+        progress_json: cli_args.progress_json,
+        progress_bar: cli_args.progress,
+        flac_recompress: cli_args.flac_recompress,
+        keep_cover: cli_args.keep_cover,
+        reencode: cli_args.reencode || cli_args.codec.is_some() || cli_args.audiobook,
+        format: cli_args.format.clone(),
+        codec: cli_args.codec.clone(),
+        normalize_each: cli_args.normalize_each || cli_args.normalize_method.is_some(),
+        normalize_method: cli_args.normalize_method.unwrap_or_default(),
+        normalize_lufs: cli_args.normalize,
+        loudness: None,
+        peak_volumes_db: vec![],
+        strict_ffmpeg: cli_args.strict_ffmpeg,
+        strict: cli_args.strict,
+        probe_timeout: cli_args.probe_timeout,
+        quiet: is_quiet(cli_args),
+        keep_temp: cli_args.keep_temp,
+        show_command: cli_args.show_command,
+        bitrate_kbps: cli_args.bitrate,
+        vbr_quality: cli_args.vbr_quality,
+        compression_level: cli_args.compression_level,
+        target_size_bytes: cli_args.target_size.map(|mb| (mb * 1_000_000.0) as u64),
+        pre_gap_secs: cli_args.pre_gap,
+        post_gap_secs: cli_args.post_gap,
+        gap_secs: cli_args.gap,
+        crossfade_secs: cli_args.crossfade,
+        fade_in_secs: cli_args.fade_in,
+        fade_out_secs: cli_args.fade_out,
+        gapless: cli_args.gapless,
+        trim_silence: cli_args.trim_silence.then_some(split::SilenceSettings {
+            noise_db: cli_args.trim_silence_db,
+            min_secs: cli_args.trim_silence_min,
+        }),
+        silence_format: None,
+        conform_format: None,
+        output_duration_secs: None,
+        resample: cli_args.resample,
+        input_format: cli_args.input_format.clone(),
+        chapters: cli_args.chapters || cli_args.audiobook,
+        cue: cli_args.cue,
+        chapters_out: cli_args.chapters_out.clone(),
+        metadata: metadata_tags(cli_args),
+        copy_metadata: cli_args.copy_metadata,
+        extra_ffmpeg_args: cli_args.ffmpeg_args.concat(),
+        trims: cli_args.input_paths().iter().flat_map(|path| playlist_trims(path)).collect(),
+        gains_db: cli_args.input_paths().iter().flat_map(|path| playlist_gains(path)).collect(),
+        formats: formats::FormatTable::with_configured(config.extensions).with_video(cli_args.video),
+        backend: cli_args.backend,
+    };
+
+    // an unnamed output only has its real extension once the inputs are known, and what's valid depends on it,
+    // so it's checked then, in `with_default_output`
     //
-    for path in &paths_to_check {
-        let output = Command::new(&path).arg("-h").output();
-        if output.is_ok_and(|x| x.status.success()) {
-            return Ok(path.to_path_buf());
+    match cli_args.out {
+        Some(_) => checked(cli_args, stitch_options),
+        None => Ok(stitch_options),
+    }
+}
+
+/// `stitch_options`, if it's valid with `cli_args`.
+fn checked(cli_args: &StitchArgs, stitch_options: StitchOptions) -> Result<StitchOptions, StitcherError> {
+    let mut errors = stitch_options.validate().err().unwrap_or_default();
+    if stitch_options.writes_to_stdout() {
+        errors.extend(stdout_conflicts(cli_args));
+    } else if cli_args.audiobook {
+        let ext = options::output_extension(&stitch_options.output).unwrap_or_default();
+        if !AUDIOBOOK_EXTENSIONS.iter().any(|x| x.eq_ignore_ascii_case(&ext)) {
+            errors.push(options::ValidationError::AudiobookNeedsMp4Output(ext));
         }
     }
+    match errors.is_empty() {
+        true => Ok(stitch_options),
+        false => Err(StitcherError::InvalidOptions(errors)),
+    }
+}
 
-    Err(format!(
-        "failed to find a valid ffmpeg binary. checked paths: {:?}",
-        paths_to_check
-    ))
+/// The flags given that need the output to be a file, for `--out -`: they read it back, or stitch more than once.
+fn stdout_conflicts(cli_args: &StitchArgs) -> Vec<options::ValidationError> {
+    [
+        (cli_args.if_newer, "--if-newer"),
+        (cli_args.readonly_output, "--readonly-output"),
+        (cli_args.checksum.is_some(), "--checksum"),
+        (cli_args.waveform.is_some(), "--waveform"),
+        (cli_args.summary_only, "--summary-only"),
+        (cli_args.json, "--json"),
+        (cli_args.watch, "--watch"),
+        (cli_args.batch, "--batch"),
+        (cli_args.max_part_duration.is_some(), "--max-part-duration"),
+    ]
+    .into_iter()
+    .filter(|(set, _)| *set)
+    .map(|(_, flag)| options::ValidationError::NeedsOutputFile(flag.to_string()))
+    .collect()
 }
 
-fn look_for_files(in_path: std::path::PathBuf) -> Vec<std::path::PathBuf> {
-    match std::fs::read_dir(in_path) {
-        Err(_) => vec![],
-        Ok(result) => result
+/// The `start=`/`end=` trims of the playlist's entries, if the input is a playlist. a playlist that can't be
+/// read is reported when the inputs are looked for.
+fn playlist_trims(input_path: &Path) -> HashMap<PathBuf, playlist::Trim> {
+    match playlist::is_playlist(input_path) {
+        false => HashMap::new(),
+        true => playlist::load_entries(input_path)
+            .unwrap_or_default()
             .into_iter()
-            .filter_map(|x| x.ok())
-            .map(|x| x.path())
-            .filter_map(filter_supported_extensions)
+            .filter(|entry| entry.trim != playlist::Trim::default())
+            .map(|entry| (entry.path, entry.trim))
             .collect(),
     }
 }
 
-fn filter_supported_extensions(path: PathBuf) -> Option<PathBuf> {
-    match path.extension()?.to_str()? {
-        | "mp3"
-        | "wav" => Some(path),
-        _ => None,
+/// The `gain=` of the playlist's entries that have one, if the input is a playlist.
+fn playlist_gains(input_path: &Path) -> HashMap<PathBuf, f64> {
+    match playlist::is_playlist(input_path) {
+        false => HashMap::new(),
+        true => playlist::load_entries(input_path)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| Some((entry.path, entry.gain_db?)))
+            .collect(),
+    }
+}
+
+/// The tags given on the command line, as `(key, value)` pairs for ffmpeg's `-metadata`.
+fn metadata_tags(cli_args: &StitchArgs) -> Vec<(String, String)> {
+    [
+        ("title", &cli_args.title),
+        ("artist", &cli_args.artist),
+        ("album", &cli_args.album),
+        ("comment", &cli_args.comment),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| Some((String::from(key), value.clone()?)))
+    .collect()
+}
+
+/// `stitch_options`, with an output that wasn't named on the command line given the inputs' extension, and
+/// checked with it. an audiobook's is always `.m4b`, and a `--format` one's is that format's.
+fn with_default_output(
+    cli_args: &StitchArgs,
+    stitch_options: &StitchOptions,
+    files: &[PathBuf],
+) -> Result<StitchOptions, StitcherError> {
+    if cli_args.out.is_some() {
+        return Ok(stitch_options.clone());
+    }
+
+    let ext = match cli_args.audiobook || cli_args.format.is_some() {
+        true => options::output_extension(&stitch_options.output).unwrap_or_default(),
+        false => formats::default_output_extension(files, fallback_output_extension(stitch_options)).to_string(),
+    };
+    let stem = stitch_options.output.file_stem().map(|x| x.to_string_lossy().into_owned()).unwrap_or_default();
+    let count = files.len().to_string();
+    let fields = [("dirname", input_dir_name(cli_args)), ("count", count), ("ext", ext.clone())];
+    let fields = fields.iter().map(|(token, value)| (*token, value.as_str())).collect::<Vec<_>>();
+    let output = stitch_options.output.with_file_name(format!("{}.{}", default_output_name(&stem, &fields), ext));
+
+    // a name that's taken gets a number instead of failing, unless it's there to be replaced. --watch replaces
+    // its own output each time, and only knows to leave that one name alone
+    //
+    let output = match stitch_options.overwrite || cli_args.watch {
+        true => output,
+        false => unused_output_path(&output, |path| match cli_args.max_part_duration {
+            Some(_) => split::part_path(path, 1).exists(),
+            None => path.exists(),
+        }),
+    };
+    checked(cli_args, StitchOptions { output, ..stitch_options.clone() })
+}
+
+/// `output`, or if `taken` says it is, the first of `<stem>_1.<ext>`, `<stem>_2.<ext>`... that isn't.
+fn unused_output_path(output: &Path, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    if !taken(output) {
+        return output.to_path_buf();
+    }
+    let stem = output.file_stem().map(|x| x.to_string_lossy().into_owned()).unwrap_or_default();
+    (1..)
+        .map(|n| match output.extension() {
+            Some(ext) => output.with_file_name(format!("{}_{}.{}", stem, n, ext.to_string_lossy())),
+            None => output.with_file_name(format!("{}_{}", stem, n)),
+        })
+        .find(|path| !taken(path))
+        .unwrap_or_else(|| output.to_path_buf())
+}
+
+/// The output extension for unnamed outputs of mixed inputs.
+fn fallback_output_extension(stitch_options: &StitchOptions) -> &'static str {
+    match stitch_options.formats.is_video() {
+        true => formats::FALLBACK_VIDEO_OUTPUT_EXTENSION,
+        false => formats::FALLBACK_OUTPUT_EXTENSION,
     }
 }
 
-fn stitch_files(
-    ffmpeg_bin_path: std::path::PathBuf,
-    output_path: std::path::PathBuf,
-    files: Vec<std::path::PathBuf>,
-) -> Result<std::path::PathBuf, String> {
-    // set up paths
+/// `cli_args`, with each `-` input, and the `--files-from` list, replaced by the paths they list. stdin is
+/// only read once, up front, so every `-` gets the same list and a --watch keeps stitching it.
+fn with_file_lists(cli_args: StitchArgs) -> Result<StitchArgs, StitcherError> {
+    let stdin = Path::new("-");
+    let reads_stdin = cli_args.input_path.iter().chain(&cli_args.inputs).chain(&cli_args.files_from).any(|x| x == stdin);
+    let stdin_list = match reads_stdin {
+        false => vec![],
+        true => {
+            let contents = std::io::read_to_string(std::io::stdin())
+                .map_err(|e| StitcherError::io("reading the file list from stdin", e))?;
+            playlist::parse_file_list(&contents)
+        }
+    };
+    let expand = |paths: Vec<PathBuf>| -> Vec<PathBuf> {
+        paths
+            .into_iter()
+            .flat_map(|path| match path == stdin {
+                true => stdin_list.clone(),
+                false => vec![path],
+            })
+            .collect()
+    };
+
+    let input_path = expand(cli_args.input_path.clone());
+    let mut inputs = expand(cli_args.inputs.clone());
+    match &cli_args.files_from {
+        None => (),
+        Some(path) if path == stdin => inputs.extend(stdin_list.clone()),
+        Some(path) => {
+            let contents = std::fs::read(path).map_err(|e| StitcherError::io(format!("reading {}", path.to_string_lossy()), e))?;
+            inputs.extend(playlist::parse_file_list(&String::from_utf8_lossy(&contents)));
+        }
+    }
+
+    // a list that turns out empty is still an input given, so $STITCHER_INPUT_PATH doesn't stand in for it
     //
-    let output_file_path = output_path.as_os_str();
-    let inputs_file_path = "./_stitcher_tmp_.txt";
+    if (reads_stdin || cli_args.files_from.is_some()) && input_path.is_empty() && inputs.is_empty() {
+        return Err(StitcherError::NoInputs(String::from("the file list is empty")));
+    }
+
+    Ok(StitchArgs {
+        input_path,
+        inputs,
+        files_from: None,
+        ..cli_args
+    })
+}
+
+/// How to walk an input directory, per the command line.
+fn walk_options(cli_args: &StitchArgs) -> discovery::WalkOptions {
+    discovery::WalkOptions {
+        recursive: cli_args.recursive,
+        max_depth: cli_args.max_depth.map(|x| x as usize),
+        prune: cli_args.prune_dir.clone(),
+        retries: cli_args.discovery_retries,
+        strict: cli_args.strict,
+    }
+}
+
+/// Find the inputs and put them in stitching order, per the command line.
+fn discover_files(cli_args: &StitchArgs, stitcher: &Stitcher, stitch_options: &StitchOptions) -> Result<Vec<PathBuf>, StitcherError> {
+    let walk_options = walk_options(cli_args);
+
+    let input_paths = cli_args.input_paths();
+    if input_paths.is_empty() {
+        return Err(StitcherError::NoInputs(String::from(
+            "no inputs given: pass a directory, playlist or files with -i, or as arguments",
+        )));
+    }
 
-    // write each 'file to stitch' path as lines to a temporary file, for use in ffmpeg
+    // each input's files stay together, in the order the inputs were given, unless --sort says otherwise
     //
-    let inputs_file_contents = {
-        let mut wip = String::new();
-        for file in files {
-            match file.to_str() {
-                Some(file) => {
-                    wip.push_str("file ");
-                    wip.push_str(file);
-                    wip.push_str("\n");
-                }
-                None => return Err(format!("failed to parse the list of files: found a None")),
-            }
+    let mut files_to_stitch = vec![];
+    for path in input_paths {
+        files_to_stitch.extend(find_files(path, &walk_options, &stitch_options.formats)?);
+    }
+    let selection = selection::Selection {
+        include: cli_args.include.clone(),
+        exclude: cli_args.exclude.clone(),
+        since: cli_args.since,
+        until: cli_args.until,
+        min_duration_secs: cli_args.min_duration,
+        max_duration_secs: cli_args.max_duration,
+        sort: cli_args.sort.unwrap_or_default(),
+        limit: cli_args.limit,
+        limit_before_filter: cli_args.limit_before_filter,
+        strict: cli_args.strict,
+    };
+    let duration_of = match (selection.min_duration_secs, selection.max_duration_secs) {
+        (Some(min), Some(max)) if min > max => {
+            return Err(StitcherError::InvalidOptions(vec![options::ValidationError::MinDurationOverMax]))
         }
-        wip
+        (Some(_), _) => duration_prober(stitcher, stitch_options, "--min-duration")?,
+        (None, Some(_)) => duration_prober(stitcher, stitch_options, "--max-duration")?,
+        (None, None) => Box::new(|_: &Path| None),
     };
+    files_to_stitch = selection::select(files_to_stitch, &selection, duration_of.as_ref())?;
+    if !cli_args.only.is_empty() {
+        files_to_stitch = select_only(files_to_stitch, &cli_args.only, cli_args.strict)?;
+    }
+    if cli_args.dedupe {
+        let (kept, skipped) = dedupe(files_to_stitch)?;
+        for duplicate in &skipped {
+            log::info!(
+                "skipping {}: same contents as {}",
+                duplicate.file.to_string_lossy(),
+                duplicate.same_as.to_string_lossy()
+            );
+        }
+        files_to_stitch = kept;
+    }
+
+    if files_to_stitch.is_empty() {
+        return Err(StitcherError::NoInputs(String::from("found no files!")));
+    }
+    log::debug!("found {} files, in stitching order:", files_to_stitch.len());
+    for file in &files_to_stitch {
+        log::debug!("  {}", file.to_string_lossy());
+    }
+    Ok(files_to_stitch)
+}
 
-    if let Err(e) = std::fs::write(&inputs_file_path, &inputs_file_contents) {
-        return Err(format!("failed to write lines to the temp file!: {:?}", e));
+/// `stitch_options`, allowed to replace its output if it already exists and whoever's at the terminal says so.
+/// without a terminal to ask, the stitch is left to refuse.
+fn confirm_overwrite(stitch_options: &StitchOptions) -> Result<StitchOptions, StitcherError> {
+    if stitch_options.overwrite
+        || stitch_options.writes_to_stdout()
+        || !stitch_options.output.exists()
+        || !std::io::stdin().is_terminal()
+    {
+        return Ok(stitch_options.clone());
     }
 
-    // run the command
+    eprint!("{} already exists. overwrite it? [y/N] ", stitch_options.output.to_string_lossy());
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .map_err(|e| StitcherError::io("reading the answer", e))?;
+    if interrupt::requested() {
+        return Err(StitcherError::Interrupted);
+    }
+    match answer.trim().eq_ignore_ascii_case("y") || answer.trim().eq_ignore_ascii_case("yes") {
+        true => Ok(StitchOptions { overwrite: true, ..stitch_options.clone() }),
+        false => Err(StitcherError::OutputExists(stitch_options.output.clone())),
+    }
+}
+
+/// Discover the inputs, stitch them, and write whatever reports were asked for. returns the files written
+/// next to the output, output included (see `written_paths`).
+fn stitch_once(
+    cli_args: &StitchArgs,
+    stitcher: &Stitcher,
+    stitch_options: &StitchOptions,
+) -> Result<Vec<PathBuf>, StitcherError> {
+    let mut files_to_stitch = discover_files(cli_args, stitcher, stitch_options)?;
+    if cli_args.interactive {
+        files_to_stitch = match interactive::reorder(files_to_stitch)? {
+            None => return Err(StitcherError::Other(String::from("cancelled, nothing was stitched"))),
+            Some(files) if files.is_empty() => {
+                return Err(StitcherError::NoInputs(String::from("every file was removed from the list")))
+            }
+            Some(files) => files,
+        };
+    }
+    let stitch_options = &with_default_output(cli_args, stitch_options, &files_to_stitch)?;
+    if let Some(dir) = cli_args.output_dir.as_ref().filter(|_| cli_args.out.is_none()) {
+        std::fs::create_dir_all(dir).map_err(|e| StitcherError::io(format!("creating {}", dir.to_string_lossy()), e))?;
+    }
+
+    let written = written_paths(cli_args, stitch_options);
+    match cli_args.max_part_duration {
+        Some(max_secs) => stitch_parts(cli_args, stitcher, stitch_options, files_to_stitch, max_secs)?,
+        None => stitch_and_report(cli_args, stitcher, stitch_options, files_to_stitch)?,
+    }
+    Ok(written)
+}
+
+/// The output, as `with_default_output` named it, and the files written alongside it: its CUE sheet,
+/// chapters and checksum.
+fn written_paths(cli_args: &StitchArgs, stitch_options: &StitchOptions) -> Vec<PathBuf> {
+    let mut written = vec![stitch_options.output.clone()];
+    if cli_args.cue {
+        written.push(options::cue_sheet_path(&stitch_options.output));
+    }
+    written.extend(cli_args.chapters_out.clone());
+    if let Some(algorithm) = cli_args.checksum {
+        written.push(order_report::checksum_path(&stitch_options.output, algorithm));
+    }
+    written
+}
+
+/// Stitch `files_to_stitch` into `stitch_options.output`, and write whatever reports were asked for.
+fn stitch_and_report(
+    cli_args: &StitchArgs,
+    stitcher: &Stitcher,
+    stitch_options: &StitchOptions,
+    files_to_stitch: Vec<PathBuf>,
+) -> Result<(), StitcherError> {
+    if cli_args.if_newer && is_up_to_date(&stitch_options.output, &files_to_stitch) {
+        let outcome = Outcome::Skipped(stitch_options.output.clone());
+        return write_outcome(&outcome, cli_args.json, &mut std::io::stdout(), &mut messages())
+            .map_err(|e| StitcherError::io("printing the result", e));
+    }
+
+    let stitch_options = &confirm_overwrite(stitch_options)?;
+    if stitch_options.overwrite && !stitch_options.writes_to_stdout() {
+        make_writable(&stitch_options.output)?;
+    }
+    // the reports cover the inputs the stitch actually used: any it couldn't read were left out of the output
     //
-    let output = Command::new(ffmpeg_bin_path)
-        .arg("-y")
-        .arg("-vn")
-        .arg("-f")
-        .arg("concat")
-        .arg("-safe")
-        .arg("0")
-        .arg("-i")
-        .arg(&inputs_file_path)
-        .arg("-c")
-        .arg("copy")
-        .arg(&output_file_path)
-        .status()
-        .expect("did not concatenate the files: ffmpeg command failed");
+    let job = StitchJob::new(files_to_stitch.clone(), stitch_options.clone());
+    let Stitched { output: output_path, inputs } = stitcher.stitch(&job)?;
 
-    // check the result
+    if cli_args.readonly_output {
+        make_read_only(&output_path)?;
+    }
+
+    if let Some(report_path) = &cli_args.order_report {
+        order_report::write(report_path, &inputs)?;
+    }
+
+    // the output itself went to stdout, so there's no path to print after it
     //
-    match output.exit_ok() {
-        Err(_e) => return Err(format!("did not concatenate the files: exit not ok: {:?}", &output)),
-        Ok(_) => println!("successfully concatenated the files"),
+    if stitch_options.writes_to_stdout() {
+        return writeln!(messages(), "successfully concatenated the files").map_err(|e| StitcherError::io("printing the result", e));
+    }
+
+    if let Some(algorithm) = cli_args.checksum {
+        let checksummed = match cli_args.checksum_inputs {
+            true => inputs.as_slice(),
+            false => &[],
+        };
+        let manifest = order_report::write_checksum(&output_path, checksummed, algorithm)?;
+        log::debug!("wrote the checksum manifest {}", manifest.to_string_lossy());
+    }
+
+    if let Some(image_path) = &cli_args.waveform {
+        let runner = ffmpeg::Ffmpeg {
+            bin_path: stitcher.ffmpeg_bin_path()?.to_path_buf(),
+            quiet: is_quiet(cli_args),
+        };
+        waveform::render(&runner, &output_path, image_path, cli_args.waveform_size)?;
+    }
+
+    let outcome = match cli_args.summary_only || cli_args.json {
+        false => Outcome::Stitched(output_path),
+        true => {
+            let size_bytes = std::fs::metadata(&output_path)
+                .map_err(|e| StitcherError::io("reading the size of the output file", e))?
+                .len();
+            let duration_secs = match stitcher.ffmpeg_bin_path() {
+                // the output is a normal file, whatever the inputs are, so it's probed without --input-format
+                //
+                Ok(ffmpeg_bin_path) => probe::find_probe(ffmpeg_bin_path, stitch_options.probe_timeout, None)
+                    .and_then(|probe| probe.duration(&output_path).ok()),
+                Err(_) => wav::duration_secs(&output_path),
+            };
+
+            Outcome::Summary(summary::Summary {
+                output: output_path,
+                inputs,
+                duration_secs,
+                size_bytes,
+            })
+        }
+    };
+
+    write_outcome(&outcome, cli_args.json, &mut std::io::stdout(), &mut messages())
+        .map_err(|e| StitcherError::io("printing the result", e))
+}
+
+/// Stitch `files_to_stitch` into numbered parts of the output (see `split::part_path`), each at most `max_secs`
+/// long, breaking only between inputs.
+fn stitch_parts(
+    cli_args: &StitchArgs,
+    stitcher: &Stitcher,
+    stitch_options: &StitchOptions,
+    files_to_stitch: Vec<PathBuf>,
+    max_secs: f64,
+) -> Result<(), StitcherError> {
+    for part in parts(cli_args, stitcher, stitch_options, files_to_stitch, max_secs)? {
+        stitch_and_report(cli_args, stitcher, &part.options, part.files)?;
     }
+    Ok(())
+}
 
-    // clean the temp file up
+/// The jobs that stitch `files_to_stitch` into numbered parts of the output (see `split::part_path`), each at
+/// most `max_secs` long with its pre-gap and post-gap, breaking only between inputs.
+fn parts(
+    cli_args: &StitchArgs,
+    stitcher: &Stitcher,
+    stitch_options: &StitchOptions,
+    files_to_stitch: Vec<PathBuf>,
+    max_secs: f64,
+) -> Result<Vec<StitchJob>, StitcherError> {
+    // an input without a duration can't be read, and is left out like the stitch itself would leave it out. when
+    // none has one, it's the probing that's missing, not the inputs
     //
-    if let Err(e) = std::fs::remove_file(inputs_file_path) {
-        return Err(format!("failed to clean up the temporary file! {:?}", e));
+    let duration_of = duration_prober(stitcher, stitch_options, "--max-part-duration")?;
+    let probed = files_to_stitch.iter().map(|file| (file, duration_of(file))).collect::<Vec<_>>();
+    if probed.iter().all(|(_, duration)| duration.is_none()) {
+        return Err(StitcherError::BadInput(String::from(
+            "--max-part-duration needs the duration of every input, but the inputs couldn't be probed",
+        )));
+    }
+    let mut files_to_stitch = vec![];
+    let mut durations = vec![];
+    for (file, duration) in probed {
+        let Some(duration) = duration else {
+            let message = format!("couldn't read the duration of {}", file.to_string_lossy());
+            match cli_args.strict {
+                true => return Err(StitcherError::BadInput(format!("{} (--strict)", message))),
+                false => log::warn!("{}, skipping it", message),
+            }
+            continue;
+        };
+        durations.push(stitch_options.trims.get(file).map_or(duration, |trim| trim.trimmed_secs(duration)));
+        files_to_stitch.push(file.clone());
+    }
+
+    let edge_secs = stitch_options.pre_gap_secs + stitch_options.post_gap_secs;
+    let too_long = files_to_stitch.iter().zip(&durations).filter(|(_, duration)| **duration + edge_secs > max_secs);
+    for (file, duration) in too_long {
+        let message = format!("{} is {:.0}s long, more than a whole part", file.to_string_lossy(), duration);
+        match cli_args.strict {
+            true => return Err(StitcherError::BadInput(format!("{} (--strict)", message))),
+            false => log::warn!("{}, so it gets one to itself", message),
+        }
+    }
+
+    let parts = split::group_into_parts(&durations, stitch_options.spacing_secs(), edge_secs, max_secs);
+    Ok(parts
+        .into_iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let options = StitchOptions {
+                output: split::part_path(&stitch_options.output, i + 1),
+                ..stitch_options.clone()
+            };
+            StitchJob::new(files_to_stitch[part].to_vec(), options)
+        })
+        .collect())
+}
+
+/// How long an input is, in seconds: with ffprobe, or from its header when there's no ffmpeg and it's a WAV.
+/// with ffmpeg but no ffprobe, there's nothing to ask, so `flag` (which needs the durations) fails up front.
+fn duration_prober(
+    stitcher: &Stitcher,
+    stitch_options: &StitchOptions,
+    flag: &str,
+) -> Result<Box<selection::DurationOf>, StitcherError> {
+    let input_format = stitch_options.input_format.as_deref();
+    match stitcher.ffmpeg_bin_path() {
+        Ok(ffmpeg_bin_path) => match probe::find_probe(ffmpeg_bin_path, stitch_options.probe_timeout, input_format) {
+            Some(probe) => Ok(Box::new(move |file| probe.duration(file).ok())),
+            None => Err(StitcherError::FfprobeNotFound(format!("{} needs ffprobe, but only ffmpeg was found", flag))),
+        },
+        Err(_) => Ok(Box::new(wav::duration_secs)),
+    }
+}
+
+/// Stitch each subdirectory of the inputs as its own job, into an output named after it. one that fails doesn't
+/// stop the rest, and one with nothing to stitch in it is skipped.
+fn stitch_batch(cli_args: &StitchArgs, stitcher: &Stitcher, stitch_options: &StitchOptions) -> Result<(), StitcherError> {
+    let subdirs = batch_dirs(&cli_args.input_paths())?;
+    if subdirs.is_empty() {
+        return Err(StitcherError::NoInputs(String::from("found no subdirectories to stitch!")));
+    }
+
+    let mut failed = 0;
+    let mut exit_code = None;
+    for subdir in &subdirs {
+        let name = subdir.file_name().map(|x| x.to_string_lossy().into_owned()).unwrap_or_default();
+        let job_args = StitchArgs {
+            input_path: vec![subdir.clone()],
+            inputs: vec![],
+            ..cli_args.clone()
+        };
+
+        // the extension is still the stand-in, for `with_default_output` to replace per folder
+        //
+        let extension = stitch_options.output.extension().unwrap_or_default().to_string_lossy();
+        let job_options = StitchOptions {
+            output: stitch_options.output.with_file_name(format!("{}.{}", name, extension)),
+            ..stitch_options.clone()
+        };
+
+        match stitch_once(&job_args, stitcher, &job_options) {
+            Ok(_) => (),
+            Err(StitcherError::Interrupted) => return Err(StitcherError::Interrupted),
+            Err(StitcherError::NoInputs(reason)) => log::info!("skipping {}: {}", subdir.to_string_lossy(), reason),
+            Err(e) => {
+                log::warn!("failed to stitch {}: {}", subdir.to_string_lossy(), e);
+                failed += 1;
+                exit_code.get_or_insert(e.exit_code());
+            }
+        }
+    }
+
+    match exit_code {
+        None => Ok(()),
+        Some(exit_code) => Err(StitcherError::BatchFailed { failed, total: subdirs.len(), exit_code }),
+    }
+}
+
+/// The immediate subdirectories of each of `input_paths` that's a directory, by name within each.
+fn batch_dirs(input_paths: &[PathBuf]) -> Result<Vec<PathBuf>, StitcherError> {
+    let mut all = vec![];
+    for input_path in input_paths.iter().filter(|path| path.is_dir()) {
+        let entries = std::fs::read_dir(input_path)
+            .map_err(|e| StitcherError::io(format!("reading {}", input_path.to_string_lossy()), e))?;
+        let mut subdirs = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect::<Vec<_>>();
+        subdirs.sort();
+        all.extend(subdirs);
+    }
+    Ok(all)
+}
+
+/// Drop write permission on `path`, for everyone.
+fn make_read_only(path: &Path) -> Result<(), StitcherError> {
+    let mut permissions = std::fs::metadata(path)
+        .map_err(|e| StitcherError::io("reading the output's permissions", e))?
+        .permissions();
+    permissions.set_readonly(true);
+    std::fs::set_permissions(path, permissions).map_err(|e| StitcherError::io("making the output read-only", e))
+}
+
+/// Give the owner write permission on `path` back, if it's there and read-only: an output that's being
+/// overwritten, which an earlier --readonly-output run (or the last stitch of a --watch) locked.
+fn make_writable(path: &Path) -> Result<(), StitcherError> {
+    let mut permissions = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.permissions(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(StitcherError::io("reading the output's permissions", e)),
+    };
+    if !permissions.readonly() {
+        return Ok(());
     }
 
+    // on unix, `set_readonly(false)` would let everyone write to it, not only its owner
     //
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.set_mode(permissions.mode() | 0o200);
+    }
+    #[cfg(not(unix))]
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    std::fs::set_permissions(path, permissions).map_err(|e| StitcherError::io("making the output writable", e))
+}
 
-    Ok(PathBuf::from(output_file_path))
+/// What a finished run reports.
+enum Outcome {
+    /// A normal run: the path of the stitched file.
+    Stitched(PathBuf),
+    /// A `--summary-only` run.
+    Summary(summary::Summary),
+    /// An `--if-newer` run that found the output already up to date, so stitched nothing.
+    Skipped(PathBuf),
+}
+
+/// Print the result of a run. stdout only ever gets the machine-readable result, so
+/// `out=$(stitcher ...)` captures just that; anything meant for a human goes to stderr.
+/// with `json`, stdout gets a single JSON object instead.
+fn write_outcome(
+    outcome: &Outcome,
+    json: bool,
+    stdout: &mut dyn std::io::Write,
+    stderr: &mut dyn std::io::Write,
+) -> std::io::Result<()> {
+    if json {
+        return writeln!(stdout, "{}", outcome_json(outcome));
+    }
+
+    match outcome {
+        Outcome::Stitched(output_path) => {
+            writeln!(stderr, "successfully concatenated the files")?;
+            writeln!(stdout, "{}", output_path.to_string_lossy())
+        }
+        Outcome::Summary(summary) => writeln!(stdout, "{}", summary.to_line()),
+        Outcome::Skipped(output_path) => {
+            writeln!(stderr, "the output is up to date, nothing to do")?;
+            writeln!(stdout, "{}", output_path.to_string_lossy())
+        }
+    }
+}
+
+/// The `--json` form of a run's result. `skipped` is always there, so a pipeline can tell a run
+/// that did nothing from one that did work.
+fn outcome_json(outcome: &Outcome) -> serde_json::Value {
+    match outcome {
+        Outcome::Stitched(output_path) => serde_json::json!({
+            "skipped": false,
+            "output": output_path.to_string_lossy(),
+        }),
+        Outcome::Summary(summary) => serde_json::json!({
+            "skipped": false,
+            "output": summary.output.to_string_lossy(),
+            "inputs": summary.inputs.iter().map(|x| x.to_string_lossy()).collect::<Vec<_>>(),
+            "input_count": summary.inputs.len(),
+            "duration_secs": summary.duration_secs,
+            "size_bytes": summary.size_bytes,
+            "ffmpeg_status": 0,
+        }),
+        Outcome::Skipped(output_path) => serde_json::json!({
+            "skipped": true,
+            "reason": "up-to-date",
+            "output": output_path.to_string_lossy(),
+        }),
+    }
+}
+
+/// The `--json` result of a stitch that failed, with the `--out` it was given, if any. `ffmpeg_status` is
+/// ffmpeg's exit status if it got as far as running and failing, or `null`, and `ffmpeg_errors` the lines of its
+/// output that say why.
+fn failure_json(error: &StitcherError, output: Option<&Path>) -> serde_json::Value {
+    let (ffmpeg_status, ffmpeg_errors) = match error {
+        StitcherError::FfmpegFailed { status, errors } => (status.code(), errors.clone()),
+        _ => (None, vec![]),
+    };
+    serde_json::json!({
+        "skipped": false,
+        "output": output.map(|x| x.to_string_lossy()),
+        "error": error.to_string(),
+        "exit_code": error.exit_code(),
+        "ffmpeg_status": ffmpeg_status,
+        "ffmpeg_errors": ffmpeg_errors,
+    })
 }
 
 #[cfg(test)]
@@ -153,94 +1291,420 @@ mod test {
     use super::*;
 
     #[test]
-    pub fn test_finding_files() {
-        let sounds_dir_path = test_path_wav();
-        let files = look_for_files(sounds_dir_path);
-        let expected_len = 3;
-        let actual_len = files.len();
-        assert!(
-            actual_len == expected_len,
-            "expected `look_for_files` to find {} files in the sounds directory",
-            expected_len
+    pub fn test_make_read_only() {
+        let dir = scratch_dir("readonly");
+        let output = dir.join("master.wav");
+        std::fs::write(&output, "RIFF").expect("failed to write a scratch file");
+
+        make_read_only(&output).expect("expected the permissions to change");
+        let permissions = std::fs::metadata(&output).expect("the output still exists").permissions();
+        assert!(permissions.readonly(), "expected the output to be read-only");
+
+        // so --overwrite, or the next stitch of a --watch, can replace it
+        //
+        make_writable(&output).expect("expected the permissions to change back");
+        let permissions = std::fs::metadata(&output).expect("the output still exists").permissions();
+        assert!(!permissions.readonly(), "expected the output to be writable again");
+        make_writable(&dir.join("missing.wav")).expect("expected a missing output to be left alone");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_results_on_stdout_and_messages_on_stderr() {
+        let mut stdout = vec![];
+        let mut stderr = vec![];
+        write_outcome(&Outcome::Stitched(PathBuf::from("out dir/joined.wav")), false, &mut stdout, &mut stderr)
+            .expect("writing to a buffer can't fail");
+
+        assert_eq!(
+            String::from_utf8_lossy(&stdout),
+            "out dir/joined.wav\n",
+            "expected stdout to carry nothing but the output path"
         );
+        assert!(String::from_utf8_lossy(&stderr).contains("successfully concatenated"));
+
+        let mut stdout = vec![];
+        let mut stderr = vec![];
+        let summary = summary::Summary {
+            output: PathBuf::from("joined.wav"),
+            inputs: ["a.wav", "b.wav"].map(PathBuf::from).to_vec(),
+            duration_secs: None,
+            size_bytes: 10,
+        };
+        write_outcome(&Outcome::Summary(summary.clone()), false, &mut stdout, &mut stderr)
+            .expect("writing to a buffer can't fail");
+        assert_eq!(String::from_utf8_lossy(&stdout), format!("{}\n", summary.to_line()));
+        assert!(stderr.is_empty(), "expected --summary-only to print nothing else");
+
+        let mut stdout = vec![];
+        write_outcome(&Outcome::Summary(summary), true, &mut stdout, &mut stderr).expect("writing to a buffer can't fail");
+        let json: serde_json::Value = serde_json::from_slice(&stdout).expect("expected a JSON object on stdout");
+        assert_eq!(json["inputs"], serde_json::json!(["a.wav", "b.wav"]));
+        assert_eq!(json["ffmpeg_status"], 0);
+        assert_eq!(json["duration_secs"], serde_json::Value::Null);
     }
 
     #[test]
-    fn test_finding_valid_ffmpeg_binary() {
-        use std::path::PathBuf;
-        match find_valid_ffmpeg_binary(vec![PathBuf::from("/bin/ffmpeg"), PathBuf::from("./vendor/ffmpeg/ffmpeg")]) {
-            Err(_) => panic!("test expected to receive a valid ffmpeg binary path from `find_valid_ffmpeg_binary`"),
-            Ok(_path) => (),
+    pub fn test_up_to_date_output_skips_with_json_signal() {
+        let dir = scratch_dir("if_newer");
+        let input = dir.join("a.wav");
+        let output = dir.join("joined.wav");
+        std::fs::write(&input, "").expect("failed to write a scratch file");
+        assert!(!is_up_to_date(&output, std::slice::from_ref(&input)), "a missing output is never up to date");
+
+        std::fs::write(&output, "").expect("failed to write a scratch file");
+        let an_hour_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        std::fs::File::options()
+            .write(true)
+            .open(&input)
+            .and_then(|file| file.set_modified(an_hour_ago))
+            .expect("failed to backdate the input");
+        assert!(is_up_to_date(&output, std::slice::from_ref(&input)));
+
+        let mut stdout = vec![];
+        let mut stderr = vec![];
+        write_outcome(&Outcome::Skipped(output.clone()), true, &mut stdout, &mut stderr)
+            .expect("writing to a buffer can't fail");
+        let json: serde_json::Value = serde_json::from_slice(&stdout).expect("expected a JSON object on stdout");
+        assert_eq!(
+            json,
+            serde_json::json!({ "skipped": true, "reason": "up-to-date", "output": output.to_string_lossy() })
+        );
+        assert!(stderr.is_empty());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_verbosity_flags() {
+        let cli = Cli::try_parse_from(["stitcher", "stitch", "-vv", "-i", "sounds"]).expect("a valid stitch");
+        assert_eq!((cli.verbose, cli.quiet), (2, false));
+
+        let cli = Cli::try_parse_from(["stitcher", "plan", "-q", "-i", "sounds"]).expect("a valid plan");
+        assert!(cli.quiet);
+        assert!(Cli::try_parse_from(["stitcher", "stitch", "-q", "-v", "-i", "sounds"]).is_err());
+    }
+
+    #[test]
+    pub fn test_max_part_duration_units() {
+        let parse = |duration: &str| match Cli::try_parse_from(["stitcher", "stitch", "--max-part-duration", duration]) {
+            Ok(Cli { command: Command::Stitch(args), .. }) => args.max_part_duration,
+            _ => None,
+        };
+        assert_eq!(parse("74min"), Some(4440.0));
+        assert_eq!(parse("1.5h"), Some(5400.0));
+        assert_eq!(parse("90s"), Some(90.0));
+        assert_eq!(parse("1:14:00"), Some(4440.0));
+        assert_eq!(parse("0"), None);
+        assert_eq!(parse("74 minutes"), None);
+    }
+
+    #[test]
+    pub fn test_since_and_until_times() {
+        let parse = |time: &str| match Cli::try_parse_from(["stitcher", "stitch", "--since", time]) {
+            Ok(Cli { command: Command::Stitch(args), .. }) => args.since,
+            _ => None,
+        };
+        let ago = |time: &str| {
+            let time = parse(time).expect("a relative time");
+            std::time::SystemTime::now().duration_since(time).expect("a time in the past").as_secs_f64()
+        };
+        assert!((ago("24h") - 86400.0).abs() < 60.0);
+        assert!((ago("2d") - 172800.0).abs() < 60.0);
+        assert!((ago("90min") - 5400.0).abs() < 60.0);
+
+        let midnight = Local.with_ymd_and_hms(2026, 10, 15, 0, 0, 0).earliest().expect("a local time");
+        assert_eq!(parse("2026-10-15"), Some(midnight.into()));
+        let half_nine = Local.with_ymd_and_hms(2026, 10, 15, 9, 30, 0).earliest().expect("a local time");
+        assert_eq!(parse("2026-10-15 09:30"), Some(half_nine.into()));
+        assert_eq!(parse("2026-10-15T09:30:00"), Some(half_nine.into()));
+        assert_eq!(parse("yesterday"), None);
+        assert_eq!(parse("1e20s"), None);
+        assert_eq!(parse("-5h"), None);
+        assert_eq!(parse("NaNs"), None);
+    }
+
+    #[test]
+    pub fn test_fades_are_zero_or_more_seconds() {
+        let parse = |flag: &str, secs: &str| match Cli::try_parse_from(["stitcher", "stitch", flag, secs]) {
+            Ok(Cli { command: Command::Stitch(args), .. }) => Some((args.fade_in, args.fade_out)),
+            _ => None,
+        };
+        assert_eq!(parse("--fade-in", "2.5"), Some((2.5, 0.0)));
+        assert_eq!(parse("--fade-out", "0"), Some((0.0, 0.0)));
+        for secs in ["-1", "NaN", "inf"] {
+            assert_eq!(parse("--fade-in", secs), None, "expected --fade-in {} to be rejected", secs);
+            assert_eq!(parse("--fade-out", secs), None, "expected --fade-out {} to be rejected", secs);
         }
     }
 
     #[test]
-    pub fn expensive_test_stitching_files() {
-        let ffmpeg_exe_path = match find_valid_ffmpeg_binary(
-            vec![PathBuf::from("/bin/ffmpeg"), PathBuf::from("./vendor/ffmpeg/ffmpeg")]) {
-            Err(_) => panic!("test expected to receive a valid ffmpeg binary path from `find_valid_ffmpeg_binary`"),
-            Ok(path) => path,
+    pub fn test_watch_interval_is_at_least_a_second() {
+        let parse = |secs: &str| match Cli::try_parse_from(["stitcher", "stitch", "--watch", "--watch-interval", secs]) {
+            Ok(Cli { command: Command::Stitch(args), .. }) => Some(args.watch_interval),
+            _ => None,
         };
+        assert_eq!(parse("5"), Some(5));
+        assert_eq!(parse("0"), None);
+    }
 
-        let input_files = look_for_files(test_path_wav());
-        let expected_output_path = std::path::PathBuf::from("./TEST_OUTPUT.wav");
-        let actual_output_path = stitch_files(ffmpeg_exe_path, expected_output_path.clone(), input_files)
-            .expect("test failed to run stitch_files");
+    #[test]
+    pub fn test_probe_timeout_is_positive_seconds() {
+        let parse = |timeout: &str| match Cli::try_parse_from(["stitcher", "stitch", "--probe-timeout", timeout]) {
+            Ok(Cli { command: Command::Stitch(args), .. }) => args.probe_timeout,
+            _ => None,
+        };
+        assert_eq!(parse("2.5"), Some(Duration::from_millis(2500)));
+        assert_eq!(parse("0"), None);
+        assert_eq!(parse("-1"), None);
+        assert_eq!(parse("NaN"), None);
+        assert_eq!(parse("inf"), None);
+        assert_eq!(parse("1e30"), None);
+    }
 
-        assert!(
-            actual_output_path == expected_output_path,
-            "expected `stitch_files` to produce an output file at {}, got {}",
-            expected_output_path.to_string_lossy(),
-            actual_output_path.to_string_lossy()
-        );
+    #[test]
+    pub fn test_subcommands() {
+        Cli::command().debug_assert();
 
-        if let Err(e) = std::fs::remove_file(actual_output_path) {
-            panic!("failed to clean up the `actual output path`!: {}", e);
+        let cli = Cli::try_parse_from(["stitcher", "stitch", "-i", "sounds", "-o", "out.wav"]).expect("a valid stitch");
+        match cli.command {
+            Command::Stitch(args) => assert_eq!(args.out, Some(PathBuf::from("out.wav"))),
+            other => panic!("expected the stitch subcommand, got {:?}", other),
         }
+        assert!(matches!(
+            Cli::try_parse_from(["stitcher", "plan", "-i", "sounds"]).map(|x| x.command),
+            Ok(Command::Plan(_))
+        ));
+        assert!(matches!(
+            Cli::try_parse_from(["stitcher", "probe", "-i", "sounds"]).map(|x| x.command),
+            Ok(Command::Probe(_))
+        ));
+        assert!(matches!(
+            Cli::try_parse_from(["stitcher", "fetch-ffmpeg"]).map(|x| x.command),
+            Ok(Command::FetchFfmpeg(FetchArgs { dest })) if dest == Path::new(fetch::VENDOR_DIR)
+        ));
+        assert!(matches!(
+            Cli::try_parse_from(["stitcher", "stitch", "-i", "sounds", "--dry-run"]).map(|x| x.command),
+            Ok(Command::Stitch(StitchArgs { dry_run: true, .. }))
+        ));
+
+        let mut script = vec![];
+        run_completions(CompletionsArgs { shell: clap_complete::Shell::Bash }, &mut script).expect("expected a completion script");
+        let script = String::from_utf8(script).expect("expected the script to be text");
+        assert!(script.contains("stitcher__subcmd__stitch") && script.contains("--trim-silence"), "expected every subcommand's flags");
     }
 
     #[test]
-    pub fn test_filter_supported_extensions() {
-        type T = Vec<PathBuf>;
+    pub fn test_inputs_merge_in_the_order_given() {
+        let dir = std::env::temp_dir().join(format!("stitcher_test_inputs_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("more")).expect("failed to create a scratch directory");
+        for file in ["intro.wav", "more/b.wav", "more/a.wav", "outro.wav"] {
+            std::fs::write(dir.join(file), "").expect("failed to write a scratch file");
+        }
 
-        let paths = vec!["file1.txt", "file2.wav", "file3.mp3", "file4.rs", "file5"]
-            .iter()
-            .map(PathBuf::from)
-            .collect::<T>();
+        let intro = dir.join("intro.wav").to_string_lossy().into_owned();
+        let more = dir.join("more").to_string_lossy().into_owned();
+        let outro = dir.join("outro.wav").to_string_lossy().into_owned();
+        let cli_args = match Cli::try_parse_from(["stitcher", "stitch", "-i", &intro, &more, &outro]).map(|x| x.command) {
+            Ok(Command::Stitch(args)) => args,
+            other => panic!("expected the stitch subcommand, got {:?}", other),
+        };
+        assert_eq!(cli_args.input_paths(), [&intro, &more, &outro].map(PathBuf::from));
 
-        let expected_supported = vec!["file2.wav", "file3.mp3"]
-            .iter()
-            .map(PathBuf::from)
-            .collect::<T>();
+        let files = discover_files(&cli_args, &Stitcher::without_ffmpeg(vec![]), &Default::default()).expect("expected to find the inputs");
+        assert_eq!(files, ["intro.wav", "more/a.wav", "more/b.wav", "outro.wav"].map(|x| dir.join(x)));
 
-        let actual = paths
-            .into_iter()
-            .filter_map(filter_supported_extensions)
-            .collect::<T>();
-
-        if !expected_supported.eq(&actual) {
-            dbg!(&expected_supported);
-            dbg!(&actual);
-            assert!(
-                false,
-                "expected the filtered extensions to match the supported values"
-            );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_batch_dirs_are_each_subdirectory_by_name() {
+        let dir = std::env::temp_dir().join(format!("stitcher_test_batch_{}", std::process::id()));
+        for subdir in ["2024-05-02", "2024-05-01"] {
+            std::fs::create_dir_all(dir.join(subdir)).expect("failed to create a scratch directory");
         }
+        std::fs::write(dir.join("notes.txt"), "").expect("failed to write a scratch file");
+
+        assert_eq!(batch_dirs(std::slice::from_ref(&dir)).ok(), Some(vec![dir.join("2024-05-01"), dir.join("2024-05-02")]));
+
+        let _ = std::fs::remove_dir_all(dir);
     }
 
-    //
+    #[test]
+    pub fn test_audiobook_is_chaptered_aac_in_m4b() {
+        let cli_args = match Cli::try_parse_from(["stitcher", "stitch", "-i", "sounds", "--audiobook"]).map(|x| x.command) {
+            Ok(Command::Stitch(args)) => args,
+            other => panic!("expected the stitch subcommand, got {:?}", other),
+        };
+        let stitch_options = stitch_options(&cli_args, Default::default()).expect("expected valid audiobook options");
+        assert!(stitch_options.chapters && stitch_options.reencode);
 
-    fn test_path_wav() -> std::path::PathBuf {
-        let sounds_dir_path = std::path::PathBuf::from("./test/stitcher/sounds/wav");
-        std::fs::try_exists(&sounds_dir_path)
-            .expect("this test expects to be run from the project root");
-        sounds_dir_path
+        let files = ["01 - Intro.mp3", "02 - Chapter One.mp3"].map(PathBuf::from);
+        let stitch_options = with_default_output(&cli_args, &stitch_options, &files).expect("expected valid audiobook options");
+        assert_eq!(stitch_options.output.extension(), Some(std::ffi::OsStr::new(AUDIOBOOK_EXTENSION)));
+
+        let args = stitcher::planned_ffmpeg_args(&files, &stitch_options);
+        let has = |pair: &[&str]| args.windows(2).any(|x| x[0] == pair[0] && x[1] == pair[1]);
+        assert!(has(&["-c:a", "aac"]) && has(&["-f", "ipod"]) && has(&["-map_chapters", "1"]));
+
+        for (out, ok) in [("book.m4a", true), ("book.M4B", true), ("book.mp3", false)] {
+            let cli_args = match Cli::try_parse_from(["stitcher", "stitch", "-i", "sounds", "--audiobook", "-o", out]) {
+                Ok(Cli { command: Command::Stitch(args), .. }) => args,
+                other => panic!("expected the stitch subcommand, got {:?}", other),
+            };
+            assert_eq!(super::stitch_options(&cli_args, Default::default()).is_ok(), ok, "--audiobook -o {}", out);
+        }
     }
 
-    fn _test_path_mp3() -> std::path::PathBuf {
-        let sounds_dir_path = std::path::PathBuf::from("./test/stitcher/sounds/mp3");
-        std::fs::try_exists(&sounds_dir_path)
-            .expect("this test expects to be run from the project root");
-        sounds_dir_path
+    #[test]
+    pub fn test_unnamed_video_output_is_matroska_until_the_inputs_say_otherwise() {
+        let cli_args = match Cli::try_parse_from(["stitcher", "stitch", "--video", "-i", "clips"]).map(|x| x.command) {
+            Ok(Command::Stitch(args)) => args,
+            other => panic!("expected the stitch subcommand, got {:?}", other),
+        };
+        let stitch_options = stitch_options(&cli_args, Default::default()).expect("expected valid video options");
+        assert_eq!(options::output_extension(&stitch_options.output).as_deref(), Some(formats::FALLBACK_VIDEO_OUTPUT_EXTENSION));
+    }
+
+    #[test]
+    pub fn test_unnamed_output_is_checked_with_the_inputs_extension() {
+        let dir = scratch_dir("unnamed_mp3");
+        for file in ["a.mp3", "b.mp3"] {
+            std::fs::write(dir.join(file), "").expect("failed to write a scratch file");
+        }
+        let input = dir.to_string_lossy().into_owned();
+        let output_dir = dir.join("out").to_string_lossy().into_owned();
+        let parse = |flags: &[&str]| {
+            let base = ["stitcher", "stitch", "-i", &input, "--output-dir", &output_dir];
+            match Cli::try_parse_from(base.iter().chain(flags)).map(|x| x.command) {
+                Ok(Command::Stitch(args)) => args,
+                other => panic!("expected the stitch subcommand, got {:?}", other),
+            }
+        };
+        let resolve = |cli_args: &StitchArgs| {
+            let stitch_options = stitch_options(cli_args, Default::default())?;
+            let files = discover_files(cli_args, &Stitcher::without_ffmpeg(vec![]), &stitch_options)?;
+            with_default_output(cli_args, &stitch_options, &files)
+        };
+
+        // each of these only works once the stand-in .wav is swapped for the inputs' .mp3
+        //
+        for flags in [&["--vbr-quality", "2"][..], &["--compression-level", "5"], &["--target-size", "5"], &["--chapters"]] {
+            let stitch_options = resolve(&parse(flags)).unwrap_or_else(|e| panic!("expected {:?} to be valid, got: {}", flags, e));
+            assert_eq!(options::output_extension(&stitch_options.output).as_deref(), Some("mp3"));
+        }
+        assert!(matches!(resolve(&parse(&["--flac-recompress", "5"])), Err(StitcherError::InvalidOptions(_))));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_min_duration_over_max_is_refused_before_probing() {
+        let dir = scratch_dir("min_over_max");
+        std::fs::write(dir.join("a.mp3"), "").expect("failed to write a scratch file");
+        let input = dir.to_string_lossy().into_owned();
+        let args = ["stitcher", "stitch", "-i", &input, "--min-duration", "10min", "--max-duration", "5min"];
+        let Ok(Cli { command: Command::Stitch(cli_args), .. }) = Cli::try_parse_from(args) else {
+            panic!("expected the stitch subcommand to parse");
+        };
+
+        let stitch_options = stitch_options(&cli_args, Default::default()).expect("expected valid options");
+        match discover_files(&cli_args, &Stitcher::without_ffmpeg(vec![]), &stitch_options) {
+            Err(StitcherError::InvalidOptions(errors)) => assert_eq!(errors, vec![options::ValidationError::MinDurationOverMax]),
+            other => panic!("expected --min-duration over --max-duration to be refused, got {:?}", other),
+        }
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_config_defaults_lose_to_the_command_line() {
+        let defaults = config::Defaults {
+            sort: Some(stitcher::sort::SortOrder::Natural),
+            output_dir: Some(PathBuf::from("stitched")),
+            ..Default::default()
+        };
+        let parse = |args: &[&str]| match Cli::try_parse_from(args).map(|x| x.command) {
+            Ok(Command::Stitch(args)) => args,
+            other => panic!("expected the stitch subcommand, got {:?}", other),
+        };
+
+        let from_config = with_config_defaults(parse(&["stitcher", "stitch", "-i", "sounds"]), &defaults);
+        assert_eq!(from_config.sort, Some(stitcher::sort::SortOrder::Natural));
+        assert_eq!(from_config.output_dir, Some(PathBuf::from("stitched")));
+
+        let flags = ["stitcher", "stitch", "-i", "sounds", "--sort", "mtime", "--output-dir", "out"];
+        let from_flag = with_config_defaults(parse(&flags), &defaults);
+        assert_eq!(from_flag.sort, Some(stitcher::sort::SortOrder::Mtime));
+        assert_eq!(from_flag.output_dir, Some(PathBuf::from("out")));
+
+        assert_eq!(default_output_name("session {date}", &[("date", "15-Oct-2026 09.30")]), "session 15-Oct-2026 09.30");
+        assert_eq!(default_output_name("take: \"{date}\"?", &[("date", "15-Oct-2026 09:30")]), "take- -15-Oct-2026 09-30--");
+        assert_eq!(
+            default_output_name("{dirname} ({count} {ext}s) {date}", &[("dirname", "gig"), ("count", "12"), ("ext", "flac")]),
+            "gig (12 flacs) {date}"
+        );
+    }
+
+    #[test]
+    pub fn test_taken_output_names_get_a_number() {
+        let taken = ["out.wav", "out_1.wav", "other"].map(PathBuf::from);
+        let is_taken = |path: &Path| taken.iter().any(|x| x == path);
+        assert_eq!(unused_output_path(Path::new("out.wav"), is_taken), Path::new("out_2.wav"));
+        assert_eq!(unused_output_path(Path::new("new.wav"), is_taken), Path::new("new.wav"));
+        assert_eq!(unused_output_path(Path::new("other"), is_taken), Path::new("other_1"));
+    }
+
+    #[test]
+    pub fn test_probe_line() {
+        let half_known = fixed_probe::FixedProbe { duration: Some(12.5), format: None };
+        assert_eq!(probe_line(&half_known, Path::new("a.wav")), "a.wav\t12.500\t-\t-\t-");
+    }
+
+    #[test]
+    pub fn test_duration_report() {
+        let files = ["intro.wav", "interview.wav", "outro.wav"].map(PathBuf::from);
+        assert_eq!(
+            duration_report(&files, &[65.5, 3600.0, 30.0], &[]),
+            "00:00:00\t00:01:05\tintro.wav\n\
+             00:01:05\t01:00:00\tinterview.wav\n\
+             01:01:05\t00:00:30\toutro.wav\n\
+             total\t01:01:35\n"
+        );
+
+        let skipped = [probe::ProbeError::Unreadable(String::from("ffprobe could not read bad.wav: Invalid data"))];
+        assert_eq!(
+            duration_report(&files[..1], &[65.5], &skipped),
+            "00:00:00\t00:01:05\tintro.wav\n\
+             total\t00:01:05\n\
+             skipped\tffprobe could not read bad.wav: Invalid data\n"
+        );
+    }
+
+    #[test]
+    pub fn test_plan_text() {
+        let files = ["take 1.wav", "take2.wav"].map(PathBuf::from);
+        let args = ["-y", "-i", "it's.txt"].map(std::ffi::OsString::from);
+        assert_eq!(
+            plan_text(&files, None, &ffmpeg::command_line(Path::new("/bin/ffmpeg"), &args)),
+            "input\ttake 1.wav\ninput\ttake2.wav\ncommand\t/bin/ffmpeg -y -i 'it'\\''s.txt'\n"
+        );
+
+        let list = "file '/rec/take 1.wav'\nfile '/rec/take2.wav'\n";
+        assert_eq!(
+            plan_text(&files, Some(list), "ffmpeg"),
+            "input\ttake 1.wav\ninput\ttake2.wav\nlist\tfile '/rec/take 1.wav'\nlist\tfile '/rec/take2.wav'\ncommand\tffmpeg\n"
+        );
+    }
+
+    //
+
+    /// A fresh, empty directory under the OS temp dir, for tests that need real files.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stitcher_test_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("failed to create a scratch directory");
+        dir
     }
 }
@@ -0,0 +1,73 @@
+use std::io::Write;
+
+use clap::CommandFactory;
+use stitcher::{error, interrupt};
+
+use crate::{cli::Cli, lock};
+
+/// Every exit status, and what it means, for the EXIT STATUS section.
+const EXIT_STATUSES: &[(u8, &str)] = &[
+    (0, "the stitch worked, or there was nothing to do (--if-newer)."),
+    (1, "anything not listed below."),
+    (error::EXIT_USAGE, "the options don't work together."),
+    (error::EXIT_DATA, "an input can't be stitched as it is."),
+    (error::EXIT_NO_INPUT, "there's nothing to stitch."),
+    (error::EXIT_UNAVAILABLE, "no ffmpeg could be found, or no ffprobe for something that needs it."),
+    (error::EXIT_SOFTWARE, "ffmpeg itself failed."),
+    (error::EXIT_CANT_CREATE, "the output already exists and wasn't to be overwritten."),
+    (error::EXIT_IO, "a file couldn't be read or written."),
+    (lock::EXIT_LOCK_HELD, "another run holds the --lockfile."),
+    (error::EXIT_CONFIG, "the config file doesn't parse."),
+    (interrupt::EXIT_INTERRUPTED, "Ctrl+C stopped it. ffmpeg is stopped too, and the partial output removed."),
+];
+
+/// Write stitcher's man page, in roff: the usual sections, then each subcommand's options under its own
+/// heading, so every flag is in the one page, then the exit statuses.
+pub fn render(out: &mut dyn Write) -> std::io::Result<()> {
+    let command = Cli::command();
+    let man = clap_mangen::Man::new(command.clone());
+    man.render_title(out)?;
+    man.render_name_section(out)?;
+    man.render_synopsis_section(out)?;
+    man.render_description_section(out)?;
+    man.render_options_section(out)?;
+    man.render_subcommands_section(out)?;
+
+    // clap_mangen puts the options under a section named after their help heading, so giving them all one
+    // per subcommand makes a section of each. --help is left out, or it'd get a plain OPTIONS section of its own
+    //
+    for subcommand in command.get_subcommands().filter(|x| x.get_arguments().next().is_some()) {
+        let heading = format!("{} {} options", command.get_name(), subcommand.get_name());
+        let subcommand = subcommand
+            .clone()
+            .disable_help_flag(true)
+            .mut_args(|arg| arg.help_heading(heading.clone()));
+        clap_mangen::Man::new(subcommand).render_options_section(out)?;
+    }
+
+    writeln!(out, ".SH \"EXIT STATUS\"")?;
+    for (status, meaning) in EXIT_STATUSES {
+        writeln!(out, ".TP\n\\fB{}\\fR\n{}", status, meaning.replace('-', "\\-"))?;
+    }
+
+    man.render_version_section(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_man_page_has_every_subcommand_and_exit_status() {
+        let mut page = vec![];
+        render(&mut page).expect("expected the man page to render");
+        let page = String::from_utf8(page).expect("expected the man page to be text");
+
+        assert!(page.starts_with(".ie") || page.starts_with(".TH"), "expected roff, got: {}", &page[..40]);
+        assert!(page.contains(".SH \"STITCHER STITCH OPTIONS\""), "expected a section for stitch's options");
+        assert!(page.contains("trim\\-silence"), "expected stitch's flags to be listed");
+        assert!(page.contains(".SH \"STITCHER SPLIT OPTIONS\""));
+        assert!(page.contains(".SH \"EXIT STATUS\"") && page.contains("\\fB73\\fR"));
+        assert_eq!(page.matches(".SH OPTIONS").count(), 1, "expected only the global options under OPTIONS");
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::{error::StitcherError, ffmpeg, filtergraph, playlist};
+
+/// Dynamic normalization, for sparse material that loudnorm makes pump. resampled for the same reason as loudnorm.
+pub const DYNAUDNORM_EACH: &str = "dynaudnorm=f=500:g=31,aresample=48000";
+
+/// The level, in dBFS, `NormalizeMethod::Peak` brings each input's loudest sample up (or down) to.
+pub const PEAK_TARGET_DB: f64 = -1.0;
+
+/// Integrated loudness, in LUFS, `--normalize` aims the whole output at when no target is given.
+pub const DEFAULT_TARGET_LUFS: f64 = -16.0;
+
+/// True peak ceiling and loudness range `--normalize` passes to loudnorm, the same as `LOUDNORM_EACH`.
+const LOUDNORM_TRUE_PEAK_DB: f64 = -1.5;
+const LOUDNORM_RANGE_LU: f64 = 11.0;
+
+/// What loudnorm's analysis pass measured, to feed back into the encode pass so it can normalize
+/// linearly, in one go, instead of adjusting as it goes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessMeasurement {
+    pub input_i: f64,
+    pub input_tp: f64,
+    pub input_lra: f64,
+    pub input_thresh: f64,
+    pub target_offset: f64,
+}
+
+/// How `--normalize-each` evens out the inputs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum NormalizeMethod {
+    /// EBU R128 loudness normalization.
+    #[default]
+    Loudnorm,
+    /// Dynamic normalization, which follows the material over time instead of targeting one loudness.
+    Dynaudnorm,
+    /// A fixed gain per input, from its peak as measured by a first `volumedetect` pass.
+    Peak,
+}
+
+impl NormalizeMethod {
+    /// Whether each input has to be measured with `measure_peak` before the filters can be built.
+    pub fn needs_measurement(&self) -> bool {
+        *self == NormalizeMethod::Peak
+    }
+}
+
+/// The filters that normalize one input with `method`. `max_volume_db` is the input's measured peak,
+/// which only the peak method uses. every method resamples to `filtergraph::LOUDNORM_EACH_SAMPLE_RATE`,
+/// so the normalized inputs all join in one format.
+pub fn filters(method: NormalizeMethod, max_volume_db: Option<f64>) -> Vec<String> {
+    match method {
+        NormalizeMethod::Loudnorm => vec![String::from(filtergraph::LOUDNORM_EACH)],
+        NormalizeMethod::Dynaudnorm => vec![String::from(DYNAUDNORM_EACH)],
+        NormalizeMethod::Peak => {
+            let gain_db = PEAK_TARGET_DB - max_volume_db.unwrap_or(PEAK_TARGET_DB);
+            vec![
+                format!("volume={:.1}dB", gain_db),
+                format!("aresample={}", filtergraph::LOUDNORM_EACH_SAMPLE_RATE),
+            ]
+        }
+    }
+}
+
+/// The filters that bring the joined output to `target_lufs`. without a `measured`, it's the analysis pass,
+/// which prints what it measured as JSON on stderr. loudnorm works at 192kHz internally, so it's
+/// resampled back down to `filtergraph::LOUDNORM_EACH_SAMPLE_RATE` afterwards.
+pub fn loudnorm_filters(target_lufs: f64, measured: Option<&LoudnessMeasurement>) -> Vec<String> {
+    let settings = format!("I={}:TP={}:LRA={}", target_lufs, LOUDNORM_TRUE_PEAK_DB, LOUDNORM_RANGE_LU);
+    let loudnorm = match measured {
+        None => format!("loudnorm={}:print_format=json", settings),
+        Some(m) => format!(
+            "loudnorm={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+            settings, m.input_i, m.input_tp, m.input_lra, m.input_thresh, m.target_offset
+        ),
+    };
+    vec![loudnorm, format!("aresample={}", filtergraph::LOUDNORM_EACH_SAMPLE_RATE)]
+}
+
+/// The measurement from the JSON report loudnorm's analysis pass prints at the end of ffmpeg's stderr.
+/// loudnorm quotes every number, e.g. `"input_i" : "-23.54"`.
+pub fn parse_loudness(stderr: &str) -> Option<LoudnessMeasurement> {
+    let start = stderr.rfind("[Parsed_loudnorm")?;
+    let json_start = start + stderr[start..].find('{')?;
+    let json_end = json_start + stderr[json_start..].find('}')? + 1;
+    let report: serde_json::Value = serde_json::from_str(&stderr[json_start..json_end]).ok()?;
+    let field = |name: &str| report.get(name)?.as_str()?.trim().parse::<f64>().ok();
+
+    Some(LoudnessMeasurement {
+        input_i: field("input_i")?,
+        input_tp: field("input_tp")?,
+        input_lra: field("input_lra")?,
+        input_thresh: field("input_thresh")?,
+        target_offset: field("target_offset")?,
+    })
+}
+
+/// ffmpeg arguments for the measuring pass: decode `file`, or the part of it `trim` keeps, through volumedetect
+/// and throw the audio away.
+pub fn volumedetect_args(file: &Path, input_format: Option<&str>, trim: Option<&playlist::Trim>) -> Vec<OsString> {
+    let mut args = vec![OsString::from("-hide_banner"), OsString::from("-nostats")];
+    if let Some(format) = input_format {
+        args.push(OsString::from("-f"));
+        args.push(OsString::from(format));
+    }
+    if let Some(secs) = trim.and_then(|trim| trim.start_secs) {
+        args.push(OsString::from("-ss"));
+        args.push(OsString::from(secs.to_string()));
+    }
+    if let Some(secs) = trim.and_then(|trim| trim.end_secs) {
+        args.push(OsString::from("-to"));
+        args.push(OsString::from(secs.to_string()));
+    }
+    args.push(OsString::from("-i"));
+    args.push(file.as_os_str().to_owned());
+    for arg in ["-vn", "-af", "volumedetect", "-f", "null", "-"] {
+        args.push(OsString::from(arg));
+    }
+    args
+}
+
+/// The peak volume (dBFS) from volumedetect's stderr report, e.g. `[Parsed_volumedetect_0 @ 0x..] max_volume: -3.2 dB`.
+pub fn parse_max_volume(stderr: &str) -> Option<f64> {
+    stderr.lines().find_map(|line| {
+        let (_, value) = line.split_once("max_volume:")?;
+        value.trim().strip_suffix("dB")?.trim().parse::<f64>().ok()
+    })
+}
+
+/// Measure the peak volume of every file in `files`, in order, over only the part of it that its trim keeps.
+pub fn measure_peaks(
+    ffmpeg_bin_path: &Path,
+    files: &[PathBuf],
+    input_format: Option<&str>,
+    trims: &HashMap<PathBuf, playlist::Trim>,
+) -> Result<Vec<f64>, StitcherError> {
+    files
+        .iter()
+        .map(|file| {
+            let args = volumedetect_args(file, input_format, trims.get(file));
+            let run = ffmpeg::run(ffmpeg_bin_path, &args, ffmpeg::Progress::Inherit, true)?;
+            run.check()?;
+            parse_max_volume(&run.stderr).ok_or_else(|| {
+                StitcherError::Other(format!("failed to measure the peak of {}: no volumedetect report", file.to_string_lossy()))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_filters_per_method() {
+        assert_eq!(filters(NormalizeMethod::Loudnorm, None), vec![filtergraph::LOUDNORM_EACH]);
+        assert_eq!(filters(NormalizeMethod::Dynaudnorm, None), vec![DYNAUDNORM_EACH]);
+        assert_eq!(filters(NormalizeMethod::Peak, Some(-7.5)), vec!["volume=6.5dB", "aresample=48000"]);
+        assert_eq!(filters(NormalizeMethod::Peak, Some(2.0)), vec!["volume=-3.0dB", "aresample=48000"]);
+    }
+
+    #[test]
+    pub fn test_two_pass_loudnorm() {
+        assert_eq!(
+            loudnorm_filters(-16.0, None),
+            vec!["loudnorm=I=-16:TP=-1.5:LRA=11:print_format=json", "aresample=48000"]
+        );
+
+        let stderr = "\
+            size=N/A time=00:03:00.00 bitrate=N/A speed= 211x\n\
+            [Parsed_loudnorm_3 @ 0x6000] \n\
+            {\n\
+            \t\"input_i\" : \"-27.61\",\n\
+            \t\"input_tp\" : \"-4.47\",\n\
+            \t\"input_lra\" : \"18.06\",\n\
+            \t\"input_thresh\" : \"-39.20\",\n\
+            \t\"output_i\" : \"-16.58\",\n\
+            \t\"normalization_type\" : \"dynamic\",\n\
+            \t\"target_offset\" : \"0.58\"\n\
+            }\n";
+        let measured = parse_loudness(stderr).expect("expected loudnorm's report to parse");
+        assert_eq!(
+            measured,
+            LoudnessMeasurement { input_i: -27.61, input_tp: -4.47, input_lra: 18.06, input_thresh: -39.2, target_offset: 0.58 }
+        );
+        assert_eq!(
+            loudnorm_filters(-14.0, Some(&measured))[0],
+            "loudnorm=I=-14:TP=-1.5:LRA=11:measured_I=-27.61:measured_TP=-4.47:measured_LRA=18.06:measured_thresh=-39.2:offset=0.58:linear=true"
+        );
+        assert_eq!(parse_loudness("Input #0, wav, from 'take 1.wav':"), None);
+    }
+
+    #[test]
+    pub fn test_peak_measurement_pass() {
+        let args = volumedetect_args(Path::new("take 1.wav"), Some("wav"), None);
+        let expected = ["-hide_banner", "-nostats", "-f", "wav", "-i", "take 1.wav", "-vn", "-af", "volumedetect", "-f", "null", "-"];
+        assert_eq!(args, expected.map(OsString::from));
+
+        // only the part of a trimmed input that's stitched counts towards its peak
+        //
+        let trim = playlist::Trim { start_secs: Some(2.5), end_secs: Some(60.0) };
+        let args = volumedetect_args(Path::new("take 1.wav"), None, Some(&trim));
+        assert_eq!(args[2..7], ["-ss", "2.5", "-to", "60", "-i"].map(OsString::from));
+
+        let stderr = "\
+            [Parsed_volumedetect_0 @ 0x6000] n_samples: 88200\n\
+            [Parsed_volumedetect_0 @ 0x6000] mean_volume: -21.4 dB\n\
+            [Parsed_volumedetect_0 @ 0x6000] max_volume: -3.2 dB\n\
+            [Parsed_volumedetect_0 @ 0x6000] histogram_3db: 12\n";
+        assert_eq!(parse_max_volume(stderr), Some(-3.2));
+        assert_eq!(parse_max_volume("Input #0, wav, from 'take 1.wav':"), None);
+    }
+}
@@ -0,0 +1,593 @@
+use std::path::{Path, PathBuf};
+
+/// Everything that changes how `stitch_files` drives ffmpeg.
+#[derive(Debug, Default, Clone)]
+pub struct StitchOptions {
+    /// Where the stitched file is written. its extension picks the output container. `-` (`STDOUT_OUTPUT`)
+    /// writes to stdout instead, in the container `format` names.
+    pub output: PathBuf,
+    /// Replace `output` if it already exists, instead of failing.
+    pub overwrite: bool,
+    /// Emit ffmpeg's `-progress` stream as JSON lines on stderr.
+    pub progress_json: bool,
+    /// Draw a progress bar on stderr from ffmpeg's `-progress` stream. `progress_json` wins if both are set.
+    pub progress_bar: bool,
+    /// Re-encode FLAC output at this compression level instead of `-c copy`.
+    pub flac_recompress: Option<u8>,
+    /// Carry attached-picture streams through instead of passing `-vn`.
+    pub keep_cover: bool,
+    /// Decode and re-encode the inputs instead of stream copying them.
+    pub reencode: bool,
+    /// The output container, by extension (e.g. `flac`). the output has to have this extension. inputs
+    /// already in it are stream copied as usual; any that aren't are transcoded, rather than copied into it.
+    pub format: Option<String>,
+    /// Audio codec to re-encode with. when unset, the output extension's default from `codec` is used.
+    pub codec: Option<String>,
+    /// Loudness-normalize every input on its own before joining them.
+    pub normalize_each: bool,
+    /// How `normalize_each` normalizes.
+    pub normalize_method: crate::normalize::NormalizeMethod,
+    /// Each input's measured peak volume in dBFS, in input order, for `NormalizeMethod::Peak`.
+    /// measured before the stitch when needed.
+    pub peak_volumes_db: Vec<f64>,
+    /// Loudness-normalize the whole output to this integrated loudness, in LUFS, with loudnorm in two passes.
+    pub normalize_lufs: Option<f64>,
+    /// The joined inputs' loudness, from the analysis pass of `normalize_lufs`. measured before the stitch when needed.
+    pub loudness: Option<crate::normalize::LoudnessMeasurement>,
+    /// Fail the stitch if ffmpeg's stderr shows a known problem warning, instead of just passing it on.
+    pub strict_ffmpeg: bool,
+    /// Turn warnings about the inputs (like a probe that timed out) into errors.
+    pub strict: bool,
+    /// Kill any ffprobe call that takes longer than this.
+    pub probe_timeout: Option<std::time::Duration>,
+    /// Don't pass ffmpeg's output through.
+    pub quiet: bool,
+    /// Leave the temp files (the concat list, chapters and filter graph) behind after the run, and log where.
+    pub keep_temp: bool,
+    /// Print each stitching ffmpeg command on stderr, shell-quoted, before it runs. the temp files it reads (the
+    /// concat list, chapters and filter graph) are kept, like `keep_temp`, so the command can be rerun as it is.
+    pub show_command: bool,
+    /// Audio bitrate to encode at, in kbit/s.
+    pub bitrate_kbps: Option<u32>,
+    /// VBR quality to encode at, on the codec's own scale (e.g. LAME's 0-9). see `codec::vbr_quality_args`.
+    pub vbr_quality: Option<f64>,
+    /// Compression level to encode at, on the codec's own scale (e.g. FLAC's 0-12).
+    pub compression_level: Option<u8>,
+    /// Aim for an output of about this many bytes, by picking the bitrate from the total input duration.
+    pub target_size_bytes: Option<u64>,
+    /// Seconds of silence before the first input. zero is no silence.
+    pub pre_gap_secs: f64,
+    /// Seconds of silence after the last input. zero is no silence.
+    pub post_gap_secs: f64,
+    /// Seconds of silence between one input and the next. zero is no silence.
+    pub gap_secs: f64,
+    /// Seconds each input fades into the next one over. zero is a hard cut. a `gap_secs` between
+    /// the inputs means there's nothing to fade into, so it wins.
+    pub crossfade_secs: f64,
+    /// Seconds the output fades in from silence over, at its start. zero is no fade.
+    pub fade_in_secs: f64,
+    /// Seconds the output fades out to silence over, at its end. zero is no fade.
+    pub fade_out_secs: f64,
+    /// How long the output comes out, in seconds, so `fade_out_secs` knows where to start. worked out from
+    /// the probed inputs when needed.
+    pub output_duration_secs: Option<f64>,
+    /// Decode every input on its own and join the decoded audio, so the encoder delay and padding that
+    /// MP3 (and AAC) encoders add are trimmed from each one instead of being heard as a gap at every join.
+    pub gapless: bool,
+    /// Cut the silence (as quiet as these settings say) off the start and end of every input, leaving pauses
+    /// in the middle (see `filtergraph::trim_silence_filters`).
+    pub trim_silence: Option<crate::split::SilenceSettings>,
+    /// Format to generate silence in, so it can be joined to the inputs. probed from the inputs when needed.
+    pub silence_format: Option<crate::probe::AudioFormat>,
+    /// Convert every input to this format before joining them, so inputs that don't match can still be
+    /// joined. filled in when probing finds the inputs differ.
+    pub conform_format: Option<crate::probe::AudioFormat>,
+    /// Convert every input to this sample rate (keeping the first input's channel layout), rather than only
+    /// converting when they don't match. nothing is converted if they're all at that rate already.
+    pub resample: Option<crate::probe::SampleRate>,
+    /// Force this ffmpeg demuxer for every input, instead of letting ffmpeg guess from the contents.
+    pub input_format: Option<String>,
+    /// Embed a chapter per input in the output.
+    pub chapters: bool,
+    /// Write a CUE sheet with a track per input next to the output, at `cue_sheet_path(output)`.
+    pub cue: bool,
+    /// Write the chapters (one per input, as for `chapters`) to this FFMETADATA file too.
+    pub chapters_out: Option<PathBuf>,
+    /// Tags to set on the output, as `(key, value)` pairs, e.g. `("title", "Side A")`.
+    pub metadata: Vec<(String, String)>,
+    /// Carry the inputs' own tags into the output too, under any in `metadata`. read from the inputs before
+    /// the stitch.
+    pub copy_metadata: Option<crate::tags::CopyMetadata>,
+    /// More ffmpeg arguments, passed through as they are just before the output path.
+    pub extra_ffmpeg_args: Vec<String>,
+    /// The part of each input to use, for inputs that aren't used whole. from a playlist's `start=`/`end=`.
+    pub trims: std::collections::HashMap<PathBuf, crate::playlist::Trim>,
+    /// How much louder (or, negative, quieter) to make each input, in dB, for inputs that need it. from a
+    /// playlist's `gain=`.
+    pub gains_db: std::collections::HashMap<PathBuf, f64>,
+    /// Which extensions are inputs and outputs, and their codecs. `FormatTable::with_video` stitches
+    /// video containers instead of audio files.
+    pub formats: crate::formats::FormatTable,
+    /// What does the stitching: an ffmpeg binary, or a library linked in at build time.
+    pub backend: Backend,
+}
+
+/// The `output` that means stdout.
+pub const STDOUT_OUTPUT: &str = "-";
+
+/// What does the stitching. everything but `Cli` is behind a cargo feature of the same name, and only
+/// stream copies (see `StitchOptions::only_copies`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Backend {
+    /// Run an ffmpeg binary.
+    #[default]
+    Cli,
+    /// Remux in-process with libav, through ffmpeg-next. needs the `libav` feature.
+    Libav,
+    /// Decode, concat and encode again with a GStreamer pipeline, in-process. needs the `gstreamer` feature.
+    Gstreamer,
+}
+
+impl Backend {
+    /// The name `--backend` takes, which is also the name of its cargo feature.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Cli => "cli",
+            Backend::Libav => "libav",
+            Backend::Gstreamer => "gstreamer",
+        }
+    }
+
+    /// Whether this build can use the backend.
+    pub fn is_built(&self) -> bool {
+        match self {
+            Backend::Cli => true,
+            Backend::Libav => cfg!(feature = "libav"),
+            Backend::Gstreamer => cfg!(feature = "gstreamer"),
+        }
+    }
+}
+
+/// A problem with a `StitchOptions` that can be spotted without running ffmpeg or probing any input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The output path has no extension, so ffmpeg can't pick a container.
+    MissingOutputExtension,
+    /// The output extension isn't one stitcher knows how to produce.
+    UnsupportedOutputExtension(String),
+    /// `flac_recompress` only applies to `.flac` output.
+    FlacRecompressNeedsFlacOutput,
+    /// `keep_cover` was asked for, but the output container can't hold attached pictures.
+    CoverNotSupportedByOutput(String),
+    /// `keep_cover` needs the stream-copy path, but another option needs a filter graph.
+    CoverNeedsStreamCopy,
+    /// `target_size_bytes` picks a bitrate, which lossless outputs don't have.
+    TargetSizeNeedsLossyOutput(String),
+    /// `chapters` was asked for, but the output container can't hold chapters.
+    ChaptersNotSupportedByOutput(String),
+    /// Video is only ever stream copied, but another option needs the audio decoded.
+    VideoNeedsStreamCopy,
+    /// This input's trim ends before it starts, so there'd be nothing left of it.
+    EmptyTrim(String),
+    /// Chapters (or a cue sheet) were asked for, but trimming silence moves the inputs by an amount that
+    /// isn't known until ffmpeg has run.
+    ChaptersNeedUntrimmedInputs,
+    /// A fade out was asked for, but trimming silence makes the output's length unknown until ffmpeg has run.
+    FadeOutNeedsUntrimmedInputs,
+    /// This backend's cargo feature wasn't enabled when stitcher was built.
+    BackendNotBuilt(Backend),
+    /// This backend only joins the inputs, but another option asks for more than that.
+    BackendNeedsStreamCopy(Backend),
+    /// --strict checks every input with ffprobe before stitching, which only the CLI backend does.
+    StrictNeedsCliBackend(Backend),
+    /// This backend has no way to write the output's extension.
+    BackendCantWrite(Backend, String),
+    /// `format` was asked for, but the output's extension is something else.
+    OutputNotInFormat(String),
+    /// The codec doesn't take the VBR quality or compression level asked for. says why.
+    QualityNotSupported(String),
+    /// The output is stdout, which has no extension to pick a container by, and `format` wasn't given.
+    StdoutNeedsFormat,
+    /// The output is stdout, but this option needs it to be a file.
+    NeedsOutputFile(String),
+    /// --min-duration is longer than --max-duration, so no file could be kept.
+    MinDurationOverMax,
+    /// An audiobook was asked for, but the output's extension isn't one of the MP4 audiobook containers.
+    AudiobookNeedsMp4Output(String),
+    /// This input, once trimmed, is no longer than the crossfade into or out of it.
+    CrossfadeOverInput(String),
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::MissingOutputExtension => {
+                write!(f, "the output file needs an extension, like .wav")
+            }
+            ValidationError::UnsupportedOutputExtension(ext) => {
+                write!(f, "unsupported output extension: .{}", ext)
+            }
+            ValidationError::FlacRecompressNeedsFlacOutput => {
+                write!(f, "--flac-recompress needs a .flac output file")
+            }
+            ValidationError::CoverNotSupportedByOutput(ext) => {
+                write!(f, "--keep-cover can't be used with .{} output: it can't hold cover art", ext)
+            }
+            ValidationError::TargetSizeNeedsLossyOutput(ext) => {
+                write!(f, "--target-size needs a lossy output format, not .{}", ext)
+            }
+            ValidationError::ChaptersNotSupportedByOutput(ext) => {
+                write!(f, "--chapters can't be used with .{} output: it can't hold chapters (try .mka)", ext)
+            }
+            ValidationError::CoverNeedsStreamCopy => {
+                write!(f, "--keep-cover can't be combined with per-input processing like --normalize-each")
+            }
+            ValidationError::VideoNeedsStreamCopy => {
+                write!(f, "--video stream copies, so it can't be combined with options that re-encode")
+            }
+            ValidationError::EmptyTrim(file) => {
+                write!(f, "the trim of {} ends before it starts", file)
+            }
+            ValidationError::OutputNotInFormat(format) => {
+                write!(f, "--format {} needs an output file ending in .{}", format, format)
+            }
+            ValidationError::QualityNotSupported(reason) => write!(f, "{}", reason),
+            ValidationError::StdoutNeedsFormat => {
+                write!(f, "--out - needs --format, to say what to write to stdout")
+            }
+            ValidationError::NeedsOutputFile(flag) => {
+                write!(f, "{} can't be used with --out -: it needs an output file", flag)
+            }
+            ValidationError::MinDurationOverMax => {
+                write!(f, "--min-duration is longer than --max-duration, so every file would be left out")
+            }
+            ValidationError::AudiobookNeedsMp4Output(ext) => {
+                write!(f, "--audiobook needs an .m4b or .m4a output file, not .{}", ext)
+            }
+            ValidationError::CrossfadeOverInput(file) => {
+                write!(f, "--crossfade is at least as long as {}: each input has to outlast it", file)
+            }
+            ValidationError::BackendNotBuilt(backend) => {
+                write!(f, "--backend {0} isn't in this build: rebuild with `--features {0}`", backend.name())
+            }
+            ValidationError::BackendCantWrite(backend, ext) => {
+                write!(f, "--backend {} can't write .{} files", backend.name(), ext)
+            }
+            ValidationError::BackendNeedsStreamCopy(backend) => {
+                write!(f, "--backend {} only joins the inputs, so it can't be combined with options that re-encode, filter, or add chapters, tags or trims", backend.name())
+            }
+            ValidationError::StrictNeedsCliBackend(backend) => {
+                write!(f, "--strict can't be used with --backend {}: only the cli backend checks the inputs first", backend.name())
+            }
+            ValidationError::FadeOutNeedsUntrimmedInputs => {
+                write!(f, "--trim-silence can't be combined with --fade-out: the trimmed length isn't known")
+            }
+            ValidationError::ChaptersNeedUntrimmedInputs => {
+                write!(f, "--trim-silence can't be combined with --chapters, --cue or --chapters-out: the trimmed lengths aren't known")
+            }
+        }
+    }
+}
+
+impl StitchOptions {
+    /// Whether the inputs have to go through a `-filter_complex` graph, rather than the concat demuxer.
+    pub fn needs_filter_graph(&self) -> bool {
+        // the concat demuxer has no way to force the demuxer of the files it lists, or to convert them,
+        // so either means giving ffmpeg each file as its own input
+        //
+        self.normalize_each
+            || self.normalize_lufs.is_some()
+            || self.generates_silence()
+            || self.crossfade_secs > 0.0
+            || self.fade_in_secs > 0.0
+            || self.fade_out_secs > 0.0
+            || self.gapless
+            || self.trim_silence.is_some()
+            || self.input_format.is_some()
+            || self.conform_format.is_some()
+            || !self.gains_db.is_empty()
+    }
+
+    /// Whether the output goes to stdout rather than a file.
+    pub fn writes_to_stdout(&self) -> bool {
+        self.output == Path::new(STDOUT_OUTPUT)
+    }
+
+    /// The output container, by extension: `output`'s, or `format` when writing to stdout.
+    pub fn output_format(&self) -> Option<String> {
+        match self.writes_to_stdout() {
+            true => self.format.clone(),
+            false => output_extension(&self.output),
+        }
+    }
+
+    /// Whether ffmpeg's `-progress` stream is read, for `progress_json` or `progress_bar`.
+    pub fn reports_progress(&self) -> bool {
+        self.progress_json || self.progress_bar
+    }
+
+    /// Whether any silence has to be generated to go with the inputs.
+    pub fn generates_silence(&self) -> bool {
+        self.pre_gap_secs > 0.0 || self.post_gap_secs > 0.0 || self.gap_secs > 0.0
+    }
+
+    /// Seconds from the end of one input to the start of the next in the output: the gap between them,
+    /// or minus the crossfade where they overlap.
+    pub fn spacing_secs(&self) -> f64 {
+        match (self.gap_secs > 0.0, self.crossfade_secs > 0.0) {
+            (true, _) => self.gap_secs,
+            (false, true) => -self.crossfade_secs,
+            (false, false) => 0.0,
+        }
+    }
+
+    /// Whether the output audio is encoded, rather than stream copied.
+    pub fn encodes(&self) -> bool {
+        self.reencode
+            || self.target_size_bytes.is_some()
+            || self.bitrate_kbps.is_some()
+            || self.vbr_quality.is_some()
+            || self.compression_level.is_some()
+            || self.needs_filter_graph()
+    }
+
+    /// Whether the audio only has to be copied across as it is: nothing is encoded, filtered, trimmed or tagged,
+    /// and there are no chapters, cover art or video to carry.
+    pub fn only_copies(&self) -> bool {
+        !self.encodes()
+            && self.flac_recompress.is_none()
+            && self.codec.is_none()
+            && !self.keep_cover
+            && !self.chapters
+            && !self.cue
+            && self.chapters_out.is_none()
+            && self.metadata.is_empty()
+            && self.copy_metadata.is_none()
+            && self.extra_ffmpeg_args.is_empty()
+            && self.trims.is_empty()
+            && !self.formats.is_video()
+    }
+
+    /// `validate`, with every problem in one `StitcherError::InvalidOptions`.
+    pub fn check(&self) -> Result<(), crate::error::StitcherError> {
+        self.validate().map_err(crate::error::StitcherError::InvalidOptions)
+    }
+
+    /// Run every static check and return all of the problems at once, so a UI can show them together.
+    /// nothing here spawns a process or touches the inputs.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = vec![];
+
+        let ext = self.output_format();
+        match &ext {
+            None if self.writes_to_stdout() => errors.push(ValidationError::StdoutNeedsFormat),
+            None => errors.push(ValidationError::MissingOutputExtension),
+            // re-encoding, --flac-recompress's included, can also produce any container we know a default codec for
+            //
+            Some(ext)
+                if (self.encodes() || self.format.is_some() || self.flac_recompress.is_some())
+                    && self.formats.default_codec(ext).is_some() => (),
+            // Matroska holds whatever codec the inputs are in, so a stream copy into it always works
+            //
+            Some(ext) if ext.eq_ignore_ascii_case("mka") => (),
+            Some(ext) if !self.formats.is_supported(ext) => {
+                errors.push(ValidationError::UnsupportedOutputExtension(ext.clone()))
+            }
+            Some(_) => (),
+        }
+
+        if self.writes_to_stdout() {
+            // ffmpeg's stdout carries the output, so there's nowhere for the progress stream to go
+            //
+            let needs_file = [
+                (self.progress_json, "--progress-json"),
+                (self.progress_bar, "--progress"),
+                (self.cue, "--cue"),
+                (self.backend != Backend::Cli, "--backend"),
+            ];
+            for (_, flag) in needs_file.into_iter().filter(|(set, _)| *set) {
+                errors.push(ValidationError::NeedsOutputFile(flag.to_string()));
+            }
+        }
+
+        let in_format = |format: &str| ext.as_deref().is_some_and(|ext| ext.eq_ignore_ascii_case(format));
+        if let Some(format) = self.format.as_deref().filter(|format| !in_format(format)) {
+            errors.push(ValidationError::OutputNotInFormat(format.to_string()));
+        }
+
+        if self.flac_recompress.is_some() && !ext.as_deref().is_some_and(|ext| ext.eq_ignore_ascii_case("flac")) {
+            errors.push(ValidationError::FlacRecompressNeedsFlacOutput);
+        }
+
+        if self.keep_cover {
+            if let Some(ext) = ext.as_deref().filter(|ext| ext.eq_ignore_ascii_case("wav")) {
+                errors.push(ValidationError::CoverNotSupportedByOutput(ext.to_string()));
+            }
+            if self.needs_filter_graph() {
+                errors.push(ValidationError::CoverNeedsStreamCopy);
+            }
+        }
+
+        if self.target_size_bytes.is_some() {
+            if let Some(ext) = ext.as_deref().filter(|ext| ["wav", "flac"].iter().any(|x| x.eq_ignore_ascii_case(ext))) {
+                errors.push(ValidationError::TargetSizeNeedsLossyOutput(ext.to_string()));
+            }
+        }
+
+        if self.formats.is_video() && (self.encodes() || self.flac_recompress.is_some()) {
+            errors.push(ValidationError::VideoNeedsStreamCopy);
+        }
+
+        // the codec is only known for sure when it was asked for, or the output extension has a default
+        //
+        if let Some(codec) = self.codec.as_deref().or_else(|| self.formats.default_codec(ext.as_deref()?)) {
+            let vbr = self.vbr_quality.map(|quality| crate::codec::vbr_quality_args(codec, quality));
+            let level = self.compression_level.map(|level| crate::codec::compression_level_args(codec, level));
+            for result in [vbr, level].into_iter().flatten() {
+                if let Err(reason) = result {
+                    errors.push(ValidationError::QualityNotSupported(reason));
+                }
+            }
+        }
+
+        if self.chapters {
+            if let Some(ext) = ext.as_deref().filter(|ext| !crate::chapters::holds_chapters(ext)) {
+                errors.push(ValidationError::ChaptersNotSupportedByOutput(ext.to_string()));
+            }
+        }
+
+        if self.trim_silence.is_some() && (self.chapters || self.cue || self.chapters_out.is_some()) {
+            errors.push(ValidationError::ChaptersNeedUntrimmedInputs);
+        }
+        if self.trim_silence.is_some() && self.fade_out_secs > 0.0 {
+            errors.push(ValidationError::FadeOutNeedsUntrimmedInputs);
+        }
+
+        if !self.backend.is_built() {
+            errors.push(ValidationError::BackendNotBuilt(self.backend));
+        } else if self.backend != Backend::Cli && !self.only_copies() {
+            errors.push(ValidationError::BackendNeedsStreamCopy(self.backend));
+        }
+        if self.strict && self.backend != Backend::Cli {
+            errors.push(ValidationError::StrictNeedsCliBackend(self.backend));
+        }
+        // an unbuilt GStreamer backend has already been refused above
+        //
+        #[cfg(feature = "gstreamer")]
+        if let Some(ext) = ext.as_deref().filter(|ext| self.backend == Backend::Gstreamer && crate::gstreamer::encoder(ext).is_none()) {
+            errors.push(ValidationError::BackendCantWrite(self.backend, ext.to_string()));
+        }
+
+        let mut empty_trims = self
+            .trims
+            .iter()
+            .filter(|(_, trim)| trim.start_secs.zip(trim.end_secs).is_some_and(|(start, end)| end <= start))
+            .map(|(file, _)| file.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        empty_trims.sort();
+        errors.extend(empty_trims.into_iter().map(ValidationError::EmptyTrim));
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+}
+
+/// Where the CUE sheet for `output` goes: next to it, with a `.cue` extension.
+pub fn cue_sheet_path(output: &Path) -> PathBuf {
+    output.with_extension("cue")
+}
+
+/// Extension of `path`, if it has one, as it's written. compare it with `eq_ignore_ascii_case`.
+pub fn output_extension(path: &Path) -> Option<String> {
+    Some(path.extension()?.to_str()?.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_validate_reports_every_problem() {
+        let options = StitchOptions {
+            output: PathBuf::from("out.wav"),
+            flac_recompress: Some(5),
+            keep_cover: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            options.validate(),
+            Err(vec![
+                ValidationError::FlacRecompressNeedsFlacOutput,
+                ValidationError::CoverNotSupportedByOutput(String::from("wav")),
+            ])
+        );
+
+        let unsupported = StitchOptions {
+            output: PathBuf::from("out.txt"),
+            ..Default::default()
+        };
+        assert_eq!(
+            unsupported.validate(),
+            Err(vec![ValidationError::UnsupportedOutputExtension(String::from("txt"))])
+        );
+
+        let valid = StitchOptions {
+            output: PathBuf::from("out.FLAC"),
+            flac_recompress: Some(5),
+            keep_cover: true,
+            ..Default::default()
+        };
+        assert_eq!(valid.validate(), Ok(()));
+
+        let uppercase = StitchOptions {
+            output: PathBuf::from("OUT.WAV"),
+            keep_cover: true,
+            ..Default::default()
+        };
+        assert_eq!(uppercase.validate(), Err(vec![ValidationError::CoverNotSupportedByOutput(String::from("WAV"))]));
+
+        let reencoded = StitchOptions {
+            output: PathBuf::from("out.m4a"),
+            reencode: true,
+            ..Default::default()
+        };
+        assert_eq!(reencoded.validate(), Ok(()));
+
+        let formatted = StitchOptions {
+            output: PathBuf::from("out.opus"),
+            format: Some(String::from("opus")),
+            ..Default::default()
+        };
+        assert_eq!(formatted.validate(), Ok(()));
+        let misnamed = StitchOptions {
+            output: PathBuf::from("out.ogg"),
+            ..formatted
+        };
+        assert_eq!(misnamed.validate(), Err(vec![ValidationError::OutputNotInFormat(String::from("opus"))]));
+        let uppercase = StitchOptions {
+            output: PathBuf::from("OUT.WAV"),
+            format: Some(String::from("wav")),
+            ..Default::default()
+        };
+        assert_eq!(uppercase.validate(), Ok(()));
+    }
+
+    #[test]
+    pub fn test_other_backends_only_stream_copy() {
+        let libav = StitchOptions {
+            output: PathBuf::from("out.mp3"),
+            backend: Backend::Libav,
+            ..Default::default()
+        };
+        let expected = match cfg!(feature = "libav") {
+            true => Ok(()),
+            false => Err(vec![ValidationError::BackendNotBuilt(Backend::Libav)]),
+        };
+        assert_eq!(libav.validate(), expected);
+
+        let filtered = StitchOptions { gap_secs: 1.0, ..libav.clone() };
+        let expected = match cfg!(feature = "libav") {
+            true => ValidationError::BackendNeedsStreamCopy(Backend::Libav),
+            false => ValidationError::BackendNotBuilt(Backend::Libav),
+        };
+        assert_eq!(filtered.validate(), Err(vec![expected]));
+        assert!(!filtered.only_copies());
+
+        let gstreamer = StitchOptions {
+            output: PathBuf::from("out.mka"),
+            backend: Backend::Gstreamer,
+            ..Default::default()
+        };
+        let expected = match cfg!(feature = "gstreamer") {
+            true => ValidationError::BackendCantWrite(Backend::Gstreamer, String::from("mka")),
+            false => ValidationError::BackendNotBuilt(Backend::Gstreamer),
+        };
+        assert_eq!(gstreamer.validate(), Err(vec![expected]));
+
+        let strict = StitchOptions { strict: true, ..libav };
+        assert!(strict.validate().expect_err("expected --strict to be rejected").contains(&ValidationError::StrictNeedsCliBackend(Backend::Libav)));
+    }
+}
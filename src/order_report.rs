@@ -0,0 +1,165 @@
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::error::StitcherError;
+
+/// First line of every order report, so the format can change later without breaking old readers.
+pub const HEADER: &str = "# stitcher order report v1";
+
+/// Build an audit trail of exactly what was stitched, in what order: one tab-separated line per
+/// input of `index`, `sha256`, `size in bytes` and `path`, in final stitch order.
+pub fn build(files: &[PathBuf]) -> Result<String, StitcherError> {
+    let mut report = String::from(HEADER);
+    report.push('\n');
+    report.push_str("index\tsha256\tsize\tpath\n");
+
+    for (index, file) in files.iter().enumerate() {
+        let size = std::fs::metadata(file)
+            .map_err(|e| StitcherError::io(format!("reading the size of {}", file.to_string_lossy()), e))?
+            .len();
+        let hash = sha256_file(file)?;
+        report.push_str(&format!("{}\t{}\t{}\t{}\n", index, hash, size, file.to_string_lossy()));
+    }
+
+    Ok(report)
+}
+
+pub fn write(path: &Path, files: &[PathBuf]) -> Result<(), StitcherError> {
+    let report = build(files)?;
+    std::fs::write(path, report)
+        .map_err(|e| StitcherError::io(format!("writing the order report {}", path.to_string_lossy()), e))
+}
+
+/// Hash algorithms `--checksum` can write a manifest with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChecksumAlgorithm {
+    #[default]
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    /// The extension of its manifest, which is also the name of the tool that checks it (`sha256sum -c`).
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "sha256",
+        }
+    }
+}
+
+/// Where the checksum manifest for `output` goes: next to it, with the algorithm's extension added,
+/// e.g. `out.wav.sha256`.
+pub fn checksum_path(output: &Path, algorithm: ChecksumAlgorithm) -> PathBuf {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".");
+    path.push(algorithm.extension());
+    PathBuf::from(path)
+}
+
+/// A `sha256sum`-style manifest (`hash  name` lines) for `output`, then each of `inputs`. the output is
+/// listed by file name, so `sha256sum -c` works from the directory it's in; inputs are listed as given.
+pub fn checksum_manifest(output: &Path, inputs: &[PathBuf]) -> Result<String, StitcherError> {
+    let name = output.file_name().map(Path::new).unwrap_or(output);
+    let mut manifest = format!("{}  {}\n", sha256_file(output)?, name.to_string_lossy());
+    for input in inputs {
+        manifest.push_str(&format!("{}  {}\n", sha256_file(input)?, input.to_string_lossy()));
+    }
+    Ok(manifest)
+}
+
+/// Write the checksum manifest for `output` (and `inputs`, which can be empty) to `checksum_path`. returns its path.
+pub fn write_checksum(
+    output: &Path,
+    inputs: &[PathBuf],
+    algorithm: ChecksumAlgorithm,
+) -> Result<PathBuf, StitcherError> {
+    let path = checksum_path(output, algorithm);
+    let manifest = checksum_manifest(output, inputs)?;
+    std::fs::write(&path, manifest)
+        .map_err(|e| StitcherError::io(format!("writing the checksum manifest {}", path.to_string_lossy()), e))?;
+    Ok(path)
+}
+
+/// Hex SHA-256 of a file's contents.
+pub fn sha256_file(path: &Path) -> Result<String, StitcherError> {
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| StitcherError::io(format!("opening {} for hashing", path.to_string_lossy()), e))?;
+
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => hasher.update(&buf[..n]),
+            Err(e) => return Err(StitcherError::io(format!("hashing {}", path.to_string_lossy()), e)),
+        }
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_order_report_lists_files_in_final_order() {
+        let dir = std::env::temp_dir().join(format!("stitcher_test_order_report_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create a scratch directory");
+
+        let files = [("b.wav", "bb"), ("a.wav", ""), ("c.wav", "abc")]
+            .map(|(name, contents)| {
+                let path = dir.join(name);
+                std::fs::write(&path, contents).expect("failed to write a scratch file");
+                path
+            });
+
+        let report = build(&files).expect("expected the report to build");
+        let lines = report.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], HEADER);
+
+        let rows = lines[2..]
+            .iter()
+            .map(|line| line.split('\t').collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 3);
+        for (i, row) in rows.iter().enumerate() {
+            assert_eq!(row[0], i.to_string(), "expected position indices in stitch order");
+            assert_eq!(row[3], files[i].to_string_lossy(), "expected files in stitch order");
+        }
+
+        assert_eq!(rows[0][2], "2");
+        assert_eq!(rows[1][1], "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(rows[2][1], "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_checksum_manifest_lists_the_output_then_inputs() {
+        let dir = std::env::temp_dir().join(format!("stitcher_test_checksum_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create a scratch directory");
+        let output = dir.join("out.wav");
+        let input = dir.join("a.wav");
+        std::fs::write(&output, "abc").expect("failed to write a scratch file");
+        std::fs::write(&input, "").expect("failed to write a scratch file");
+
+        let path = write_checksum(&output, std::slice::from_ref(&input), ChecksumAlgorithm::Sha256)
+            .expect("expected the manifest to be written");
+        assert_eq!(path, dir.join("out.wav.sha256"));
+        assert_eq!(
+            std::fs::read_to_string(&path).expect("expected to read the manifest back"),
+            format!(
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  out.wav\n\
+                 e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  {}\n",
+                input.to_string_lossy()
+            )
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,224 @@
+use std::path::{Path, PathBuf};
+
+/// Input extensions that are read as a playlist of files to stitch, rather than stitched themselves.
+pub const PLAYLIST_EXTENSIONS: &[&str] = &["m3u", "m3u8"];
+
+/// Whether `path` looks like an M3U playlist, going by its extension.
+pub fn is_playlist(path: &Path) -> bool {
+    path.extension()
+        .and_then(|x| x.to_str())
+        .is_some_and(|ext| PLAYLIST_EXTENSIONS.iter().any(|x| x.eq_ignore_ascii_case(ext)))
+}
+
+/// How much of an input to use: from `start_secs` (or its start) to `end_secs` (or its end).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Trim {
+    pub start_secs: Option<f64>,
+    pub end_secs: Option<f64>,
+}
+
+impl Trim {
+    /// How long an input of `duration_secs` is once trimmed.
+    pub fn trimmed_secs(&self, duration_secs: f64) -> f64 {
+        let end_secs = self.end_secs.map_or(duration_secs, |end| end.min(duration_secs));
+        (end_secs - self.start_secs.unwrap_or(0.0)).max(0.0)
+    }
+}
+
+/// One playlist entry: a file, the part of it to use, and how much louder (or, negative, quieter) to make it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub trim: Trim,
+    pub gain_db: Option<f64>,
+}
+
+/// The entries of an M3U playlist, in order. `#EXTM3U`, `#EXTINF` and any other `#` lines are
+/// directives or comments and get skipped, as do blank lines. relative entries are relative to `base_dir`
+/// (the playlist's own directory), which is how players write them.
+pub fn parse(contents: &str, base_dir: &Path) -> Vec<PathBuf> {
+    parse_entries(contents, base_dir).into_iter().map(|entry| entry.path).collect()
+}
+
+/// `parse`, keeping each entry's trim and gain: a path can be followed by `start=` and/or `end=` timestamps
+/// (seconds, `MM:SS` or `HH:MM:SS`, with or without a fraction), e.g. `take 1.wav start=0:03.5 end=1:20`,
+/// and a `gain=` in dB, e.g. `take 2.wav gain=-3.5`.
+pub fn parse_entries(contents: &str, base_dir: &Path) -> Vec<Entry> {
+    contents
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (path, trim, gain_db) = split_fields(line);
+            Entry {
+                path: base_dir.join(entry_path(path)),
+                trim,
+                gain_db,
+            }
+        })
+        .collect()
+}
+
+/// The path a playlist entry names. playlists written on Windows separate directories with `\`, which
+/// only Windows reads as a separator, so elsewhere they're turned into `/`. an entry with a drive letter
+/// (`C:\Music\a.mp3`) is absolute on Windows, so `base_dir` doesn't apply to it there.
+fn entry_path(entry: &str) -> PathBuf {
+    match cfg!(windows) {
+        true => PathBuf::from(entry),
+        false => PathBuf::from(entry.replace('\\', "/")),
+    }
+}
+
+/// Split the `start=`/`end=`/`gain=` fields off the end of a playlist line. anything that doesn't parse as
+/// one is left as part of the path, since file names can have spaces and `=` in them.
+fn split_fields(line: &str) -> (&str, Trim, Option<f64>) {
+    let mut path = line;
+    let mut trim = Trim::default();
+    let mut gain_db = None;
+    while let Some((rest, field)) = path.rsplit_once(char::is_whitespace) {
+        match field.split_once('=') {
+            Some(("start", value)) if trim.start_secs.is_none() => match parse_timestamp(value) {
+                Some(secs) => trim.start_secs = Some(secs),
+                None => break,
+            },
+            Some(("end", value)) if trim.end_secs.is_none() => match parse_timestamp(value) {
+                Some(secs) => trim.end_secs = Some(secs),
+                None => break,
+            },
+            Some(("gain", value)) if gain_db.is_none() => match parse_gain(value) {
+                Some(db) => gain_db = Some(db),
+                None => break,
+            },
+            _ => break,
+        }
+        path = rest.trim_end();
+    }
+    (path, trim, gain_db)
+}
+
+/// Decibels from a gain like `-3.5` or `+2dB`.
+pub fn parse_gain(gain: &str) -> Option<f64> {
+    let gain = gain.strip_suffix("dB").or_else(|| gain.strip_suffix("db")).unwrap_or(gain);
+    gain.parse::<f64>().ok().filter(|db| db.is_finite())
+}
+
+/// Seconds from a timestamp like `75`, `1:15.5` or `0:01:15`.
+pub fn parse_timestamp(timestamp: &str) -> Option<f64> {
+    let parts = timestamp.split(':').collect::<Vec<_>>();
+    if parts.len() > 3 {
+        return None;
+    }
+
+    let (secs, units) = parts.split_last()?;
+    let secs = secs.parse::<f64>().ok().filter(|secs| secs.is_finite() && *secs >= 0.0)?;
+    let minutes = units
+        .iter()
+        .try_fold(0u32, |total, unit| total.checked_mul(60)?.checked_add(unit.parse::<u32>().ok()?))?;
+    Some(minutes as f64 * 60.0 + secs)
+}
+
+/// The paths of a plain file list, like `find` or `fzf` print: one per line, as written, in order. unlike a
+/// playlist, nothing is a comment, and relative paths stay relative to wherever stitcher runs. `find -print0`'s
+/// NUL separators work too.
+pub fn parse_file_list(contents: &str) -> Vec<PathBuf> {
+    contents
+        .split(['\n', '\0'])
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.trim().is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Read the playlist at `path`. plain `.m3u` files are often in the player's local encoding instead of
+/// UTF-8, so anything that doesn't decode is replaced rather than failing the whole playlist.
+pub fn load(path: &Path) -> Result<Vec<PathBuf>, String> {
+    Ok(load_entries(path)?.into_iter().map(|entry| entry.path).collect())
+}
+
+/// `load`, keeping each entry's trim and gain.
+pub fn load_entries(path: &Path) -> Result<Vec<Entry>, String> {
+    let contents = std::fs::read(path)
+        .map_err(|e| format!("failed to read the playlist {}: {:?}", path.to_string_lossy(), e))?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    Ok(parse_entries(&String::from_utf8_lossy(&contents), base_dir))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_parse_playlist() {
+        let contents = "\u{feff}#EXTM3U\r\n\
+                        #EXTINF:123,Artist - First\r\n\
+                        first.mp3\r\n\
+                        \r\n\
+                        #EXTINF:-1,Second\r\n\
+                        disc 2/second.flac\r\n\
+                        /music/third.wav\r\n";
+        let entries = parse(contents, Path::new("/playlists"));
+        assert_eq!(
+            entries,
+            ["/playlists/first.mp3", "/playlists/disc 2/second.flac", "/music/third.wav"].map(PathBuf::from)
+        );
+
+        let from_windows = parse("disc 2\\second.flac\r\n", Path::new("playlists"));
+        assert_eq!(from_windows, [Path::new("playlists").join("disc 2").join("second.flac")]);
+
+        assert!(is_playlist(Path::new("mix.M3U8")));
+        assert!(!is_playlist(Path::new("mix.wav")));
+    }
+
+    #[test]
+    pub fn test_parse_file_list() {
+        let contents = "./takes/#1 intro.wav\r\n\n/music/two.wav\n";
+        assert_eq!(parse_file_list(contents), ["./takes/#1 intro.wav", "/music/two.wav"].map(PathBuf::from));
+
+        let print0 = "a.wav\0b c.wav\0";
+        assert_eq!(parse_file_list(print0), ["a.wav", "b c.wav"].map(PathBuf::from));
+    }
+
+    #[test]
+    pub fn test_parse_entry_gains() {
+        let contents = "quiet take.wav gain=+4.5dB\n\
+                        loud take.wav start=2 gain=-3\n\
+                        plain.wav\n\
+                        odd gain=loud.wav\n";
+        let entries = parse_entries(contents, Path::new(""));
+        let gains = entries.iter().map(|x| (x.path.to_string_lossy().into_owned(), x.gain_db)).collect::<Vec<_>>();
+        assert_eq!(
+            gains,
+            vec![
+                (String::from("quiet take.wav"), Some(4.5)),
+                (String::from("loud take.wav"), Some(-3.0)),
+                (String::from("plain.wav"), None),
+                (String::from("odd gain=loud.wav"), None),
+            ]
+        );
+        assert_eq!(entries[1].trim.start_secs, Some(2.0));
+        assert_eq!(parse_gain("inf"), None);
+    }
+
+    #[test]
+    pub fn test_parse_trimmed_entries() {
+        let contents = "take 1.wav start=3.5\n\
+                        take 2.wav start=0:01 end=1:02:03.25\n\
+                        odd start=name.wav\n";
+        let entries = parse_entries(contents, Path::new("/sessions"));
+        let trims = entries.iter().map(|x| (x.path.clone(), x.trim)).collect::<Vec<_>>();
+        assert_eq!(
+            trims,
+            vec![
+                (PathBuf::from("/sessions/take 1.wav"), Trim { start_secs: Some(3.5), end_secs: None }),
+                (PathBuf::from("/sessions/take 2.wav"), Trim { start_secs: Some(1.0), end_secs: Some(3723.25) }),
+                (PathBuf::from("/sessions/odd start=name.wav"), Trim::default()),
+            ]
+        );
+
+        assert_eq!(trims[1].1.trimmed_secs(30.0), 29.0);
+        assert_eq!(parse_timestamp("1:2:3:4"), None);
+        assert_eq!(parse_timestamp("-1"), None);
+        assert_eq!(parse_timestamp("4294967295:0:0"), None);
+    }
+}
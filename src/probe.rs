@@ -0,0 +1,664 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::error::StitcherError;
+
+/// How often a probe with a timeout checks whether ffprobe has finished.
+const TIMEOUT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Work out where ffprobe lives, given the ffmpeg binary we already found.
+/// `$STITCHER_FFPROBE` wins if set. otherwise, static builds ship both side by side, so look
+/// next to ffmpeg, then fall back to $PATH.
+pub fn find_ffprobe_binary(ffmpeg_bin_path: &Path) -> Option<PathBuf> {
+    let from_env = std::env::var_os("STITCHER_FFPROBE").map(PathBuf::from);
+    let sibling = ffmpeg_bin_path.with_file_name(crate::exe_name("ffprobe"));
+    for path in from_env.into_iter().chain([sibling, PathBuf::from("ffprobe")]) {
+        let output = Command::new(&path).arg("-h").output();
+        if output.is_ok_and(|x| x.status.success()) {
+            return Some(path);
+        }
+    }
+
+    None
+}
+
+/// What to probe files with: ffprobe, found as for `find_ffprobe_binary`, or without it (and with the
+/// `symphonia` feature), symphonia, which reads the files itself. `None` if there's neither. ffprobe reads
+/// the inputs with the `input_format` demuxer when it's set, like ffmpeg does; symphonia has no such thing,
+/// so with `input_format` and no ffprobe, there's nothing to probe with rather than every input unreadable.
+pub fn find_probe(
+    ffmpeg_bin_path: &Path,
+    timeout: Option<Duration>,
+    input_format: Option<&str>,
+) -> Option<Box<dyn Probe>> {
+    match find_ffprobe_binary(ffmpeg_bin_path) {
+        Some(bin_path) => Some(Box::new(Ffprobe { bin_path, timeout, input_format: input_format.map(String::from) })),
+        None if input_format.is_some() => None,
+        None => native_probe(),
+    }
+}
+
+#[cfg(feature = "symphonia")]
+fn native_probe() -> Option<Box<dyn Probe>> {
+    log::debug!("no ffprobe found, so probing with symphonia");
+    Some(Box::new(crate::symphonia_probe::SymphoniaProbe))
+}
+
+#[cfg(not(feature = "symphonia"))]
+fn native_probe() -> Option<Box<dyn Probe>> {
+    None
+}
+
+/// Sample rate and channel count of an audio stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    pub channels: u32,
+}
+
+impl Default for AudioFormat {
+    fn default() -> Self {
+        AudioFormat {
+            sample_rate: 48000,
+            channels: 2,
+        }
+    }
+}
+
+impl AudioFormat {
+    /// The ffmpeg channel layout name for this channel count.
+    pub fn channel_layout(&self) -> String {
+        match self.channels {
+            1 => String::from("mono"),
+            2 => String::from("stereo"),
+            n => format!("{}c", n),
+        }
+    }
+}
+
+/// The sample rate to convert every input to, for `StitchOptions::resample`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleRate {
+    /// The highest rate among the inputs, so nothing is downsampled.
+    Max,
+    /// This many Hz.
+    Hz(u32),
+}
+
+impl SampleRate {
+    /// The rate to convert `formats` to.
+    pub fn pick(&self, formats: &[AudioFormat]) -> Option<u32> {
+        match self {
+            SampleRate::Max => formats.iter().map(|x| x.sample_rate).max(),
+            SampleRate::Hz(hz) => Some(*hz),
+        }
+    }
+}
+
+impl std::str::FromStr for SampleRate {
+    type Err = String;
+
+    /// `max`, or a rate in Hz like `48000`. `44.1k` and `48k` work too.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hz = match s.trim().to_lowercase() {
+            s if s == "max" => return Ok(SampleRate::Max),
+            s => match s.strip_suffix('k') {
+                Some(khz) => khz.parse::<f64>().map(|khz| (khz * 1000.0).round()),
+                None => s.parse::<f64>(),
+            },
+        };
+        match hz {
+            Ok(hz) if hz >= 1.0 && hz <= u32::MAX as f64 => Ok(SampleRate::Hz(hz as u32)),
+            _ => Err(format!("expected `max` or a sample rate in Hz, like 48000 or 44.1k, got `{}`", s)),
+        }
+    }
+}
+
+/// What has to match between inputs for them to join with a stream copy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamParams {
+    pub codec: String,
+    pub format: AudioFormat,
+}
+
+impl std::fmt::Display for StreamParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}Hz {}", self.codec, self.format.sample_rate, self.format.channel_layout())
+    }
+}
+
+/// Why a file couldn't be probed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProbeError {
+    /// The probe didn't finish within its timeout, and was killed.
+    TimedOut { file: PathBuf, after: Duration },
+    /// The file couldn't be read: it's missing, corrupt, or not something the probe can decode.
+    Unreadable(String),
+    Failed(String),
+}
+
+impl std::fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProbeError::TimedOut { file, after } => {
+                write!(f, "ffprobe timed out on {} after {:?}", file.to_string_lossy(), after)
+            }
+            ProbeError::Unreadable(message) | ProbeError::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Something that can inspect media files. ffprobe in real runs, a stand-in for tests.
+pub trait Probe {
+    /// Duration of `file` in seconds.
+    fn duration(&self, file: &Path) -> Result<f64, ProbeError>;
+
+    /// Format of the first audio stream in `file`.
+    fn audio_format(&self, file: &Path) -> Result<AudioFormat, ProbeError>;
+
+    /// Codec and format of the first audio stream in `file`.
+    fn stream_params(&self, file: &Path) -> Result<StreamParams, ProbeError>;
+
+    /// Tags of `file` (artist, album...), as `(key, value)` pairs. a probe that can't read tags finds none.
+    fn tags(&self, _file: &Path) -> Result<Vec<(String, String)>, ProbeError> {
+        Ok(vec![])
+    }
+}
+
+/// Probes files by running an ffprobe binary.
+#[derive(Debug, Clone)]
+pub struct Ffprobe {
+    pub bin_path: PathBuf,
+    /// Kill any ffprobe call that takes longer than this. `None` waits forever.
+    pub timeout: Option<Duration>,
+    /// Demuxer to read the files with (`-f`), for inputs ffprobe can't recognize on its own.
+    pub input_format: Option<String>,
+}
+
+impl Probe for Ffprobe {
+    fn duration(&self, file: &Path) -> Result<f64, ProbeError> {
+        probe_duration(&self.bin_path, file, self.input_format.as_deref(), self.timeout)
+    }
+
+    fn audio_format(&self, file: &Path) -> Result<AudioFormat, ProbeError> {
+        probe_audio_format(&self.bin_path, file, self.input_format.as_deref(), self.timeout)
+    }
+
+    fn stream_params(&self, file: &Path) -> Result<StreamParams, ProbeError> {
+        probe_stream_params(&self.bin_path, file, self.input_format.as_deref(), self.timeout)
+    }
+
+    fn tags(&self, file: &Path) -> Result<Vec<(String, String)>, ProbeError> {
+        probe_tags(&self.bin_path, file, self.input_format.as_deref(), self.timeout)
+    }
+}
+
+/// Run an ffprobe `command` on `file`, killing it if it's still going after `timeout`.
+fn output_with_timeout(command: &mut Command, file: &Path, timeout: Option<Duration>) -> Result<Output, ProbeError> {
+    let failed = |e: std::io::Error| ProbeError::Failed(format!("failed to run ffprobe on {}: {:?}", file.to_string_lossy(), e));
+
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(failed)?;
+
+    // ffprobe's output is a line or two (and with `-v error`, so are its complaints), so it can't fill a pipe and block while we wait
+    //
+    if let Some(timeout) = timeout {
+        let started = Instant::now();
+        while child.try_wait().map_err(failed)?.is_none() {
+            if started.elapsed() >= timeout {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(ProbeError::TimedOut { file: file.to_path_buf(), after: timeout });
+            }
+            std::thread::sleep(TIMEOUT_POLL_INTERVAL);
+        }
+    }
+
+    child.wait_with_output().map_err(failed)
+}
+
+/// The error for an ffprobe run on `file` that failed, with the last thing it said about why.
+fn unreadable(file: &Path, output: &Output) -> ProbeError {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let reason = match stderr.lines().map(str::trim).rfind(|line| !line.is_empty()) {
+        Some(line) => line.to_string(),
+        None => format!("exit not ok: {:?}", output.status),
+    };
+    ProbeError::Unreadable(format!("ffprobe could not read {}: {}", file.to_string_lossy(), reason))
+}
+
+/// An ffprobe command that only reports errors, reading its input with the `input_format` demuxer if set.
+fn ffprobe_command(ffprobe_bin_path: &Path, input_format: Option<&str>) -> Command {
+    let mut command = Command::new(ffprobe_bin_path);
+    command.arg("-v").arg("error");
+    if let Some(format) = input_format {
+        command.arg("-f").arg(format);
+    }
+    command
+}
+
+/// Ask ffprobe for the duration of a file, in seconds.
+pub fn probe_duration(
+    ffprobe_bin_path: &Path,
+    file: &Path,
+    input_format: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<f64, ProbeError> {
+    let mut command = ffprobe_command(ffprobe_bin_path, input_format);
+    command
+        .arg("-show_entries")
+        .arg("format=duration")
+        .arg("-of")
+        .arg("default=noprint_wrappers=1:nokey=1")
+        .arg(file);
+    let output = output_with_timeout(&mut command, file, timeout)?;
+
+    if !output.status.success() {
+        return Err(unreadable(file, &output));
+    }
+
+    parse_duration(&String::from_utf8_lossy(&output.stdout)).ok_or_else(|| {
+        ProbeError::Failed(format!("ffprobe reported no duration for {}", file.to_string_lossy()))
+    })
+}
+
+/// Ask ffprobe for the sample rate and channel count of the first audio stream in a file.
+pub fn probe_audio_format(
+    ffprobe_bin_path: &Path,
+    file: &Path,
+    input_format: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<AudioFormat, ProbeError> {
+    let mut command = ffprobe_command(ffprobe_bin_path, input_format);
+    command
+        .arg("-select_streams")
+        .arg("a:0")
+        .arg("-show_entries")
+        .arg("stream=sample_rate,channels")
+        .arg("-of")
+        .arg("default=noprint_wrappers=1")
+        .arg(file);
+    let output = output_with_timeout(&mut command, file, timeout)?;
+
+    if !output.status.success() {
+        return Err(unreadable(file, &output));
+    }
+
+    parse_audio_format(&String::from_utf8_lossy(&output.stdout)).ok_or_else(|| {
+        ProbeError::Failed(format!("ffprobe found no audio stream in {}", file.to_string_lossy()))
+    })
+}
+
+/// Ask ffprobe for the codec, sample rate and channel count of the first audio stream in a file.
+pub fn probe_stream_params(
+    ffprobe_bin_path: &Path,
+    file: &Path,
+    input_format: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<StreamParams, ProbeError> {
+    let mut command = ffprobe_command(ffprobe_bin_path, input_format);
+    command
+        .arg("-select_streams")
+        .arg("a:0")
+        .arg("-show_entries")
+        .arg("stream=codec_name,sample_rate,channels")
+        .arg("-of")
+        .arg("default=noprint_wrappers=1")
+        .arg(file);
+    let output = output_with_timeout(&mut command, file, timeout)?;
+
+    if !output.status.success() {
+        return Err(unreadable(file, &output));
+    }
+
+    parse_stream_params(&String::from_utf8_lossy(&output.stdout)).ok_or_else(|| {
+        ProbeError::Failed(format!("ffprobe found no audio stream in {}", file.to_string_lossy()))
+    })
+}
+
+/// Ask ffprobe for the tags of a file: the container's, then its first audio stream's, which is where Ogg
+/// and Opus files keep theirs.
+pub fn probe_tags(
+    ffprobe_bin_path: &Path,
+    file: &Path,
+    input_format: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<Vec<(String, String)>, ProbeError> {
+    let mut command = ffprobe_command(ffprobe_bin_path, input_format);
+    command
+        .arg("-select_streams")
+        .arg("a:0")
+        .arg("-show_entries")
+        .arg("format_tags:stream_tags")
+        .arg("-of")
+        .arg("default=noprint_wrappers=1")
+        .arg(file);
+    let output = output_with_timeout(&mut command, file, timeout)?;
+
+    if !output.status.success() {
+        return Err(unreadable(file, &output));
+    }
+
+    Ok(parse_tags(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Two inputs whose streams differ, so they can't be joined with a stream copy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub first: (PathBuf, StreamParams),
+    pub other: (PathBuf, StreamParams),
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} is {}, {} is {}",
+            self.first.0.to_string_lossy(),
+            self.first.1,
+            self.other.0.to_string_lossy(),
+            self.other.1
+        )
+    }
+}
+
+/// The first input whose streams differ from the first one's, if any. files that can't be probed
+/// are left out, since there's nothing to compare.
+pub fn find_mismatch(probe: &dyn Probe, files: &[PathBuf]) -> Option<Mismatch> {
+    let mut probed = files
+        .iter()
+        .filter_map(|file| probe.stream_params(file).ok().map(|params| (file.clone(), params)));
+    let first = probed.next()?;
+    let other = probed.find(|(_, params)| *params != first.1)?;
+
+    Some(Mismatch { first, other })
+}
+
+/// What `probe_inputs` made of the inputs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbedInputs {
+    /// The inputs that are left, in order.
+    pub files: Vec<PathBuf>,
+    /// Each of `files`' durations, if every one of them could be probed.
+    pub durations: Option<Vec<f64>>,
+    /// Why each input that was dropped couldn't be probed.
+    pub skipped: Vec<ProbeError>,
+}
+
+/// Probe the duration of every file, dropping the ones that can't be read or whose probe timed out: with
+/// a warning, or as an error when `strict` is set. a file that's read but has no duration is kept, unless
+/// `strict` is set, when that's an error too.
+pub fn probe_inputs(probe: &dyn Probe, files: Vec<PathBuf>, strict: bool) -> Result<ProbedInputs, StitcherError> {
+    let mut kept = vec![];
+    let mut durations = Some(vec![]);
+    let mut skipped = vec![];
+
+    for file in files {
+        // Ctrl+C reaches ffprobe too, which would make every file from here on look unreadable
+        //
+        if crate::interrupt::requested() {
+            return Err(StitcherError::Interrupted);
+        }
+        match probe.duration(&file) {
+            Ok(secs) => {
+                log::trace!("probed {}: {:.3}s", file.to_string_lossy(), secs);
+                if let Some(durations) = &mut durations {
+                    durations.push(secs);
+                }
+            }
+            Err(e @ (ProbeError::TimedOut { .. } | ProbeError::Unreadable(_))) => match strict {
+                true => return Err(StitcherError::BadInput(e.to_string())),
+                false => {
+                    log::warn!("skipping {}: {}", file.to_string_lossy(), e);
+                    skipped.push(e);
+                    continue;
+                }
+            },
+            Err(e @ ProbeError::Failed(_)) if strict => return Err(StitcherError::BadInput(e.to_string())),
+            Err(ProbeError::Failed(_)) => durations = None,
+        }
+        kept.push(file);
+    }
+
+    Ok(ProbedInputs { files: kept, durations, skipped })
+}
+
+fn parse_duration(ffprobe_stdout: &str) -> Option<f64> {
+    ffprobe_stdout.trim().parse::<f64>().ok()
+}
+
+/// Parse ffprobe's `TAG:key=value` entries, keeping the first of any key that shows up twice.
+fn parse_tags(ffprobe_stdout: &str) -> Vec<(String, String)> {
+    let mut tags: Vec<(String, String)> = vec![];
+    for line in ffprobe_stdout.lines() {
+        let Some((key, value)) = line.strip_prefix("TAG:").and_then(|x| x.split_once('=')) else {
+            continue;
+        };
+        if !tags.iter().any(|(known, _)| known.eq_ignore_ascii_case(key)) {
+            tags.push((key.to_string(), value.trim_end_matches('\r').to_string()));
+        }
+    }
+    tags
+}
+
+/// Parse ffprobe's `key=value` stream entries into an `AudioFormat`.
+fn parse_audio_format(ffprobe_stdout: &str) -> Option<AudioFormat> {
+    let mut sample_rate = None;
+    let mut channels = None;
+    for line in ffprobe_stdout.lines() {
+        match line.trim().split_once('=') {
+            Some(("sample_rate", value)) => sample_rate = value.parse().ok(),
+            Some(("channels", value)) => channels = value.parse().ok(),
+            _ => (),
+        }
+    }
+
+    Some(AudioFormat {
+        sample_rate: sample_rate?,
+        channels: channels?,
+    })
+}
+
+/// Parse ffprobe's `key=value` stream entries into `StreamParams`.
+fn parse_stream_params(ffprobe_stdout: &str) -> Option<StreamParams> {
+    let codec = ffprobe_stdout.lines().find_map(|line| line.trim().strip_prefix("codec_name="))?;
+    Some(StreamParams {
+        codec: String::from(codec),
+        format: parse_audio_format(ffprobe_stdout)?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_parse_duration() {
+        assert_eq!(parse_duration("12.345000\n"), Some(12.345));
+        assert_eq!(parse_duration("N/A\n"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+
+    #[test]
+    pub fn test_parse_audio_format() {
+        assert_eq!(
+            parse_audio_format("sample_rate=44100\nchannels=1\n"),
+            Some(AudioFormat { sample_rate: 44100, channels: 1 })
+        );
+        assert_eq!(parse_audio_format(""), None);
+    }
+
+    #[test]
+    pub fn test_parse_tags() {
+        let stdout = "TAG:artist=Band\nTAG:title=a=b\nTAG:ARTIST=Someone Else\nsample_rate=44100\n";
+        assert_eq!(
+            parse_tags(stdout),
+            vec![(String::from("artist"), String::from("Band")), (String::from("title"), String::from("a=b"))]
+        );
+        assert!(parse_tags("").is_empty());
+    }
+
+    #[test]
+    pub fn test_sample_rate_to_resample_to() {
+        assert_eq!("max".parse(), Ok(SampleRate::Max));
+        assert_eq!("48000".parse(), Ok(SampleRate::Hz(48000)));
+        assert_eq!("44.1k".parse(), Ok(SampleRate::Hz(44100)));
+        assert!("fast".parse::<SampleRate>().is_err());
+        assert!("0".parse::<SampleRate>().is_err());
+
+        let formats = [
+            AudioFormat { sample_rate: 44100, channels: 2 },
+            AudioFormat { sample_rate: 96000, channels: 1 },
+        ];
+        assert_eq!(SampleRate::Max.pick(&formats), Some(96000));
+        assert_eq!(SampleRate::Hz(22050).pick(&formats), Some(22050));
+        assert_eq!(SampleRate::Max.pick(&[]), None);
+    }
+
+    #[test]
+    pub fn test_find_mismatch() {
+        struct ByName;
+        impl Probe for ByName {
+            fn duration(&self, _file: &Path) -> Result<f64, ProbeError> {
+                Ok(1.0)
+            }
+
+            fn audio_format(&self, file: &Path) -> Result<AudioFormat, ProbeError> {
+                self.stream_params(file).map(|params| params.format)
+            }
+
+            fn stream_params(&self, file: &Path) -> Result<StreamParams, ProbeError> {
+                let sample_rate = match file.to_string_lossy().contains("44k") {
+                    true => 44100,
+                    false => 48000,
+                };
+                match file.to_string_lossy().contains("broken") {
+                    true => Err(ProbeError::Failed(String::from("no audio stream"))),
+                    false => Ok(StreamParams {
+                        codec: String::from("pcm_s16le"),
+                        format: AudioFormat { sample_rate, channels: 2 },
+                    }),
+                }
+            }
+        }
+
+        let matching = ["a.wav", "broken.wav", "b.wav"].map(PathBuf::from);
+        assert_eq!(find_mismatch(&ByName, &matching), None);
+
+        let mixed = ["a.wav", "b-44k.wav"].map(PathBuf::from);
+        let mismatch = find_mismatch(&ByName, &mixed).expect("expected the sample rates to differ");
+        assert_eq!(mismatch.first.1.format.sample_rate, 48000);
+        assert_eq!(
+            mismatch.to_string(),
+            "a.wav is pcm_s16le 48000Hz stereo, b-44k.wav is pcm_s16le 44100Hz stereo"
+        );
+
+        assert_eq!(
+            parse_stream_params("codec_name=mp3\nsample_rate=44100\nchannels=2\n"),
+            Some(StreamParams { codec: String::from("mp3"), format: AudioFormat { sample_rate: 44100, channels: 2 } })
+        );
+    }
+
+    /// A stand-in ffprobe that hangs, like one stuck on a dead mount.
+    #[cfg(unix)]
+    fn hanging_ffprobe(dir: &Path) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.join("ffprobe");
+        std::fs::write(&path, "#!/bin/sh\nexec sleep 10\n").expect("failed to write the mock ffprobe");
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).expect("failed to make the mock ffprobe executable");
+        path
+    }
+
+    #[test]
+    #[cfg(unix)]
+    pub fn test_probe_timeout_kills_a_hung_ffprobe() {
+        let dir = std::env::temp_dir().join(format!("stitcher_test_probe_timeout_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create a scratch directory");
+
+        let ffprobe = Ffprobe {
+            bin_path: hanging_ffprobe(&dir),
+            timeout: Some(Duration::from_millis(200)),
+            input_format: None,
+        };
+        let files = ["slow.wav"].map(|x| dir.join(x)).to_vec();
+
+        let started = Instant::now();
+        match ffprobe.duration(&files[0]) {
+            Err(ProbeError::TimedOut { after, .. }) => assert_eq!(after, Duration::from_millis(200)),
+            other => panic!("expected the probe to time out, got {:?}", other),
+        }
+        assert!(started.elapsed() < Duration::from_secs(5), "expected the hung ffprobe to be killed");
+
+        // skipped with a warning by default, an error under --strict
+        //
+        let probed = probe_inputs(&ffprobe, files.clone(), false).expect("expected a skip, not an error");
+        assert!(probed.files.is_empty());
+        assert_eq!(probed.durations, Some(vec![]));
+        assert_eq!(probed.skipped.len(), 1);
+        assert!(probe_inputs(&ffprobe, files, true).is_err());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    pub fn test_unreadable_inputs_are_skipped() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("stitcher_test_probe_unreadable_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create a scratch directory");
+
+        // a mock ffprobe that reads any file but one named corrupt.wav, as 2.5 seconds long
+        //
+        let script = "#!/bin/sh\nfor last; do :; done\ncase \"$last\" in\n  */corrupt.wav) echo \"$last: Invalid data found when processing input\" >&2; exit 1 ;;\nesac\necho 2.5\n";
+        let bin_path = dir.join("ffprobe");
+        std::fs::write(&bin_path, script).expect("failed to write the mock ffprobe");
+        std::fs::set_permissions(&bin_path, std::fs::Permissions::from_mode(0o755)).expect("failed to make the mock ffprobe executable");
+        let ffprobe = Ffprobe { bin_path, timeout: None, input_format: None };
+        let files = ["a.wav", "corrupt.wav", "b.wav"].map(|x| dir.join(x)).to_vec();
+
+        let probed = probe_inputs(&ffprobe, files.clone(), false).expect("expected a skip, not an error");
+        assert_eq!(probed.files, [dir.join("a.wav"), dir.join("b.wav")]);
+        assert_eq!(probed.durations, Some(vec![2.5, 2.5]));
+        match probed.skipped.as_slice() {
+            [ProbeError::Unreadable(message)] => assert!(message.ends_with("corrupt.wav: Invalid data found when processing input")),
+            other => panic!("expected corrupt.wav to be skipped, got {:?}", other),
+        }
+        assert!(probe_inputs(&ffprobe, files, true).is_err());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    pub fn test_headerless_inputs_are_probed_with_the_input_format() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("stitcher_test_probe_input_format_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create a scratch directory");
+
+        // a mock ffprobe that, like the real one on raw PCM, can only read a file when told it's s16le
+        //
+        let script = "#!/bin/sh\ncase \"$*\" in\n  *\"-f s16le\"*) echo 2.5 ;;\n  *) echo \"Invalid data found when processing input\" >&2; exit 1 ;;\nesac\n";
+        let bin_path = dir.join("ffprobe");
+        std::fs::write(&bin_path, script).expect("failed to write the mock ffprobe");
+        std::fs::set_permissions(&bin_path, std::fs::Permissions::from_mode(0o755)).expect("failed to make the mock ffprobe executable");
+        let files = ["a.pcm", "b.pcm"].map(|x| dir.join(x)).to_vec();
+
+        let ffprobe = Ffprobe { bin_path: bin_path.clone(), timeout: None, input_format: None };
+        let probed = probe_inputs(&ffprobe, files.clone(), false).expect("expected a skip, not an error");
+        assert!(probed.files.is_empty(), "expected raw PCM to be unreadable without -f");
+
+        let ffprobe = Ffprobe { bin_path, timeout: None, input_format: Some(String::from("s16le")) };
+        let probed = probe_inputs(&ffprobe, files.clone(), true).expect("expected -f s16le to read raw PCM");
+        assert_eq!(probed.files, files);
+        assert_eq!(probed.durations, Some(vec![2.5, 2.5]));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
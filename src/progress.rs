@@ -0,0 +1,156 @@
+use serde::Serialize;
+
+/// One block of ffmpeg's `-progress` output. ffmpeg writes `key=value` lines and ends
+/// each block with a `progress=continue` (or `progress=end`) line.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FfmpegProgress {
+    /// Position in the output, in microseconds.
+    pub out_time_us: Option<u64>,
+    /// `true` once ffmpeg has written `progress=end`.
+    pub finished: bool,
+}
+
+/// Collects `-progress` lines into whole blocks.
+#[derive(Debug, Default)]
+pub struct ProgressParser {
+    current: FfmpegProgress,
+}
+
+impl ProgressParser {
+    /// Feed one line of the progress stream. returns a block once its terminating `progress=` line arrives.
+    pub fn feed_line(&mut self, line: &str) -> Option<FfmpegProgress> {
+        let (key, value) = line.trim().split_once('=')?;
+        match key {
+            // `out_time_ms` is also microseconds, despite the name - older ffmpegs only write that one
+            //
+            "out_time_us" | "out_time_ms" => {
+                if let Ok(us) = value.parse::<u64>() {
+                    self.current.out_time_us = Some(us);
+                }
+                None
+            }
+            "progress" => {
+                self.current.finished = value == "end";
+                Some(std::mem::take(&mut self.current))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Width of the bar drawn by `to_bar_line`, in characters, not counting its brackets.
+pub const BAR_WIDTH: usize = 30;
+
+/// A machine-readable progress event, emitted as one JSON line per ffmpeg progress block.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ProgressEvent {
+    pub stage: &'static str,
+    /// `None` when the total duration isn't known (e.g. no ffprobe).
+    pub percent: Option<f64>,
+    pub out_time: String,
+}
+
+impl ProgressEvent {
+    pub fn from_ffmpeg(progress: &FfmpegProgress, total_duration_secs: Option<f64>) -> ProgressEvent {
+        let out_time_us = progress.out_time_us.unwrap_or(0);
+        let percent = match (progress.finished, total_duration_secs) {
+            (true, _) => Some(100.0),
+            (false, Some(total)) if total > 0.0 => {
+                let percent = (out_time_us as f64 / 1_000_000.0) / total * 100.0;
+                Some((percent.min(100.0) * 10.0).round() / 10.0)
+            }
+            _ => None,
+        };
+
+        ProgressEvent {
+            stage: "ffmpeg",
+            percent,
+            out_time: format_timestamp(out_time_us / 1_000_000),
+        }
+    }
+
+    pub fn to_json_line(&self) -> String {
+        serde_json::to_string(self).expect("progress events always serialize")
+    }
+
+    /// The event as a progress bar line, like `[#########.....]  42.1% 00:10:05`. with no percent
+    /// (the total duration isn't known) the bar is left empty and only the time moves.
+    pub fn to_bar_line(&self) -> String {
+        let (filled, percent) = match self.percent {
+            Some(percent) => (
+                ((percent / 100.0) * BAR_WIDTH as f64).round() as usize,
+                format!("{:5.1}%", percent),
+            ),
+            None => (0, String::from("    ?%")),
+        };
+        let filled = filled.min(BAR_WIDTH);
+
+        format!(
+            "[{}{}] {} {}",
+            "#".repeat(filled),
+            ".".repeat(BAR_WIDTH - filled),
+            percent,
+            self.out_time
+        )
+    }
+}
+
+/// `HH:MM:SS` for `total_secs` seconds.
+pub fn format_timestamp(total_secs: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        total_secs / 3600,
+        (total_secs / 60) % 60,
+        total_secs % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_progress_block_to_json_event() {
+        let stream = "frame=0\nout_time_us=605000000\nout_time=00:10:05.000000\nspeed=52.1x\nprogress=continue\n";
+
+        let mut parser = ProgressParser::default();
+        let blocks = stream
+            .lines()
+            .filter_map(|line| parser.feed_line(line))
+            .collect::<Vec<_>>();
+        assert_eq!(blocks.len(), 1, "expected one complete progress block");
+
+        let event = ProgressEvent::from_ffmpeg(&blocks[0], Some(1437.0));
+        assert_eq!(
+            event.to_json_line(),
+            r#"{"stage":"ffmpeg","percent":42.1,"out_time":"00:10:05"}"#
+        );
+    }
+
+    #[test]
+    pub fn test_progress_bar_line() {
+        let halfway = ProgressEvent::from_ffmpeg(&FfmpegProgress { out_time_us: Some(30_000_000), finished: false }, Some(60.0));
+        assert_eq!(
+            halfway.to_bar_line(),
+            format!("[{}{}]  50.0% 00:00:30", "#".repeat(15), ".".repeat(15))
+        );
+
+        let unknown_total = ProgressEvent::from_ffmpeg(&FfmpegProgress { out_time_us: Some(61_000_000), finished: false }, None);
+        assert_eq!(unknown_total.to_bar_line(), format!("[{}]     ?% 00:01:01", ".".repeat(BAR_WIDTH)));
+    }
+
+    #[test]
+    pub fn test_progress_event_without_total_or_at_end() {
+        let unknown_total = ProgressEvent::from_ffmpeg(
+            &FfmpegProgress { out_time_us: Some(1_000_000), finished: false },
+            None,
+        );
+        assert_eq!(unknown_total.percent, None);
+
+        let finished = ProgressEvent::from_ffmpeg(
+            &FfmpegProgress { out_time_us: Some(1_000_000), finished: true },
+            None,
+        );
+        assert_eq!(finished.percent, Some(100.0));
+    }
+}
@@ -0,0 +1,217 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::error::StitcherError;
+use crate::sort;
+
+/// How long a file is, in seconds, if that can be found.
+pub type DurationOf = dyn Fn(&Path) -> Option<f64>;
+
+/// Which of the discovered files get stitched, and in what order.
+///
+/// The pipeline runs filter → sort → limit: files that aren't included, are excluded, were modified outside
+/// `since`..`until`, or are shorter or longer than the duration limits, are dropped first, so `limit` counts
+/// only files that would actually be stitched. `limit_before_filter` moves the limit to straight
+/// after the sort instead (sort → limit → filter), so excluded files use up part of the limit.
+#[derive(Debug, Default, Clone)]
+pub struct Selection {
+    /// If there are any, only files whose name matches one of these are kept.
+    pub include: Vec<glob::Pattern>,
+    /// Files whose name matches any of these are left out, even if they're included.
+    pub exclude: Vec<glob::Pattern>,
+    /// If set, only files modified at or after this are kept.
+    pub since: Option<SystemTime>,
+    /// If set, only files modified before this are kept.
+    pub until: Option<SystemTime>,
+    /// If set, files shorter than this many seconds are left out.
+    pub min_duration_secs: Option<f64>,
+    /// If set, files longer than this many seconds are left out.
+    pub max_duration_secs: Option<f64>,
+    /// Order to stitch in.
+    pub sort: sort::SortOrder,
+    /// Keep at most this many files.
+    pub limit: Option<usize>,
+    /// Apply `limit` before `exclude` rather than after.
+    pub limit_before_filter: bool,
+    /// Fail on a file whose modification time or duration can't be found for the filters, instead of leaving
+    /// it out with a warning.
+    pub strict: bool,
+}
+
+/// Run `files` through the selection pipeline. `duration_of` says how long a file is, in seconds, and is only
+/// asked about files that pass every other filter, when there are duration limits. only fails with
+/// `selection.strict`.
+pub fn select(
+    files: Vec<PathBuf>,
+    selection: &Selection,
+    duration_of: &DurationOf,
+) -> Result<Vec<PathBuf>, StitcherError> {
+    let mut files = files;
+    if selection.limit_before_filter {
+        sort(&mut files, selection)?;
+        limit(&mut files, selection.limit);
+        filter(files, selection, duration_of)
+    } else {
+        let mut files = filter(files, selection, duration_of)?;
+        sort(&mut files, selection)?;
+        limit(&mut files, selection.limit);
+        Ok(files)
+    }
+}
+
+/// Sort `files` in `selection.sort` order. only fails with `selection.strict`, on a sidecar that doesn't parse.
+fn sort(files: &mut [PathBuf], selection: &Selection) -> Result<(), StitcherError> {
+    if selection.strict && selection.sort == sort::SortOrder::SidecarOrder {
+        sort::check_sidecars(files).map_err(|e| StitcherError::BadInput(format!("{} (--strict)", e)))?;
+    }
+    sort::sort_files(files, selection.sort);
+    Ok(())
+}
+
+fn filter(
+    files: Vec<PathBuf>,
+    selection: &Selection,
+    duration_of: &DurationOf,
+) -> Result<Vec<PathBuf>, StitcherError> {
+    let mut kept = vec![];
+    for file in files {
+        // the duration filters probe each file, which can take a while over a big folder
+        //
+        if crate::interrupt::requested() {
+            return Err(StitcherError::Interrupted);
+        }
+        let name = file.file_name().map(|x| x.to_string_lossy()).unwrap_or_default();
+        let included = selection.include.is_empty() || selection.include.iter().any(|pattern| pattern.matches(&name));
+        if included
+            && !selection.exclude.iter().any(|pattern| pattern.matches(&name))
+            && modified_within(&file, selection)?
+            && duration_within(&file, selection, duration_of)?
+        {
+            kept.push(file);
+        }
+    }
+    Ok(kept)
+}
+
+/// Whether `file` is between `selection.min_duration_secs` and `selection.max_duration_secs` long. with either
+/// set, a file whose duration can't be found is left out.
+fn duration_within(file: &Path, selection: &Selection, duration_of: &DurationOf) -> Result<bool, StitcherError> {
+    if selection.min_duration_secs.is_none() && selection.max_duration_secs.is_none() {
+        return Ok(true);
+    }
+    let Some(secs) = duration_of(file) else {
+        return skip(file, "couldn't find how long it is", selection.strict);
+    };
+    let within = selection.min_duration_secs.is_none_or(|min| secs >= min) && selection.max_duration_secs.is_none_or(|max| secs <= max);
+    if !within {
+        log::debug!("leaving out {}: it's {:.1}s long", file.to_string_lossy(), secs);
+    }
+    Ok(within)
+}
+
+/// Whether `file` was modified in `selection.since`..`selection.until`. with either set, a file whose
+/// modification time can't be read is left out.
+fn modified_within(file: &Path, selection: &Selection) -> Result<bool, StitcherError> {
+    if selection.since.is_none() && selection.until.is_none() {
+        return Ok(true);
+    }
+    let Ok(modified) = std::fs::metadata(file).and_then(|x| x.modified()) else {
+        return skip(file, "couldn't read when it was modified", selection.strict);
+    };
+    Ok(selection.since.is_none_or(|since| modified >= since) && selection.until.is_none_or(|until| modified < until))
+}
+
+/// Leave `file` out because of `reason`, with a warning, or fail on it when `strict` is set.
+fn skip(file: &Path, reason: &str, strict: bool) -> Result<bool, StitcherError> {
+    match strict {
+        true => Err(StitcherError::BadInput(format!("{}: {} (--strict)", file.to_string_lossy(), reason))),
+        false => {
+            log::warn!("skipping {}: {}", file.to_string_lossy(), reason);
+            Ok(false)
+        }
+    }
+}
+
+fn limit(files: &mut Vec<PathBuf>, limit: Option<usize>) {
+    if let Some(limit) = limit {
+        files.truncate(limit);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_limit_after_filter_by_default_and_before_on_request() {
+        let files = ["d.wav", "b.wav", "a.wav", "c.wav", "e.wav"].map(PathBuf::from).to_vec();
+        let selection = Selection {
+            include: vec![],
+            exclude: vec![glob::Pattern::new("b*").expect("valid pattern")],
+            sort: sort::SortOrder::Name,
+            limit: Some(2),
+            limit_before_filter: false,
+            ..Default::default()
+        };
+
+        // filter → sort → limit: b is gone before the limit counts, so two files still make it
+        //
+        assert_eq!(select(files.clone(), &selection, &|_| None).ok(), Some(["a.wav", "c.wav"].map(PathBuf::from).to_vec()));
+
+        // sort → limit → filter: the limit keeps a and b, then b is excluded
+        //
+        let limit_first = Selection { limit_before_filter: true, ..selection };
+        assert_eq!(select(files, &limit_first, &|_| None).ok(), Some(vec![PathBuf::from("a.wav")]));
+    }
+
+    #[test]
+    pub fn test_include_then_exclude() {
+        let files = ["take_1.wav", "take_2_rough.wav", "take_3.wav", "notes.wav"].map(PathBuf::from).to_vec();
+        let selection = Selection {
+            include: vec![glob::Pattern::new("take_*.wav").expect("valid pattern")],
+            exclude: vec![glob::Pattern::new("*_rough*").expect("valid pattern")],
+            ..Default::default()
+        };
+        assert_eq!(select(files, &selection, &|_| None).ok(), Some(["take_1.wav", "take_3.wav"].map(PathBuf::from).to_vec()));
+    }
+
+    #[test]
+    pub fn test_modified_between_since_and_until() {
+        let dir = std::env::temp_dir().join(format!("stitcher_test_selection_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create a scratch directory");
+        let day = std::time::Duration::from_secs(86400);
+        let now = SystemTime::now();
+        let files = [("old.wav", now - day * 3), ("yesterday.wav", now - day), ("today.wav", now)].map(|(name, modified)| {
+            let file = std::fs::File::create(dir.join(name)).expect("failed to create a scratch file");
+            file.set_modified(modified).expect("failed to set a modification time");
+            dir.join(name)
+        });
+
+        let since = Selection { since: Some(now - day * 2), sort: sort::SortOrder::Name, ..Default::default() };
+        assert_eq!(select(files.to_vec(), &since, &|_| None).ok(), Some(vec![dir.join("today.wav"), dir.join("yesterday.wav")]));
+        let between = Selection { until: Some(now - day / 2), ..since };
+        assert_eq!(select(files.to_vec(), &between, &|_| None).ok(), Some(vec![dir.join("yesterday.wav")]));
+        let missing = Selection { since: Some(now - day), ..Default::default() };
+        assert_eq!(select(vec![dir.join("missing.wav")], &missing, &|_| None).ok(), Some(vec![]));
+        let strict = Selection { strict: true, ..missing };
+        assert!(matches!(select(vec![dir.join("missing.wav")], &strict, &|_| None), Err(StitcherError::BadInput(_))));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_duration_limits() {
+        let files = ["blip.wav", "take.wav", "all_night.wav", "broken.wav"].map(PathBuf::from).to_vec();
+        let duration_of = |file: &Path| match file.to_str() {
+            Some("blip.wav") => Some(0.4),
+            Some("take.wav") => Some(180.0),
+            Some("all_night.wav") => Some(28800.0),
+            _ => None,
+        };
+        let selection = Selection { min_duration_secs: Some(5.0), max_duration_secs: Some(3600.0), ..Default::default() };
+        assert_eq!(select(files.clone(), &selection, &duration_of).ok(), Some(vec![PathBuf::from("take.wav")]));
+
+        let no_limits = Selection::default();
+        assert_eq!(select(files.clone(), &no_limits, &duration_of).ok(), Some(files));
+    }
+}
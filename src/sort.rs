@@ -0,0 +1,223 @@
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+
+/// The order discovered files are stitched in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// The order the directory walk finds them in: by name within each directory.
+    #[default]
+    None,
+    /// By file name.
+    Name,
+    /// By modification time, oldest first. files whose time can't be read go last, by file name.
+    Mtime,
+    /// By file name, with runs of digits compared as numbers, so `track2` comes before `track10`.
+    Natural,
+    /// By the numeric `order` field in each file's JSON sidecar (`clip.json` or `clip.wav.json`).
+    /// files without a readable sidecar go last, by file name.
+    #[value(name = "sidecar:order")]
+    #[serde(rename = "sidecar:order")]
+    SidecarOrder,
+}
+
+#[derive(Debug, Deserialize)]
+struct Sidecar {
+    order: f64,
+}
+
+pub fn sort_files(files: &mut [PathBuf], order: SortOrder) {
+    match order {
+        SortOrder::None => (),
+        SortOrder::Name => files.sort_by(|a, b| a.file_name().cmp(&b.file_name())),
+        SortOrder::Mtime => {
+            let mut keyed = files
+                .iter()
+                .map(|file| (std::fs::metadata(file).and_then(|x| x.modified()).ok(), file.clone()))
+                .collect::<Vec<_>>();
+
+            keyed.sort_by(|(a_time, a), (b_time, b)| {
+                by_key_missing_last(a_time, b_time).then_with(|| a.file_name().cmp(&b.file_name()))
+            });
+
+            for (slot, (_, file)) in files.iter_mut().zip(keyed) {
+                *slot = file;
+            }
+        }
+        SortOrder::Natural => files.sort_by(|a, b| {
+            let a_name = a.file_name().unwrap_or_default().to_string_lossy();
+            let b_name = b.file_name().unwrap_or_default().to_string_lossy();
+            natural_cmp(&a_name, &b_name).then_with(|| a.file_name().cmp(&b.file_name()))
+        }),
+        SortOrder::SidecarOrder => {
+            let mut keyed = files
+                .iter()
+                .map(|file| (sidecar_order(file), file.clone()))
+                .collect::<Vec<_>>();
+
+            keyed.sort_by(|(a_order, a), (b_order, b)| {
+                let by_order = match (a_order, b_order) {
+                    (Some(x), Some(y)) => x.total_cmp(y),
+                    _ => by_key_missing_last(a_order, b_order),
+                };
+                by_order.then_with(|| a.file_name().cmp(&b.file_name()))
+            });
+
+            for (slot, (_, file)) in files.iter_mut().zip(keyed) {
+                *slot = file;
+            }
+        }
+    }
+}
+
+/// `None` sorts after every `Some`, so files without a key end up last.
+fn by_key_missing_last<T: PartialOrd>(a: &Option<T>, b: &Option<T>) -> Ordering {
+    match (a, b) {
+        (Some(x), Some(y)) => x.partial_cmp(y).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Compare `a` and `b` a chunk at a time, where a chunk is a run of digits or a run of anything else.
+/// digit runs compare by value (ignoring leading zeros) and everything else compares as text.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chunks = chunks(a);
+    let mut b_chunks = chunks(b);
+    loop {
+        let ordering = match (a_chunks.next(), b_chunks.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => match (is_digits(x), is_digits(y)) {
+                (true, true) => {
+                    let x = x.trim_start_matches('0');
+                    let y = y.trim_start_matches('0');
+                    x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+                }
+                _ => x.cmp(y),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn is_digits(chunk: &str) -> bool {
+    chunk.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Split `s` into alternating runs of ascii digits and everything else.
+fn chunks(s: &str) -> impl Iterator<Item = &str> {
+    let mut rest = s;
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let digits = first.is_ascii_digit();
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != digits)
+            .unwrap_or(rest.len());
+        let (chunk, remaining) = rest.split_at(end);
+        rest = remaining;
+        Some(chunk)
+    })
+}
+
+/// Fail on the first of `files` with a sidecar that doesn't parse, which `SortOrder::SidecarOrder` would
+/// otherwise ignore with a warning. for --strict.
+pub fn check_sidecars(files: &[PathBuf]) -> Result<(), String> {
+    files.iter().try_for_each(|file| read_sidecar_order(file).map(|_| ()))
+}
+
+/// The `order` from `file`'s sidecar, if it has one that parses.
+fn sidecar_order(file: &Path) -> Option<f64> {
+    read_sidecar_order(file).unwrap_or_else(|e| {
+        log::warn!("ignoring {}", e);
+        None
+    })
+}
+
+/// The `order` from `file`'s first sidecar: `None` without one, or an error if it doesn't parse.
+fn read_sidecar_order(file: &Path) -> Result<Option<f64>, String> {
+    let mut with_full_name = file.as_os_str().to_owned();
+    with_full_name.push(".json");
+
+    for sidecar in [file.with_extension("json"), PathBuf::from(with_full_name)] {
+        let Ok(contents) = std::fs::read_to_string(&sidecar) else {
+            continue;
+        };
+        return match serde_json::from_str::<Sidecar>(&contents) {
+            Ok(sidecar) => Ok(Some(sidecar.order)),
+            Err(e) => Err(format!("sidecar {}: {}", sidecar.to_string_lossy(), e)),
+        };
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_sidecar_order_wins_and_missing_sort_last() {
+        let dir = std::env::temp_dir().join(format!("stitcher_test_sidecar_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create a scratch directory");
+
+        let write = |name: &str, contents: &str| {
+            std::fs::write(dir.join(name), contents).expect("failed to write a scratch file");
+        };
+        for name in ["a.wav", "b.wav", "c.wav", "d.wav", "e.wav"] {
+            write(name, "");
+        }
+        write("a.json", r#"{"order": 3}"#);
+        write("b.wav.json", r#"{"order": 1, "title": "intro"}"#);
+        write("c.json", r#"{"order": 2}"#);
+        write("d.json", "not json");
+
+        let mut files = ["e.wav", "d.wav", "c.wav", "b.wav", "a.wav"].map(|x| dir.join(x));
+        sort_files(&mut files, SortOrder::SidecarOrder);
+
+        let expected = ["b.wav", "c.wav", "a.wav", "d.wav", "e.wav"].map(|x| dir.join(x));
+        assert_eq!(files, expected);
+
+        let error = check_sidecars(&files).expect_err("expected the sidecar that isn't json to fail the check");
+        assert!(error.contains("d.json"), "expected the error to name the sidecar, got: {}", error);
+        assert_eq!(check_sidecars(&files[..3]), Ok(()));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_natural_order() {
+        let mut files = ["track10.wav", "track2.wav", "Track1.wav", "track02b.wav", "intro.wav", "track1.wav"]
+            .map(PathBuf::from);
+        sort_files(&mut files, SortOrder::Natural);
+
+        let expected = ["Track1.wav", "intro.wav", "track1.wav", "track2.wav", "track02b.wav", "track10.wav"];
+        assert_eq!(files, expected.map(PathBuf::from));
+        assert_eq!(natural_cmp("take007", "take7"), Ordering::Equal);
+    }
+
+    #[test]
+    pub fn test_mtime_order() {
+        let dir = std::env::temp_dir().join(format!("stitcher_test_mtime_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create a scratch directory");
+
+        let epoch = std::time::SystemTime::UNIX_EPOCH;
+        for (name, secs) in [("a.wav", 300), ("b.wav", 100), ("c.wav", 200)] {
+            let file = std::fs::File::create(dir.join(name)).expect("failed to write a scratch file");
+            file.set_modified(epoch + std::time::Duration::from_secs(secs))
+                .expect("failed to set a modification time");
+        }
+
+        let mut files = ["a.wav", "missing.wav", "b.wav", "c.wav"].map(|x| dir.join(x));
+        sort_files(&mut files, SortOrder::Mtime);
+
+        let expected = ["b.wav", "c.wav", "a.wav", "missing.wav"].map(|x| dir.join(x));
+        assert_eq!(files, expected);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
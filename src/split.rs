@@ -0,0 +1,285 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::{error::StitcherError, ffmpeg, playlist};
+
+/// Where `stitcher split` cuts its input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SplitPoints {
+    /// Every this many seconds.
+    Every(f64),
+    /// At these times, in seconds from the start.
+    At(Vec<f64>),
+}
+
+/// How quiet, and for how long, audio has to be for `silence_split_points` to count it as a pause.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SilenceSettings {
+    /// Anything below this level (dBFS) is silence.
+    pub noise_db: f64,
+    /// Silence has to last at least this many seconds.
+    pub min_secs: f64,
+}
+
+/// ffmpeg arguments to cut `input` at `points` into numbered files, `001` up, following `output_pattern`
+/// (e.g. `out/talk_%03d.wav`). streams are copied, so cuts land on the nearest packet. with nowhere to cut,
+/// the whole input is copied to part `001`. a part that's already there is only replaced with `overwrite`.
+pub fn segment_args(input: &Path, points: &SplitPoints, output_pattern: &Path, overwrite: bool) -> Vec<OsString> {
+    let overwrite = match overwrite {
+        true => "-y",
+        false => "-n",
+    };
+    let mut args = vec![OsString::from(overwrite), OsString::from("-i"), input.as_os_str().to_owned()];
+    for arg in ["-map", "0:a", "-c", "copy"] {
+        args.push(OsString::from(arg));
+    }
+
+    // the segment muxer cuts every 2 seconds when it's given no times at all
+    //
+    if matches!(points, SplitPoints::At(times) if times.is_empty()) {
+        args.push(segment_path(output_pattern, 1).into_os_string());
+        return args;
+    }
+
+    for arg in ["-f", "segment", "-reset_timestamps", "1", "-segment_start_number", "1"] {
+        args.push(OsString::from(arg));
+    }
+    match points {
+        SplitPoints::Every(secs) => {
+            args.push(OsString::from("-segment_time"));
+            args.push(OsString::from(secs.to_string()));
+        }
+        SplitPoints::At(times) => {
+            let times = times.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+            args.push(OsString::from("-segment_times"));
+            args.push(OsString::from(times.join(",")));
+        }
+    }
+    args.push(output_pattern.as_os_str().to_owned());
+    args
+}
+
+/// ffmpeg arguments for finding the pauses in `input` with silencedetect, throwing the audio away.
+pub fn silencedetect_args(input: &Path, silence: &SilenceSettings) -> Vec<OsString> {
+    let filter = format!("silencedetect=noise={}dB:d={}", silence.noise_db, silence.min_secs);
+    let mut args = vec![OsString::from("-hide_banner"), OsString::from("-nostats"), OsString::from("-i")];
+    args.push(input.as_os_str().to_owned());
+    for arg in ["-vn", "-af", &filter, "-f", "null", "-"] {
+        args.push(OsString::from(arg));
+    }
+    args
+}
+
+/// The middle of each pause silencedetect reported on stderr, so each cut leaves half the pause either
+/// side. a pause that runs off the end of the input (with no `silence_end`) isn't cut at.
+pub fn parse_silence_midpoints(stderr: &str) -> Vec<f64> {
+    let value = |line: &str, key: &str| {
+        let (_, rest) = line.split_once(key)?;
+        rest.split_whitespace().next()?.parse::<f64>().ok()
+    };
+
+    let mut midpoints = vec![];
+    let mut start = None;
+    for line in stderr.lines() {
+        if let Some(secs) = value(line, "silence_start:") {
+            start = Some(secs);
+        }
+        if let Some(end) = value(line, "silence_end:") {
+            // a pause right at the start isn't between two parts
+            //
+            if let Some(start) = start.take().filter(|secs| *secs > 0.0) {
+                midpoints.push((start + end) / 2.0);
+            }
+        }
+    }
+    midpoints
+}
+
+/// Run silencedetect over `input` and return where to cut it.
+pub fn silence_split_points(ffmpeg_bin_path: &Path, input: &Path, silence: &SilenceSettings) -> Result<Vec<f64>, StitcherError> {
+    let run = ffmpeg::run(ffmpeg_bin_path, &silencedetect_args(input, silence), ffmpeg::Progress::Inherit, true)?;
+    run.check()?;
+    Ok(parse_silence_midpoints(&run.stderr))
+}
+
+/// The start of every chapter after the first, from either a CUE sheet (`INDEX 01 MM:SS:FF`, 75 frames
+/// a second) or an FFMETADATA file (`[CHAPTER]` sections with `START` in `TIMEBASE` units).
+pub fn parse_chapter_starts(contents: &str) -> Vec<f64> {
+    let starts = match contents.trim_start_matches('\u{feff}').starts_with(";FFMETADATA") {
+        true => ffmetadata_starts(contents),
+        false => cue_starts(contents),
+    };
+    starts.into_iter().filter(|secs| *secs > 0.0).collect()
+}
+
+fn cue_starts(contents: &str) -> Vec<f64> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let timestamp = line.trim().strip_prefix("INDEX 01")?.trim();
+            let (minutes_seconds, frames) = timestamp.rsplit_once(':')?;
+            let secs = playlist::parse_timestamp(minutes_seconds)?;
+            Some(secs + frames.parse::<u32>().ok()? as f64 / 75.0)
+        })
+        .collect()
+}
+
+fn ffmetadata_starts(contents: &str) -> Vec<f64> {
+    let mut starts = vec![];
+    let mut timebase = 1.0 / 1000.0;
+    let mut start = None;
+    for line in contents.lines().map(|x| x.trim()) {
+        if line == "[CHAPTER]" {
+            starts.extend(start.take().map(|units: f64| units * timebase));
+            timebase = 1.0 / 1000.0;
+        } else if let Some(value) = line.strip_prefix("TIMEBASE=") {
+            if let Some((num, den)) = value.split_once('/') {
+                if let (Ok(num), Ok(den)) = (num.parse::<f64>(), den.parse::<f64>()) {
+                    timebase = num / den;
+                }
+            }
+        } else if let Some(value) = line.strip_prefix("START=") {
+            start = value.parse::<f64>().ok();
+        }
+    }
+    starts.extend(start.map(|units| units * timebase));
+    starts
+}
+
+/// Where the parts of `input` go: `<stem>_%03d.<ext>` in `out_dir`, for `segment_args`.
+pub fn output_pattern(input: &Path, out_dir: &Path) -> PathBuf {
+    let stem = input.file_stem().map(|x| x.to_string_lossy().into_owned()).unwrap_or_default();
+    let pattern = match input.extension() {
+        Some(ext) => format!("{}_%03d.{}", stem, ext.to_string_lossy()),
+        None => format!("{}_%03d", stem),
+    };
+    out_dir.join(pattern)
+}
+
+/// Part `number` of `output_pattern`, e.g. `out/talk_002.wav`.
+pub fn segment_path(output_pattern: &Path, number: usize) -> PathBuf {
+    PathBuf::from(output_pattern.to_string_lossy().replace("%03d", &format!("{:03}", number)))
+}
+
+/// The parts of `output_pattern` that are there now, in order.
+pub fn existing_parts(output_pattern: &Path) -> Result<Vec<PathBuf>, StitcherError> {
+    let pattern = glob::Pattern::escape(&output_pattern.to_string_lossy()).replace("%03d", "[0-9][0-9][0-9]");
+    let parts = glob::glob(&pattern).map_err(|e| StitcherError::Other(format!("failed to list the parts: {}", e)))?;
+    Ok(parts.filter_map(|x| x.ok()).collect())
+}
+
+/// Group inputs lasting `durations` into parts that each last at most `max_secs`, with `spacing_secs` between
+/// one input and the next and `edge_secs` more in every part (its pre-gap and post-gap), so an output can be
+/// split without cutting any input in two. an input too long for a part of its own still gets one to itself.
+/// returns the range of inputs in each part, in order.
+pub fn group_into_parts(
+    durations: &[f64],
+    spacing_secs: f64,
+    edge_secs: f64,
+    max_secs: f64,
+) -> Vec<std::ops::Range<usize>> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut part_secs = edge_secs;
+    for (i, duration) in durations.iter().enumerate() {
+        if i > start && part_secs + spacing_secs + duration > max_secs {
+            parts.push(start..i);
+            start = i;
+            part_secs = edge_secs;
+        }
+        part_secs += match i > start {
+            true => spacing_secs + duration,
+            false => *duration,
+        };
+    }
+    if start < durations.len() {
+        parts.push(start..durations.len());
+    }
+    parts
+}
+
+/// Where part `number` (from 1) of `output` goes: `<stem>_part<number>.<ext>` next to it.
+pub fn part_path(output: &Path, number: usize) -> PathBuf {
+    let stem = output.file_stem().map(|x| x.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match output.extension() {
+        Some(ext) => format!("{}_part{}.{}", stem, number, ext.to_string_lossy()),
+        None => format!("{}_part{}", stem, number),
+    };
+    output.with_file_name(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_parts_break_between_inputs() {
+        let durations = [1800.0, 1800.0, 900.0, 5000.0, 60.0];
+        assert_eq!(group_into_parts(&durations, 0.0, 0.0, 4440.0), vec![0..2, 2..3, 3..4, 4..5]);
+        assert_eq!(group_into_parts(&durations[..3], 2.0, 0.0, 4500.0), vec![0..2, 2..3]);
+        assert_eq!(group_into_parts(&durations[..3], 0.0, 0.0, 4500.0), vec![0..3]);
+        assert!(group_into_parts(&[], 0.0, 0.0, 60.0).is_empty());
+
+        // every part gets its own pre-gap and post-gap
+        //
+        assert_eq!(group_into_parts(&durations[..3], 0.0, 2.0, 4500.0), vec![0..2, 2..3]);
+        assert_eq!(group_into_parts(&durations[..3], 0.0, 2.0, 4502.0), vec![0..3]);
+
+        assert_eq!(part_path(Path::new("/cds/mix.wav"), 2), Path::new("/cds/mix_part2.wav"));
+        assert_eq!(part_path(Path::new("mix"), 1), Path::new("mix_part1"));
+    }
+
+    #[test]
+    pub fn test_segment_args() {
+        let pattern = output_pattern(Path::new("/rec/talk.wav"), Path::new("parts"));
+        assert_eq!(pattern, Path::new("parts/talk_%03d.wav"));
+
+        let args = segment_args(Path::new("/rec/talk.wav"), &SplitPoints::At(vec![61.5, 120.0]), &pattern, true);
+        let expected = [
+            "-y", "-i", "/rec/talk.wav", "-map", "0:a", "-c", "copy", "-f", "segment", "-reset_timestamps", "1",
+            "-segment_start_number", "1", "-segment_times", "61.5,120", "parts/talk_%03d.wav",
+        ];
+        assert_eq!(args, expected.map(OsString::from));
+
+        let every = segment_args(Path::new("talk.wav"), &SplitPoints::Every(600.0), &pattern, false);
+        assert!(every.windows(2).any(|x| x[0] == "-segment_time" && x[1] == "600"));
+        assert_eq!(every[0], "-n");
+
+        // nowhere to cut is one part, not the segment muxer's default of a cut every 2 seconds
+        //
+        let whole = segment_args(Path::new("talk.wav"), &SplitPoints::At(vec![]), &pattern, true);
+        let expected = ["-y", "-i", "talk.wav", "-map", "0:a", "-c", "copy", "parts/talk_001.wav"];
+        assert_eq!(whole, expected.map(OsString::from));
+    }
+
+    #[test]
+    pub fn test_existing_parts_match_only_the_pattern() {
+        let dir = std::env::temp_dir().join(format!("stitcher_test_split_parts_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create a scratch directory");
+        for name in ["talk_002.wav", "talk_001.wav", "talk_1.wav", "talk.wav", "other_001.wav"] {
+            std::fs::write(dir.join(name), "").expect("failed to write a scratch file");
+        }
+
+        let pattern = output_pattern(Path::new("talk.wav"), &dir);
+        assert_eq!(existing_parts(&pattern).ok(), Some(vec![dir.join("talk_001.wav"), dir.join("talk_002.wav")]));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_split_points_from_silence_and_chapters() {
+        let stderr = "\
+            [silencedetect @ 0x6000] silence_start: -0.01\n\
+            [silencedetect @ 0x6000] silence_end: 0.5 | silence_duration: 0.51\n\
+            [silencedetect @ 0x6000] silence_start: 60\n\
+            [silencedetect @ 0x6000] silence_end: 62 | silence_duration: 2\n\
+            [silencedetect @ 0x6000] silence_start: 170.5\n";
+        assert_eq!(parse_silence_midpoints(stderr), vec![61.0]);
+
+        let cue = "FILE \"talk.wav\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    INDEX 01 01:30:15\n";
+        assert_eq!(parse_chapter_starts(cue), vec![90.2]);
+
+        let ffmetadata = ";FFMETADATA1\n[CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=1500\n[CHAPTER]\nTIMEBASE=1/1000\nSTART=1500\nEND=3000\n";
+        assert_eq!(parse_chapter_starts(ffmetadata), vec![1.5]);
+    }
+}
@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+
+/// The result of a stitch, as printed by `--summary-only` (and, with more to it, `--json`).
+///
+/// The line is tab-separated, always in this order, with no header:
+///
+/// `output path` `\t` `input count` `\t` `duration in seconds (3 decimals, or "-" if unknown)` `\t` `size in bytes`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub output: PathBuf,
+    /// The inputs, in the order they were stitched.
+    pub inputs: Vec<PathBuf>,
+    pub duration_secs: Option<f64>,
+    pub size_bytes: u64,
+}
+
+impl Summary {
+    pub fn to_line(&self) -> String {
+        let duration = match self.duration_secs {
+            Some(secs) => format!("{:.3}", secs),
+            None => String::from("-"),
+        };
+
+        format!(
+            "{}\t{}\t{}\t{}",
+            self.output.to_string_lossy(),
+            self.inputs.len(),
+            duration,
+            self.size_bytes
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_summary_line_fields() {
+        let summary = Summary {
+            output: PathBuf::from("out dir/joined.wav"),
+            inputs: ["a.wav", "b.wav", "c.wav"].map(PathBuf::from).to_vec(),
+            duration_secs: Some(12.5),
+            size_bytes: 2_205_044,
+        };
+        assert_eq!(summary.to_line(), "out dir/joined.wav\t3\t12.500\t2205044");
+
+        let unknown_duration = Summary {
+            duration_secs: None,
+            ..summary
+        };
+        let fields = unknown_duration.to_line();
+        let fields = fields.split('\t').collect::<Vec<_>>();
+        assert_eq!(fields, ["out dir/joined.wav", "3", "-", "2205044"]);
+    }
+}
@@ -0,0 +1,166 @@
+use std::path::Path;
+
+use symphonia::core::{
+    codecs::{CodecParameters, CODEC_TYPE_NULL, CODEC_TYPE_OPUS},
+    formats::{FormatOptions, FormatReader},
+    io::MediaSourceStream,
+    meta::{MetadataOptions, StandardTagKey, Tag},
+    probe::{Hint, ProbeResult},
+};
+
+use crate::probe::{AudioFormat, Probe, ProbeError, StreamParams};
+
+/// Probes files by reading them with symphonia, in-process, for when there's no ffprobe. codec names
+/// follow ffprobe's (`pcm_s16le`, `mp3`, `flac`...), so the two can be compared.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SymphoniaProbe;
+
+impl Probe for SymphoniaProbe {
+    fn duration(&self, file: &Path) -> Result<f64, ProbeError> {
+        let (mut reader, params) = open(file)?;
+        let time_base = params
+            .time_base
+            .or_else(|| Some(symphonia::core::units::TimeBase::new(1, params.sample_rate?)))
+            .ok_or_else(|| failed(file, "no time base"))?;
+
+        // the header usually says how long the stream is. when it doesn't (e.g. an mp3 without a Xing
+        // header), the packets are counted up instead
+        //
+        let frames = match params.n_frames {
+            Some(frames) => frames,
+            None => {
+                let track_id = reader.default_track().map(|x| x.id).unwrap_or_default();
+                let mut frames = 0;
+                while let Ok(packet) = reader.next_packet() {
+                    if packet.track_id() == track_id {
+                        frames += packet.dur();
+                    }
+                }
+                frames
+            }
+        };
+        let time = time_base.calc_time(frames);
+        Ok(time.seconds as f64 + time.frac)
+    }
+
+    fn audio_format(&self, file: &Path) -> Result<AudioFormat, ProbeError> {
+        let (_, params) = open(file)?;
+        audio_format(file, &params)
+    }
+
+    fn stream_params(&self, file: &Path) -> Result<StreamParams, ProbeError> {
+        let (_, params) = open(file)?;
+        let codec = match params.codec {
+            // symphonia can find opus streams, but has no decoder to name them
+            //
+            CODEC_TYPE_OPUS => "opus",
+            codec => symphonia::default::get_codecs().get_codec(codec).map(|x| x.short_name).unwrap_or("unknown"),
+        };
+        Ok(StreamParams {
+            codec: String::from(codec),
+            format: audio_format(file, &params)?,
+        })
+    }
+
+    fn tags(&self, file: &Path) -> Result<Vec<(String, String)>, ProbeError> {
+        // tags ahead of the container (like an MP3's ID3v2) come with the probe, the container's own with the reader
+        //
+        let mut probed = probe(file)?;
+        let mut tags: Vec<(String, String)> = vec![];
+        let ahead = probed.metadata.get().and_then(|x| x.current().map(|x| x.tags().to_vec()));
+        let own = probed.format.metadata().current().map(|x| x.tags().to_vec());
+        for tag in ahead.into_iter().chain(own).flatten() {
+            let key = tag_key(&tag);
+            if !tags.iter().any(|(known, _)| *known == key) {
+                tags.push((key, tag.value.to_string()));
+            }
+        }
+        Ok(tags)
+    }
+}
+
+/// ffprobe's name for `tag`, where it's one both know, or its own key, lowercased.
+fn tag_key(tag: &Tag) -> String {
+    let key = match tag.std_key {
+        Some(StandardTagKey::Album) => "album",
+        Some(StandardTagKey::AlbumArtist) => "album_artist",
+        Some(StandardTagKey::Artist) => "artist",
+        Some(StandardTagKey::Comment) => "comment",
+        Some(StandardTagKey::Composer) => "composer",
+        Some(StandardTagKey::Date) => "date",
+        Some(StandardTagKey::Genre) => "genre",
+        Some(StandardTagKey::TrackNumber) => "track",
+        Some(StandardTagKey::TrackTitle) => "title",
+        _ => return tag.key.to_lowercase(),
+    };
+    String::from(key)
+}
+
+/// The format reader for `file`, and the codec parameters of its first audio stream.
+fn open(file: &Path) -> Result<(Box<dyn FormatReader>, CodecParameters), ProbeError> {
+    let probed = probe(file)?;
+
+    let params = probed
+        .format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .map(|track| track.codec_params.clone())
+        .ok_or_else(|| failed(file, "no audio stream"))?;
+    Ok((probed.format, params))
+}
+
+/// What symphonia makes of `file`: its format reader, and any metadata found ahead of the container.
+fn probe(file: &Path) -> Result<ProbeResult, ProbeError> {
+    let source = std::fs::File::open(file).map_err(|e| unreadable(file, &e.to_string()))?;
+    let stream = MediaSourceStream::new(Box::new(source), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = file.extension().and_then(|x| x.to_str()) {
+        hint.with_extension(ext);
+    }
+    symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|e| unreadable(file, &e.to_string()))
+}
+
+fn audio_format(file: &Path, params: &CodecParameters) -> Result<AudioFormat, ProbeError> {
+    Ok(AudioFormat {
+        sample_rate: params.sample_rate.ok_or_else(|| failed(file, "no sample rate"))?,
+        channels: params.channels.ok_or_else(|| failed(file, "no channel count"))?.count() as u32,
+    })
+}
+
+fn unreadable(file: &Path, reason: &str) -> ProbeError {
+    ProbeError::Unreadable(format!("failed to read {}: {}", file.to_string_lossy(), reason))
+}
+
+fn failed(file: &Path, reason: &str) -> ProbeError {
+    ProbeError::Failed(format!("failed to read {}: {}", file.to_string_lossy(), reason))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_symphonia_probes_a_wav() {
+        let file = std::env::temp_dir().join(format!("stitcher_test_symphonia_{}.wav", std::process::id()));
+        let spec = hound::WavSpec { channels: 2, sample_rate: 8000, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut writer = hound::WavWriter::create(&file, spec).expect("failed to create a scratch wav");
+        for _ in 0..8000 {
+            writer.write_sample(0i16).expect("failed to write a sample");
+        }
+        writer.finalize().expect("failed to finish a scratch wav");
+
+        let params = SymphoniaProbe.stream_params(&file).expect("expected the wav to probe");
+        assert_eq!(params.codec, "pcm_s16le");
+        assert_eq!(params.format, AudioFormat { sample_rate: 8000, channels: 2 });
+        assert_eq!(SymphoniaProbe.duration(&file), Ok(0.5));
+
+        let missing = SymphoniaProbe.duration(Path::new("/nonexistent/stitcher.wav"));
+        assert!(matches!(missing, Err(ProbeError::Unreadable(_))));
+
+        let _ = std::fs::remove_file(file);
+    }
+}
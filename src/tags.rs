@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+
+use crate::probe::Probe;
+
+/// Tags that describe how a file was muxed rather than what's in it, so they're never carried over.
+const TECHNICAL_TAGS: &[&str] = &["encoder", "major_brand", "minor_version", "compatible_brands", "creation_time"];
+
+/// Which inputs' tags `--copy-metadata` carries into the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CopyMetadata {
+    /// Every tag of the first input.
+    First,
+    /// Every tag all the inputs share: one that's missing from any of them, or different in two of them (like each
+    /// track's title), is left out.
+    Merge,
+}
+
+/// The tags to carry from `files` into the output, as `(key, value)` pairs. an input whose tags can't be read
+/// is passed over, with a warning, or is an error when `strict` is set.
+pub fn from_inputs(probe: &dyn Probe, files: &[PathBuf], copy: CopyMetadata, strict: bool) -> Result<Vec<(String, String)>, String> {
+    let files = match copy {
+        CopyMetadata::First => &files[..files.len().min(1)],
+        CopyMetadata::Merge => files,
+    };
+    let mut per_input = vec![];
+    for file in files {
+        match (probe.tags(file), strict) {
+            (Ok(tags), _) => per_input.push(tags),
+            (Err(e), true) => return Err(format!("couldn't read the tags of {} (--strict): {}", file.to_string_lossy(), e)),
+            (Err(e), false) => log::warn!("couldn't read the tags of {}: {}", file.to_string_lossy(), e),
+        }
+    }
+    Ok(merge(&per_input))
+}
+
+/// The tags every input in `per_input` has, with the same value, in the first input's order. keys are compared
+/// without case, and technical ones are dropped.
+pub fn merge(per_input: &[Vec<(String, String)>]) -> Vec<(String, String)> {
+    let per_input = per_input
+        .iter()
+        .map(|tags| {
+            tags.iter()
+                .map(|(key, value)| (key.to_lowercase(), value))
+                .filter(|(key, _)| !TECHNICAL_TAGS.contains(&key.as_str()))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let Some((first, rest)) = per_input.split_first() else {
+        return vec![];
+    };
+
+    let mut merged: Vec<(String, String)> = vec![];
+    for (key, value) in first {
+        let shared = rest
+            .iter()
+            .all(|tags| tags.iter().find(|(other, _)| other == key).is_some_and(|(_, other)| other == value));
+        if shared && !merged.iter().any(|(known, _)| known == key) {
+            merged.push((key.clone(), value.to_string()));
+        }
+    }
+    merged
+}
+
+/// `given`, then each of `copied` whose key isn't among them, so tags set outright win over copied ones.
+pub fn under(given: &[(String, String)], copied: Vec<(String, String)>) -> Vec<(String, String)> {
+    let is_given = |key: &str| given.iter().any(|(known, _)| known.eq_ignore_ascii_case(key));
+    let copied = copied.into_iter().filter(|(key, _)| !is_given(key));
+    given.iter().cloned().chain(copied).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    pub fn test_merge_keeps_what_the_inputs_agree_on() {
+        let per_input = [
+            tags(&[("ARTIST", "Band"), ("title", "One"), ("album", "Live"), ("encoder", "Lavf60")]),
+            tags(&[("album", "Live"), ("artist", "Band"), ("title", "Two"), ("genre", "Rock")]),
+            tags(&[("title", "Three"), ("album", "Live"), ("artist", "Band"), ("date", "2024")]),
+        ];
+        assert_eq!(merge(&per_input), tags(&[("artist", "Band"), ("album", "Live")]));
+        assert!(merge(&[]).is_empty());
+        assert_eq!(merge(&per_input[..1]), tags(&[("artist", "Band"), ("title", "One"), ("album", "Live")]));
+
+        let given = tags(&[("title", "Live at the Hall")]);
+        assert_eq!(
+            under(&given, merge(&per_input[..1])),
+            tags(&[("title", "Live at the Hall"), ("artist", "Band"), ("album", "Live")])
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+
+use crate::{discovery, error::StitcherError};
+
+/// How often the stop flag is checked while waiting between polls.
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone)]
+pub struct WatchConfig {
+    /// How long to wait between looks at the watched paths.
+    pub poll_interval: Duration,
+    /// Paths whose changes don't count, from the start. what `on_change` writes is added to these as it goes.
+    pub ignore: Vec<PathBuf>,
+    /// How to walk a watched directory: the same way as finding the inputs, so a change to any file that
+    /// could be an input counts, and nothing else does.
+    pub walk: discovery::WalkOptions,
+}
+
+/// One look at the watched paths: every file with its size and modification time.
+type Snapshot = Vec<(PathBuf, u64, Option<SystemTime>)>;
+
+/// Watch `paths`, calling `on_change` once straight away and again whenever any of them change. a
+/// directory changes when its contents do, and a file when it's rewritten or goes away.
+///
+/// A change only counts once everything has looked the same for a whole poll, so a recorder that's
+/// still writing a file doesn't trigger a stitch per chunk. set `stop` to end the watch: the loop
+/// notices within `STOP_CHECK_INTERVAL`, or as soon as an in-flight `on_change` returns.
+/// `on_change` returns the files it wrote (the output, and any reports), which don't count as changes from
+/// then on, so an output written into a watched directory doesn't trigger another stitch. errors from
+/// `on_change` are reported and the watch carries on, and so are failed looks at the paths once the watch has
+/// started (a file removed while its directory is being listed, a share that drops out for a moment), which are
+/// tried again on the next poll.
+///
+/// The paths are polled rather than watched with OS file notifications (`notify`), so a watch behaves the same on
+/// network shares and mounts, where those notifications don't arrive, at the cost of noticing a change up to a
+/// `poll_interval` late.
+pub fn watch<F>(paths: &[PathBuf], config: &WatchConfig, stop: &AtomicBool, on_change: F) -> Result<(), StitcherError>
+where
+    F: FnMut() -> Result<Vec<PathBuf>, StitcherError>,
+{
+    watch_in(&discovery::RealFs, paths, config, stop, on_change)
+}
+
+/// `watch`, listing directories with `fs`.
+fn watch_in<F>(
+    fs: &dyn discovery::DirReader,
+    paths: &[PathBuf],
+    config: &WatchConfig,
+    stop: &AtomicBool,
+    mut on_change: F,
+) -> Result<(), StitcherError>
+where
+    F: FnMut() -> Result<Vec<PathBuf>, StitcherError>,
+{
+    let mut ignore = vec![];
+    add_ignored(&mut ignore, &config.ignore);
+
+    let mut stitched = snapshot_all(fs, paths, &ignore, &config.walk)?;
+    let written = report(on_change());
+    add_ignored(&mut ignore, &written);
+    stitched.retain(|(path, ..)| !is_ignored(&ignore, path));
+
+    let mut previous = stitched.clone();
+    while wait_unless_stopped(config.poll_interval, stop) {
+        let current = match snapshot_all(fs, paths, &ignore, &config.walk) {
+            Ok(current) => current,
+            Err(e) => {
+                log::warn!("{}, trying again", e);
+                continue;
+            }
+        };
+        let settled = current == previous;
+        if settled && current != stitched {
+            let written = report(on_change());
+            add_ignored(&mut ignore, &written);
+            stitched = current;
+            stitched.retain(|(path, ..)| !is_ignored(&ignore, path));
+            previous = stitched.clone();
+        } else {
+            previous = current;
+        }
+    }
+
+    Ok(())
+}
+
+/// What `on_change` wrote, or nothing if it failed, which is reported.
+fn report(result: Result<Vec<PathBuf>, StitcherError>) -> Vec<PathBuf> {
+    result.unwrap_or_else(|e| {
+        log::warn!("stitch failed, still watching: {}", e);
+        vec![]
+    })
+}
+
+/// Add `paths` to `ignore`, as they'd be found by a snapshot.
+fn add_ignored(ignore: &mut Vec<PathBuf>, paths: &[PathBuf]) {
+    for path in paths {
+        let path = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if !ignore.contains(&path) {
+            ignore.push(path);
+        }
+    }
+}
+
+fn is_ignored(ignore: &[PathBuf], path: &Path) -> bool {
+    ignore.contains(&path.canonicalize().unwrap_or_else(|_| path.to_path_buf()))
+}
+
+fn snapshot_all(
+    fs: &dyn discovery::DirReader,
+    paths: &[PathBuf],
+    ignore: &[PathBuf],
+    walk: &discovery::WalkOptions,
+) -> Result<Snapshot, StitcherError> {
+    let mut all = vec![];
+    for path in paths {
+        match path.is_dir() {
+            true => all.extend(snapshot(fs, path, ignore, walk)?),
+            false => all.push(file_state(path.clone())),
+        }
+    }
+    Ok(all)
+}
+
+fn snapshot(
+    fs: &dyn discovery::DirReader,
+    dir: &Path,
+    ignore: &[PathBuf],
+    walk: &discovery::WalkOptions,
+) -> Result<Snapshot, StitcherError> {
+    let files = discovery::walk(fs, dir, walk)
+        .map_err(|e| StitcherError::io(format!("watching {}", dir.to_string_lossy()), e))?;
+
+    Ok(files
+        .into_iter()
+        .filter(|path| !is_ignored(ignore, path))
+        .map(file_state)
+        .collect())
+}
+
+/// `path` with its size and modification time, or zero and `None` if it's gone.
+fn file_state(path: PathBuf) -> (PathBuf, u64, Option<SystemTime>) {
+    let metadata = std::fs::metadata(&path).ok();
+    let len = metadata.as_ref().map(|x| x.len()).unwrap_or(0);
+    (path, len, metadata.and_then(|x| x.modified().ok()))
+}
+
+/// Sleep for `duration`, waking early if `stop` gets set. returns `false` once stopped.
+fn wait_unless_stopped(duration: Duration, stop: &AtomicBool) -> bool {
+    let mut waited = Duration::ZERO;
+    while waited < duration {
+        if stop.load(Ordering::SeqCst) {
+            return false;
+        }
+        let step = STOP_CHECK_INTERVAL.min(duration - waited);
+        std::thread::sleep(step);
+        waited += step;
+    }
+    !stop.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    #[test]
+    pub fn test_watch_stops_promptly() {
+        let dir = std::env::temp_dir().join(format!("stitcher_test_watch_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create a scratch directory");
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stitches = Arc::new(AtomicUsize::new(0));
+
+        let watcher = {
+            let dir = dir.clone();
+            let stop = stop.clone();
+            let stitches = stitches.clone();
+            std::thread::spawn(move || {
+                let config = WatchConfig {
+                    poll_interval: Duration::from_secs(60),
+                    ignore: vec![],
+                    walk: Default::default(),
+                };
+                watch(std::slice::from_ref(&dir), &config, &stop, || {
+                    stitches.fetch_add(1, Ordering::SeqCst);
+                    Ok(vec![])
+                })
+            })
+        };
+
+        std::thread::sleep(Duration::from_millis(100));
+        let stop_requested = std::time::Instant::now();
+        stop.store(true, Ordering::SeqCst);
+
+        let result = watcher.join().expect("the watcher thread panicked");
+        assert!(result.is_ok());
+        assert!(
+            stop_requested.elapsed() < Duration::from_secs(1),
+            "expected the watcher to return promptly once stopped, not wait out the poll interval"
+        );
+        assert_eq!(stitches.load(Ordering::SeqCst), 1, "expected one initial stitch");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_watch_waits_for_a_nested_file_to_settle() {
+        let dir = std::env::temp_dir().join(format!("stitcher_test_watch_settle_{}", std::process::id()));
+        let nested = dir.join("day1");
+        std::fs::create_dir_all(&nested).expect("failed to create a scratch directory");
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stitches = Arc::new(AtomicUsize::new(0));
+
+        let watcher = {
+            let dir = dir.clone();
+            let stop = stop.clone();
+            let stitches = stitches.clone();
+            std::thread::spawn(move || {
+                let config = WatchConfig {
+                    poll_interval: Duration::from_millis(100),
+                    ignore: vec![],
+                    walk: discovery::WalkOptions { recursive: true, ..Default::default() },
+                };
+                watch(std::slice::from_ref(&dir), &config, &stop, || {
+                    stitches.fetch_add(1, Ordering::SeqCst);
+                    Ok(vec![])
+                })
+            })
+        };
+        std::thread::sleep(Duration::from_millis(50));
+
+        // a recording that's still growing never looks the same two polls running
+        //
+        let recording = nested.join("take.wav");
+        for i in 0..30 {
+            std::fs::write(&recording, vec![0u8; i * 100]).expect("failed to write a scratch file");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(stitches.load(Ordering::SeqCst), 1, "expected no stitch while the file was still being written");
+
+        std::thread::sleep(Duration::from_millis(500));
+        stop.store(true, Ordering::SeqCst);
+        let result = watcher.join().expect("the watcher thread panicked");
+        assert!(result.is_ok());
+        assert_eq!(stitches.load(Ordering::SeqCst), 2, "expected one more stitch once the file settled");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    pub fn test_watch_ignores_what_each_stitch_writes() {
+        let dir = std::env::temp_dir().join(format!("stitcher_test_watch_output_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create a scratch directory");
+        std::fs::write(dir.join("take.wav"), b"RIFF").expect("failed to write a scratch file");
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stitches = Arc::new(AtomicUsize::new(0));
+
+        // each stitch writes a differently named output into the watched directory, like a templated name does
+        //
+        let watcher = {
+            let dir = dir.clone();
+            let stop = stop.clone();
+            let stitches = stitches.clone();
+            std::thread::spawn(move || {
+                let config = WatchConfig {
+                    poll_interval: Duration::from_millis(50),
+                    ignore: vec![],
+                    walk: Default::default(),
+                };
+                watch(std::slice::from_ref(&dir), &config, &stop, || {
+                    let n = stitches.fetch_add(1, Ordering::SeqCst);
+                    let output = dir.join(format!("stitched {}.wav", n));
+                    std::fs::write(&output, b"RIFF").expect("failed to write the output");
+                    Ok(vec![output])
+                })
+            })
+        };
+
+        std::thread::sleep(Duration::from_millis(400));
+        assert_eq!(stitches.load(Ordering::SeqCst), 1, "expected the output not to trigger another stitch");
+
+        std::fs::write(dir.join("take 2.wav"), b"RIFF").expect("failed to write a scratch file");
+        std::thread::sleep(Duration::from_millis(400));
+        stop.store(true, Ordering::SeqCst);
+        let result = watcher.join().expect("the watcher thread panicked");
+        assert!(result.is_ok());
+        assert_eq!(stitches.load(Ordering::SeqCst), 2, "expected one stitch for the new input, and none for its output");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    /// The real filesystem, but for listings `failing` (counting from 0), which fail like a share that dropped out.
+    struct FlakyFs {
+        listings: std::cell::Cell<usize>,
+        failing: std::ops::Range<usize>,
+    }
+
+    impl discovery::DirReader for FlakyFs {
+        fn read_dir(&self, dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+            let listing = self.listings.replace(self.listings.get() + 1);
+            match self.failing.contains(&listing) {
+                true => Err(std::io::Error::other("stale file handle")),
+                false => discovery::RealFs.read_dir(dir),
+            }
+        }
+
+        fn is_dir(&self, path: &Path) -> bool {
+            discovery::RealFs.is_dir(path)
+        }
+    }
+
+    #[test]
+    pub fn test_watch_outlasts_a_failed_look() {
+        let dir = std::env::temp_dir().join(format!("stitcher_test_watch_flaky_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create a scratch directory");
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stitches = Arc::new(AtomicUsize::new(0));
+
+        // the first look works, then the next few fail
+        //
+        let watcher = {
+            let dir = dir.clone();
+            let stop = stop.clone();
+            let stitches = stitches.clone();
+            std::thread::spawn(move || {
+                let fs = FlakyFs { listings: std::cell::Cell::new(0), failing: 1..4 };
+                let config = WatchConfig {
+                    poll_interval: Duration::from_millis(50),
+                    ignore: vec![],
+                    walk: Default::default(),
+                };
+                watch_in(&fs, std::slice::from_ref(&dir), &config, &stop, || {
+                    stitches.fetch_add(1, Ordering::SeqCst);
+                    Ok(vec![])
+                })
+            })
+        };
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(!watcher.is_finished(), "expected the watch to carry on past the failed looks");
+
+        std::fs::write(dir.join("take.wav"), b"RIFF").expect("failed to write a scratch file");
+        std::thread::sleep(Duration::from_millis(400));
+
+        stop.store(true, Ordering::SeqCst);
+        let result = watcher.join().expect("the watcher thread panicked");
+        assert!(result.is_ok());
+        assert_eq!(stitches.load(Ordering::SeqCst), 2, "expected a stitch for the new file once the looks worked again");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,148 @@
+use std::path::{Path, PathBuf};
+
+use crate::{error::StitcherError, interrupt, options::output_extension, options::StitchOptions};
+
+/// How many samples are copied between checks for Ctrl+C.
+const INTERRUPT_CHECK_SAMPLES: usize = 1 << 16;
+
+/// Whether `files` can be stitched into `options.output` without ffmpeg: they're all WAVs, so is the output,
+/// and the options only ask for the audio to be copied across as it is.
+pub fn can_join(files: &[PathBuf], options: &StitchOptions) -> bool {
+    let is_wav = |path: &Path| output_extension(path).is_some_and(|ext| ext.eq_ignore_ascii_case("wav"));
+    is_wav(&options.output) && files.iter().all(|file| is_wav(file)) && options.only_copies()
+}
+
+/// Join the WAVs in `files`, in order, into `output`, sample for sample. they all have to have the same
+/// channels, sample rate and sample format as the first. an existing `output` is only replaced if `overwrite`
+/// is set. returns the path of the joined file.
+pub fn join(files: &[PathBuf], output: &Path, overwrite: bool) -> Result<PathBuf, StitcherError> {
+    let Some(first) = files.first() else {
+        return Err(StitcherError::NoInputs(String::from("found no files!")));
+    };
+    if !overwrite && output.exists() {
+        return Err(StitcherError::OutputExists(output.to_path_buf()));
+    }
+
+    // check every header before writing anything, so a mismatch doesn't leave half an output behind
+    //
+    let spec = open(first)?.spec();
+    for file in &files[1..] {
+        let other = open(file)?.spec();
+        if other != spec {
+            return Err(StitcherError::Other(format!(
+                "{} is {}, but {} is {}: WAVs can only be joined without ffmpeg if they all match",
+                file.to_string_lossy(),
+                describe(&other),
+                first.to_string_lossy(),
+                describe(&spec)
+            )));
+        }
+    }
+
+    let result = write(files, output, spec);
+    if result.is_err() {
+        let _ = std::fs::remove_file(output);
+    }
+    result.map(|()| output.to_path_buf())
+}
+
+fn write(files: &[PathBuf], output: &Path, spec: hound::WavSpec) -> Result<(), StitcherError> {
+    let mut writer = hound::WavWriter::create(output, spec).map_err(|e| wav_error("creating", output, e))?;
+    for file in files {
+        log::debug!("appending {}", file.to_string_lossy());
+        let reader = open(file)?;
+        match (spec.sample_format, spec.bits_per_sample) {
+            (hound::SampleFormat::Float, _) => copy_samples::<f32>(reader, &mut writer, file)?,
+            (hound::SampleFormat::Int, 8) => copy_samples::<i8>(reader, &mut writer, file)?,
+            (hound::SampleFormat::Int, 16) => copy_samples::<i16>(reader, &mut writer, file)?,
+            (hound::SampleFormat::Int, _) => copy_samples::<i32>(reader, &mut writer, file)?,
+        }
+    }
+    writer.finalize().map_err(|e| wav_error("writing", output, e))
+}
+
+fn copy_samples<S: hound::Sample>(
+    reader: hound::WavReader<std::io::BufReader<std::fs::File>>,
+    writer: &mut hound::WavWriter<std::io::BufWriter<std::fs::File>>,
+    file: &Path,
+) -> Result<(), StitcherError> {
+    for (i, sample) in reader.into_samples::<S>().enumerate() {
+        // there's no child for Ctrl+C to kill, so the copy has to notice itself, and `join` removes what's written
+        //
+        if i % INTERRUPT_CHECK_SAMPLES == 0 && interrupt::requested() {
+            return Err(StitcherError::Interrupted);
+        }
+        let sample = sample.map_err(|e| wav_error("reading", file, e))?;
+        writer.write_sample(sample).map_err(|e| wav_error("writing", file, e))?;
+    }
+    Ok(())
+}
+
+/// The length of the WAV at `path`, in seconds, if it can be read.
+pub fn duration_secs(path: &Path) -> Option<f64> {
+    let reader = hound::WavReader::open(path).ok()?;
+    Some(reader.duration() as f64 / reader.spec().sample_rate as f64)
+}
+
+fn open(file: &Path) -> Result<hound::WavReader<std::io::BufReader<std::fs::File>>, StitcherError> {
+    hound::WavReader::open(file).map_err(|e| wav_error("opening", file, e))
+}
+
+fn describe(spec: &hound::WavSpec) -> String {
+    let format = match spec.sample_format {
+        hound::SampleFormat::Float => "float",
+        hound::SampleFormat::Int => "bit",
+    };
+    format!("{} Hz, {} channels, {}-{}", spec.sample_rate, spec.channels, spec.bits_per_sample, format)
+}
+
+fn wav_error(doing: &str, file: &Path, error: hound::Error) -> StitcherError {
+    match error {
+        hound::Error::IoError(e) => StitcherError::io(format!("{} {}", doing, file.to_string_lossy()), e),
+        other => StitcherError::Other(format!("failed {} {}: {}", doing, file.to_string_lossy(), other)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write_wav(path: &Path, spec: hound::WavSpec, samples: &[i16]) {
+        let mut writer = hound::WavWriter::create(path, spec).expect("failed to create a scratch wav");
+        for sample in samples {
+            writer.write_sample(*sample).expect("failed to write a sample");
+        }
+        writer.finalize().expect("failed to finish a scratch wav");
+    }
+
+    #[test]
+    pub fn test_wavs_join_without_ffmpeg() {
+        let dir = std::env::temp_dir().join(format!("stitcher_test_wav_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create a scratch directory");
+        let spec = hound::WavSpec { channels: 2, sample_rate: 8000, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        write_wav(&dir.join("a.wav"), spec, &[1, -1, 2, -2]);
+        write_wav(&dir.join("b.wav"), spec, &[3, -3]);
+        write_wav(&dir.join("mono.wav"), hound::WavSpec { channels: 1, ..spec }, &[4]);
+
+        let output = dir.join("out.wav");
+        let files = [dir.join("a.wav"), dir.join("b.wav")];
+        assert_eq!(join(&files, &output, false).ok(), Some(output.clone()));
+
+        let joined = hound::WavReader::open(&output).expect("expected the output to be a wav");
+        assert_eq!(joined.spec(), spec);
+        let samples = joined.into_samples::<i16>().map(|x| x.expect("a sample")).collect::<Vec<_>>();
+        assert_eq!(samples, vec![1, -1, 2, -2, 3, -3]);
+        assert_eq!(duration_secs(&output), Some(3.0 / 8000.0));
+
+        assert!(matches!(join(&files, &output, false), Err(StitcherError::OutputExists(_))));
+        let mismatched = join(&[dir.join("a.wav"), dir.join("mono.wav")], &output, true).expect_err("expected a mismatch");
+        assert!(mismatched.to_string().contains("1 channels"), "got: {}", mismatched);
+
+        let options = StitchOptions { output: output.clone(), ..Default::default() };
+        assert!(can_join(&files, &options));
+        assert!(!can_join(&files, &StitchOptions { reencode: true, ..options.clone() }));
+        assert!(!can_join(&[dir.join("a.mp3")], &options));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,96 @@
+use std::ffi::OsString;
+use std::path::Path;
+
+use crate::{error::StitcherError, ffmpeg};
+
+/// Width and height of a waveform image, in pixels. written (and parsed) as `WIDTHxHEIGHT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaveformSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for WaveformSize {
+    fn default() -> Self {
+        WaveformSize { width: 1200, height: 240 }
+    }
+}
+
+impl std::fmt::Display for WaveformSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+impl std::str::FromStr for WaveformSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected a size like 1200x240, got {:?}", s);
+        let (width, height) = s.split_once('x').ok_or_else(invalid)?;
+        let width = width.parse::<u32>().map_err(|_| invalid())?;
+        let height = height.parse::<u32>().map_err(|_| invalid())?;
+        if width == 0 || height == 0 {
+            return Err(invalid());
+        }
+        Ok(WaveformSize { width, height })
+    }
+}
+
+/// ffmpeg arguments for drawing the waveform of `audio` into the PNG `image`.
+pub fn args(audio: &Path, image: &Path, size: WaveformSize) -> Vec<OsString> {
+    let mut args = vec![OsString::from("-y"), OsString::from("-i")];
+    args.push(audio.as_os_str().to_owned());
+    args.push(OsString::from("-filter_complex"));
+    args.push(OsString::from(format!("showwavespic=s={}", size)));
+    args.push(OsString::from("-frames:v"));
+    args.push(OsString::from("1"));
+    args.push(image.as_os_str().to_owned());
+    args
+}
+
+/// Render the waveform of `audio` to `image`, as its own ffmpeg run.
+pub fn render(
+    runner: &dyn ffmpeg::Runner,
+    audio: &Path,
+    image: &Path,
+    size: WaveformSize,
+) -> Result<(), StitcherError> {
+    let run = runner.run(&args(audio, image, size))?;
+    match run.status.success() {
+        true => Ok(()),
+        false => Err(StitcherError::ffmpeg_failed(run.status, &run.stderr)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Records every command instead of running it.
+    #[derive(Default)]
+    struct RecordingRunner {
+        commands: RefCell<Vec<Vec<OsString>>>,
+    }
+
+    impl ffmpeg::Runner for RecordingRunner {
+        fn run(&self, args: &[OsString]) -> Result<ffmpeg::FfmpegRun, StitcherError> {
+            self.commands.borrow_mut().push(args.to_vec());
+            Ok(ffmpeg::FfmpegRun { status: Default::default(), stderr: String::new() })
+        }
+    }
+
+    #[test]
+    pub fn test_render_issues_showwavespic_over_the_output() {
+        let runner = RecordingRunner::default();
+        let size = "800x120".parse::<WaveformSize>().expect("valid size");
+        render(&runner, Path::new("STITCH_OUTPUT.wav"), Path::new("wave.png"), size).expect("expected the render to succeed");
+
+        let expected = ["-y", "-i", "STITCH_OUTPUT.wav", "-filter_complex", "showwavespic=s=800x120", "-frames:v", "1", "wave.png"];
+        assert_eq!(*runner.commands.borrow(), vec![expected.map(OsString::from).to_vec()]);
+
+        assert!("0x120".parse::<WaveformSize>().is_err());
+        assert!("800".parse::<WaveformSize>().is_err());
+    }
+}